@@ -0,0 +1,54 @@
+/// Options parsed from the command line.
+pub struct Config {
+    pub headless: bool,
+    pub output_path: String,
+    pub power_preference: wgpu::PowerPreference,
+    /// Request WebGL2 level limits, to check what still runs on mobile and web backends.
+    pub compat: bool,
+    /// TTF or OTF file to draw the console and other monospace text with.
+    pub console_font: Option<std::path::PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            headless: false,
+            output_path: "headless.png".to_string(),
+            power_preference: wgpu::PowerPreference::default(),
+            compat: false,
+            console_font: None,
+        }
+    }
+}
+
+impl Config {
+    /// Parses `--headless`, `--output <path>`, `--compat`, `--console-font <path>` and
+    /// `--power-preference <default|low-power|high-performance>`.
+    /// Unknown arguments and values are reported and ignored.
+    pub fn from_args() -> Self {
+        let mut config = Self::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => config.headless = true,
+                "--compat" => config.compat = true,
+                "--output" => match args.next() {
+                    Some(path) => config.output_path = path,
+                    None => log::warn!("Missing value for --output"),
+                },
+                "--console-font" => match args.next() {
+                    Some(path) => config.console_font = Some(path.into()),
+                    None => log::warn!("Missing value for --console-font"),
+                },
+                "--power-preference" => match args.next().as_deref() {
+                    Some("default") => config.power_preference = wgpu::PowerPreference::default(),
+                    Some("low-power") => config.power_preference = wgpu::PowerPreference::LowPower,
+                    Some("high-performance") => config.power_preference = wgpu::PowerPreference::HighPerformance,
+                    value => log::warn!("Invalid value for --power-preference: {:?}", value),
+                },
+                _ => log::warn!("Unknown argument: {}", arg),
+            }
+        }
+        config
+    }
+}
@@ -0,0 +1,341 @@
+use cgmath::InnerSpace;
+use wgpu::util::DeviceExt;
+
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::mesh::Vertex;
+use crate::pipeline::PipelineBuilder;
+use crate::texture;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BillboardVertex {
+    position: [f32; 3],
+    tex_coords: [f32; 2],
+    color: [f32; 4],
+}
+
+impl Vertex for BillboardVertex {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<BillboardVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+/// A text label to draw, as (anchor, text) with the anchor in world space.
+pub type Label = (cgmath::Point3<f32>, String);
+
+/// Draws text labels that face the camera and keep their size on screen, centered above their anchor.
+/// Glyphs come from the egui font atlas, so labels use the GUI's body font.
+/// Labels fade out behind geometry by comparing against the scene depth instead of depth testing.
+pub struct BillboardPass {
+    pub color: Color,
+    quads: Vec<Label>,
+    vertex_buffer: Option<wgpu::Buffer>,
+    num_vertices: u32,
+    // Version and size of the egui font atlas copied into the bind group,
+    // replaced whenever egui rasterizes new glyphs.
+    font_version: Option<u64>,
+    font_size: [f32; 2],
+    layout: wgpu::BindGroupLayout,
+    bind_group: Option<wgpu::BindGroup>,
+    depth_layout: wgpu::BindGroupLayout,
+    depth_bind_group: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl BillboardPass {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera_layout: &wgpu::BindGroupLayout,
+        depth_texture: &texture::Texture,
+    ) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Billboard Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let depth_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Billboard Depth Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let depth_bind_group = Self::create_depth_bind_group(device, &depth_layout, depth_texture);
+
+        let render_pipeline = {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Billboard Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_layout,
+                    &layout,
+                    &depth_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+            let shader = wgpu::ShaderModuleDescriptor {
+                label: Some("Billboard Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("billboard.wgsl").into()),
+            };
+            // No depth attachment, the scene depth is sampled in the shader instead.
+            PipelineBuilder::new("Billboard Render Pipeline")
+                .color_format(config.format)
+                .vertex_layouts(&[BillboardVertex::desc()])
+                .cull_mode(None)
+                .blend(wgpu::BlendState::ALPHA_BLENDING)
+                .shader(shader)
+                .build(device, &pipeline_layout)
+        };
+
+        Self {
+            color: Color::WHITE,
+            quads: Vec::new(),
+            vertex_buffer: None,
+            num_vertices: 0,
+            font_version: None,
+            font_size: [1.0, 1.0],
+            layout,
+            bind_group: None,
+            depth_layout,
+            depth_bind_group,
+            render_pipeline,
+        }
+    }
+
+    fn create_depth_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        depth_texture: &texture::Texture,
+    ) -> wgpu::BindGroup {
+        // Bindings can only sample one aspect of a depth stencil texture.
+        let depth_view = depth_texture.texture.create_view(&wgpu::TextureViewDescriptor {
+            aspect: wgpu::TextureAspect::DepthOnly,
+            ..Default::default()
+        });
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Billboard Depth Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&depth_view),
+                },
+            ],
+        })
+    }
+
+    /// Call after the scene depth texture was recreated.
+    pub fn resize(&mut self, device: &wgpu::Device, depth_texture: &texture::Texture) {
+        self.depth_bind_group = Self::create_depth_bind_group(device, &self.depth_layout, depth_texture);
+    }
+
+    /// Replaces the labels and rebuilds their quads for `camera`, so call it every frame the camera moves.
+    /// `screen_height` is the height of the target in pixels.
+    pub fn set_labels(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        ctx: &egui::CtxRef,
+        camera: &Camera,
+        screen_height: u32,
+        labels: Vec<Label>,
+    ) {
+        self.quads = labels;
+        if self.quads.is_empty() {
+            self.vertex_buffer = None;
+            self.num_vertices = 0;
+            return;
+        }
+
+        // Lay out every label before copying the atlas, laying out can add glyphs to it.
+        let galleys = self.quads.iter()
+            .map(|(_, text)| ctx.fonts().layout_no_wrap(text.clone(), egui::TextStyle::Body, egui::Color32::WHITE))
+            .collect::<Vec<_>>();
+        self.update_font_texture(device, queue, ctx);
+
+        let forward = camera.eye.forward();
+        let right = camera.eye.right();
+        let up = camera.eye.up();
+        let color = self.color.0;
+        let mut vertices = Vec::new();
+        for ((anchor, _), galley) in self.quads.iter().zip(&galleys) {
+            let anchor = *anchor;
+            let depth = (anchor - camera.eye.position).dot(forward);
+            if depth <= camera.projection.z_near() {
+                continue;
+            }
+            // Galleys are laid out in points with y down.
+            let scale = camera.projection.world_per_pixel(depth, screen_height) * ctx.pixels_per_point();
+            let origin = (-galley.rect.width() * 0.5, -galley.rect.height());
+            let corner = |x: f32, y: f32, u: u16, v: u16| BillboardVertex {
+                position: (anchor + right * (origin.0 + x) * scale - up * (origin.1 + y) * scale).into(),
+                tex_coords: [u as f32 / self.font_size[0], v as f32 / self.font_size[1]],
+                color,
+            };
+            for glyph in galley.rows.iter().flat_map(|row| &row.glyphs) {
+                let uv = &glyph.uv_rect;
+                if uv.min == uv.max {
+                    // Whitespace has nothing to draw.
+                    continue;
+                }
+                let (x0, y0) = (glyph.pos.x + uv.offset.x, glyph.pos.y + uv.offset.y);
+                let (x1, y1) = (x0 + uv.size.x, y0 + uv.size.y);
+                let top_left = corner(x0, y0, uv.min[0], uv.min[1]);
+                let top_right = corner(x1, y0, uv.max[0], uv.min[1]);
+                let bottom_left = corner(x0, y1, uv.min[0], uv.max[1]);
+                let bottom_right = corner(x1, y1, uv.max[0], uv.max[1]);
+                vertices.extend_from_slice(&[
+                    top_left, bottom_left, bottom_right,
+                    top_left, bottom_right, top_right,
+                ]);
+            }
+        }
+
+        self.num_vertices = vertices.len() as u32;
+        self.vertex_buffer = if vertices.is_empty() {
+            None
+        } else {
+            Some(device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Billboard Vertex Buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }))
+        };
+    }
+
+    fn update_font_texture(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, ctx: &egui::CtxRef) {
+        let font_image = ctx.font_image();
+        if self.font_version == Some(font_image.version) {
+            return;
+        }
+
+        let size = wgpu::Extent3d {
+            width: font_image.width as u32,
+            height: font_image.height as u32,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Billboard Font Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+        queue.write_texture(
+            texture.as_image_copy(),
+            &font_image.pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(size.width),
+                rows_per_image: std::num::NonZeroU32::new(size.height),
+            },
+            size,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Billboard Font Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        self.bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Billboard Bind Group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        }));
+        self.font_version = Some(font_image.version);
+        self.font_size = [size.width as f32, size.height as f32];
+    }
+
+    /// Draws over `view`, which must be the target the depth texture given at creation or resize belongs to.
+    pub fn render(
+        &self,
+        view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        let (vertex_buffer, bind_group) = match (&self.vertex_buffer, &self.bind_group) {
+            (Some(vertex_buffer), Some(bind_group)) => (vertex_buffer, bind_group),
+            _ => return,
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Billboard Render Pass"),
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }
+            ],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, bind_group, &[]);
+        render_pass.set_bind_group(2, &self.depth_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.draw(0..self.num_vertices, 0..1);
+    }
+}
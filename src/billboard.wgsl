@@ -0,0 +1,53 @@
+// Vertex shader
+
+struct Camera {
+    view_pos: vec4<f32>;
+    view_proj: mat4x4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+// Single channel copy of the egui font atlas, holding glyph coverage.
+[[group(1), binding(0)]]
+var t_font: texture_2d<f32>;
+[[group(1), binding(1)]]
+var s_font: sampler;
+// Depth of the scene the labels are drawn over.
+[[group(2), binding(0)]]
+var t_depth: texture_depth_2d;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+    [[location(2)]] color: vec4<f32>;
+};
+
+// Depth range over which a label fades out as it passes behind geometry.
+let DEPTH_FADE: f32 = 0.02;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
+    out.tex_coords = model.tex_coords;
+    out.color = model.color;
+    return out;
+}
+
+// Fragment shader
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let coverage = textureSample(t_font, s_font, in.tex_coords).r;
+    let scene_depth = textureLoad(t_depth, vec2<i32>(in.clip_position.xy), 0);
+    let visibility = smoothStep(0.0, DEPTH_FADE, scene_depth - in.clip_position.z);
+    return vec4<f32>(in.color.rgb, in.color.a * coverage * visibility);
+}
@@ -4,9 +4,13 @@ use winit::dpi::PhysicalPosition;
 use std::time::Duration;
 use std::f32::consts::FRAC_PI_2;
 use wgpu::util::DeviceExt;
+use serde::{Deserialize, Serialize};
+
+use crate::spline::CatmullRomSpline;
+use crate::util::BufferUploader;
 
 #[rustfmt::skip]
-const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
+pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
     0.0, 0.0, 0.5, 0.0,
@@ -20,6 +24,8 @@ pub struct Eye {
     pub position: Point3<f32>,
     yaw: Rad<f32>,
     pitch: Rad<f32>,
+    // Looks straight down -Y with -Z up the screen, ignoring yaw and pitch.
+    top_down: bool,
 }
 
 impl Eye {
@@ -36,20 +42,75 @@ impl Eye {
             position: position.into(),
             yaw: yaw.into(),
             pitch: pitch.into(),
+            top_down: false,
+        }
+    }
+
+    /// Points the eye straight down, which yaw and pitch can't express.
+    pub fn set_top_down(&mut self, top_down: bool) {
+        self.top_down = top_down;
+    }
+
+    pub fn yaw(&self) -> Rad<f32> {
+        self.yaw
+    }
+
+    pub fn pitch(&self) -> Rad<f32> {
+        self.pitch
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        if self.top_down {
+            return -Vector3::unit_y();
         }
+        Vector3::new(
+            self.yaw.0.cos(),
+            self.pitch.0.sin(),
+            self.yaw.0.sin(),
+        ).normalize()
+    }
+
+    /// Orientation as the rotation from +X (yaw and pitch of zero) to the view direction.
+    pub fn rotation(&self) -> Quaternion<f32> {
+        Quaternion::from_arc(Vector3::unit_x(), self.forward(), None)
+    }
+
+    /// Points the eye along `rotation` applied to +X. Roll is discarded.
+    pub fn set_rotation(&mut self, rotation: Quaternion<f32>) {
+        let forward = rotation.rotate_vector(Vector3::unit_x());
+        let horizontal = (forward.x * forward.x + forward.z * forward.z).sqrt();
+        self.yaw = Rad(forward.z.atan2(forward.x));
+        // Inverse of `forward`, which scales the vertical component by the horizontal one.
+        let sin_pitch = if horizontal > 0.0 { forward.y / horizontal } else { forward.y.signum() };
+        self.pitch = Rad(sin_pitch.clamp(-1.0, 1.0).asin().clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2));
     }
 
     fn calc_matrix(&self) -> Matrix4<f32> {
         Matrix4::look_to_rh(
             self.position,
-            Vector3::new(
-                self.yaw.0.cos(),
-                self.pitch.0.sin(),
-                self.yaw.0.sin(),
-            ).normalize(),
-            Vector3::unit_y(),
+            self.forward(),
+            self.reference_up(),
         )
     }
+
+    // Reference up for the view matrix, it can't be parallel to the forward direction.
+    fn reference_up(&self) -> Vector3<f32> {
+        if self.top_down {
+            -Vector3::unit_z()
+        } else {
+            Vector3::unit_y()
+        }
+    }
+
+    /// World space direction pointing right on screen.
+    pub fn right(&self) -> Vector3<f32> {
+        self.forward().cross(self.reference_up()).normalize()
+    }
+
+    /// World space direction pointing up on screen, perpendicular to `forward`.
+    pub fn up(&self) -> Vector3<f32> {
+        self.right().cross(self.forward())
+    }
 }
 
 pub struct Projection {
@@ -57,6 +118,8 @@ pub struct Projection {
     fov_y: Rad<f32>,
     z_near: f32,
     z_far: f32,
+    // Height of the view volume when orthographic, fov_y is ignored then.
+    ortho_height: Option<f32>,
 }
 
 impl Projection {
@@ -72,6 +135,7 @@ impl Projection {
             fov_y: fov_y.into(),
             z_near,
             z_far,
+            ortho_height: None,
         }
     }
 
@@ -79,16 +143,254 @@ impl Projection {
         self.aspect = width as f32 / height as f32;
     }
 
+    pub fn fov_y(&self) -> Rad<f32> {
+        self.fov_y
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.aspect
+    }
+
+    pub fn z_near(&self) -> f32 {
+        self.z_near
+    }
+
+    pub fn z_far(&self) -> f32 {
+        self.z_far
+    }
+
+    pub fn set_fov<F: Into<Rad<f32>>>(&mut self, fov_y: F) {
+        self.fov_y = fov_y.into();
+    }
+
+    pub fn set_near_far(&mut self, z_near: f32, z_far: f32) {
+        self.z_near = z_near;
+        self.z_far = z_far;
+    }
+
+    /// World units covered by one pixel of a `screen_height` pixel tall view, `depth` units in front of the eye.
+    pub fn world_per_pixel(&self, depth: f32, screen_height: u32) -> f32 {
+        let view_height = match self.ortho_height {
+            Some(height) => height,
+            None => 2.0 * depth * (self.fov_y / 2.0).tan(),
+        };
+        view_height / screen_height as f32
+    }
+
+    /// Switches to an orthographic projection `height` world units tall, or back to perspective with None.
+    pub fn set_orthographic(&mut self, height: Option<f32>) {
+        self.ortho_height = height;
+    }
+
     fn calc_matrix(&self) -> Matrix4<f32> {
-        OPENGL_TO_WGPU_MATRIX * perspective(self.fov_y, self.aspect, self.z_near, self.z_far)
+        match self.ortho_height {
+            Some(height) => {
+                let (half_width, half_height) = (height * self.aspect * 0.5, height * 0.5);
+                OPENGL_TO_WGPU_MATRIX * ortho(-half_width, half_width, -half_height, half_height, self.z_near, self.z_far)
+            },
+            None => OPENGL_TO_WGPU_MATRIX * perspective(self.fov_y, self.aspect, self.z_near, self.z_far),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Frustum {
+    // Plane equations (a, b, c, d) with normals pointing inwards.
+    planes: [Vector4<f32>; 6],
+    // Maps clip space back to world space, to find the corners.
+    inv_view_proj: Matrix4<f32>,
+}
+
+impl Frustum {
+    pub fn from_matrix(view_proj: Matrix4<f32>) -> Self {
+        // Gribb/Hartmann plane extraction, using the 0..1 depth range of wgpu.
+        let row0 = view_proj.row(0);
+        let row1 = view_proj.row(1);
+        let row2 = view_proj.row(2);
+        let row3 = view_proj.row(3);
+
+        let mut planes = [
+            row3 + row0, // Left
+            row3 - row0, // Right
+            row3 + row1, // Bottom
+            row3 - row1, // Top
+            row2,        // Near
+            row3 - row2, // Far
+        ];
+        for plane in planes.iter_mut() {
+            *plane /= plane.truncate().magnitude();
+        }
+
+        Self {
+            planes,
+            inv_view_proj: view_proj.invert().unwrap_or_else(Matrix4::identity),
+        }
+    }
+
+    pub fn planes(&self) -> [[f32; 4]; 6] {
+        self.planes.map(|plane| plane.into())
+    }
+
+    pub fn contains_sphere(&self, center: Vector3<f32>, radius: f32) -> bool {
+        self.planes.iter().all(|plane| plane.truncate().dot(center) + plane.w >= -radius)
+    }
+
+    /// Conservative, a box just outside a corner of the frustum can still pass.
+    pub fn intersects_aabb(&self, min: Vector3<f32>, max: Vector3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane normal.
+            let corner = Vector3::new(
+                if plane.x >= 0.0 { max.x } else { min.x },
+                if plane.y >= 0.0 { max.y } else { min.y },
+                if plane.z >= 0.0 { max.z } else { min.z },
+            );
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+
+    /// World space corners. Corner i is on the right, top and far sides for bits 1, 2 and 4.
+    pub fn corners(&self) -> [Vector3<f32>; 8] {
+        let mut corners = [Vector3::zero(); 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let ndc = Vector4::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { 0.0 } else { 1.0 },
+                1.0,
+            );
+            let world = self.inv_view_proj * ndc;
+            *corner = world.truncate() / world.w;
+        }
+        corners
+    }
+}
+
+/// Camera keyframes as (time in seconds, position, rotation), sorted by time.
+#[derive(Debug, Default)]
+pub struct CameraPath {
+    keyframes: Vec<(f32, Point3<f32>, Quaternion<f32>)>,
+    // Spline through the keyframe positions, one segment per pair of keyframes.
+    spline: CatmullRomSpline,
+}
+
+impl CameraPath {
+    pub fn add_keyframe(&mut self, time: f32, position: Point3<f32>, rotation: Quaternion<f32>) {
+        let index = self.keyframes.partition_point(|(t, _, _)| *t <= time);
+        self.keyframes.insert(index, (time, position, rotation));
+        self.spline = CatmullRomSpline::new(self.keyframes.iter().map(|(_, p, _)| *p).collect());
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+        self.spline = CatmullRomSpline::default();
+    }
+
+    pub fn len(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
     }
+
+    /// Time of the last keyframe.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |(t, _, _)| *t)
+    }
+
+    /// Evaluates the path at `t`, clamped to the keyframe range. Positions use a
+    /// Catmull-Rom spline through the keyframes and rotations are slerped.
+    pub fn sample(&self, t: f32) -> Option<(Point3<f32>, Quaternion<f32>)> {
+        let first = self.keyframes.first()?;
+        let last = self.keyframes.last()?;
+        if t <= first.0 {
+            return Some((first.1, first.2));
+        }
+        if t >= last.0 {
+            return Some((last.1, last.2));
+        }
+
+        // Index of the keyframe starting the segment containing t.
+        let i = self.keyframes.partition_point(|(time, _, _)| *time <= t) - 1;
+        let (t1, _, r1) = self.keyframes[i];
+        let (t2, _, r2) = self.keyframes[i + 1];
+        let u = (t - t1) / (t2 - t1);
+
+        Some((self.spline.evaluate_segment(i, u), r1.slerp(r2, u)))
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
 }
 
+impl Ray {
+    /// Unprojects a point in normalized device coordinates (y up) into a world space ray
+    /// starting on the near plane.
+    pub fn from_screen(mouse_ndc: Vector2<f32>, inv_view_proj: &Matrix4<f32>) -> Ray {
+        let near = inv_view_proj * Vector4::new(mouse_ndc.x, mouse_ndc.y, 0.0, 1.0);
+        let far = inv_view_proj * Vector4::new(mouse_ndc.x, mouse_ndc.y, 1.0, 1.0);
+        let near = Point3::from_homogeneous(near);
+        let far = Point3::from_homogeneous(far);
+        Ray {
+            origin: near,
+            direction: (far - near).normalize(),
+        }
+    }
+
+    /// Returns the distance along the ray to the first intersection with the sphere, if any.
+    pub fn intersect_sphere(&self, center: Point3<f32>, radius: f32) -> Option<f32> {
+        let to_origin = self.origin - center;
+        let b = to_origin.dot(self.direction);
+        let c = to_origin.magnitude2() - radius * radius;
+        let discriminant = b * b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        [-b - root, -b + root].into_iter().find(|&t| t >= 0.0)
+    }
+}
+
+/// Rotation by `roll` around Z, then `pitch` around X, then `yaw` around Y, all in radians.
+pub fn euler_to_quaternion(yaw: f32, pitch: f32, roll: f32) -> Quaternion<f32> {
+    Quaternion::from_angle_y(Rad(yaw)) * Quaternion::from_angle_x(Rad(pitch)) * Quaternion::from_angle_z(Rad(roll))
+}
+
+/// Inverse of `euler_to_quaternion`, as (yaw, pitch, roll) in radians with pitch in [-pi/2, pi/2].
+/// Looking straight up or down, yaw and roll turn around the same axis, so roll is reported as 0.
+pub fn quaternion_to_euler(q: Quaternion<f32>) -> (f32, f32, f32) {
+    let q = q.normalize();
+    let (w, x, y, z) = (q.s, q.v.x, q.v.y, q.v.z);
+    // The rotation matrix entries that are needed, m_rc for row r and column c.
+    let m00 = 1.0 - 2.0 * (y * y + z * z);
+    let m02 = 2.0 * (x * z + w * y);
+    let m10 = 2.0 * (x * y + w * z);
+    let m11 = 1.0 - 2.0 * (x * x + z * z);
+    let m12 = 2.0 * (y * z - w * x);
+    let m20 = 2.0 * (x * z - w * y);
+    let m22 = 1.0 - 2.0 * (x * x + y * y);
+
+    if m12.abs() > GIMBAL_LOCK_THRESHOLD {
+        let pitch = -FRAC_PI_2 * m12.signum();
+        return ((-m20).atan2(m00), pitch, 0.0);
+    }
+    (m02.atan2(m22), (-m12).asin(), m10.atan2(m11))
+}
+
+// How close the sine of the pitch gets to 1 before yaw and roll can't be told apart.
+const GIMBAL_LOCK_THRESHOLD: f32 = 0.9999;
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 struct Uniform {
     view_pos: [f32; 4],
     view_proj: [[f32; 4]; 4],
+    // World space axes of the view, for billboards. Shaders that don't need them can leave them out.
+    right: [f32; 4],
+    up: [f32; 4],
 }
 
 impl Uniform {
@@ -96,12 +398,16 @@ impl Uniform {
         Self {
             view_pos: [0.0; 4],
             view_proj: cgmath::Matrix4::identity().into(),
+            right: [1.0, 0.0, 0.0, 0.0],
+            up: [0.0, 1.0, 0.0, 0.0],
         }
     }
 
     fn update_view_proj(&mut self, eye: &Eye, projection: &Projection) {
         self.view_pos = eye.position.to_homogeneous().into();
         self.view_proj = (projection.calc_matrix() * eye.calc_matrix()).into();
+        self.right = eye.right().extend(0.0).into();
+        self.up = eye.up().extend(0.0).into();
     }
 }
 
@@ -121,6 +427,12 @@ pub struct Controller {
 }
 
 impl Controller {
+    // Ranges the GUI offers, and that saved settings are clamped to.
+    pub const MIN_SPEED: f32 = 0.5;
+    pub const MAX_SPEED: f32 = 50.0;
+    pub const MIN_SENSITIVITY: f32 = 0.05;
+    pub const MAX_SENSITIVITY: f32 = 2.0;
+
     fn new(speed: f32, sensitivity: f32) -> Self {
         Self {
             move_left: 0.0,
@@ -137,6 +449,24 @@ impl Controller {
         }
     }
 
+    /// Movement in world units per second.
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, s: f32) {
+        self.speed = s;
+    }
+
+    /// Scales mouse rotation and scroll.
+    pub fn sensitivity(&self) -> f32 {
+        self.sensitivity
+    }
+
+    pub fn set_sensitivity(&mut self, s: f32) {
+        self.sensitivity = s;
+    }
+
     pub fn process_keyboard(&mut self, key: VirtualKeyCode, state: ElementState) -> bool {
         let move_amount = if state == ElementState::Pressed { 1.0 } else { 0.0 };
         match key {
@@ -222,6 +552,17 @@ impl Controller {
     }
 }
 
+/// Eye placement saved between sessions, with yaw and pitch in radians.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct CameraState {
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Controller settings, missing from files saved before they were added.
+    pub speed: Option<f32>,
+    pub sensitivity: Option<f32>,
+}
+
 pub struct Camera {
     pub eye: Eye,
     pub projection: Projection,
@@ -299,9 +640,118 @@ impl Camera {
         }
     }
 
-    pub fn update(&mut self, dt: std::time::Duration, queue: &mut wgpu::Queue) {
+    pub fn view_proj(&self) -> Matrix4<f32> {
+        self.projection.calc_matrix() * self.eye.calc_matrix()
+    }
+
+    /// Position of `world_pos` on a screen of `screen_size`, from the top left corner,
+    /// or None if it's behind the camera.
+    pub fn project_to_screen(&self, world_pos: Point3<f32>, screen_size: Vector2<f32>) -> Option<Vector2<f32>> {
+        let clip = self.view_proj() * world_pos.to_homogeneous();
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let ndc = clip.truncate() / clip.w;
+        Some(Vector2::new(
+            (ndc.x * 0.5 + 0.5) * screen_size.x,
+            (0.5 - ndc.y * 0.5) * screen_size.y,
+        ))
+    }
+
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(self.view_proj())
+    }
+
+    pub fn update(&mut self, dt: std::time::Duration, uploader: &mut BufferUploader) {
         self.controller.update_eye(&mut self.eye, dt);
+        self.upload_uniform(uploader);
+    }
+
+    /// Moves the eye to `path` evaluated at `t` seconds.
+    pub fn play_path(&mut self, path: &CameraPath, t: f32, uploader: &mut BufferUploader) {
+        if let Some((position, rotation)) = path.sample(t) {
+            self.eye.position = position;
+            self.eye.set_rotation(rotation);
+            self.upload_uniform(uploader);
+        }
+    }
+
+    /// Uploads the current eye and projection, e.g. after changing the projection outside of `update`.
+    pub fn update_uniform(&mut self, queue: &wgpu::Queue) {
         self.uniform.update_view_proj(&self.eye, &self.projection);
         queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
     }
+
+    pub fn save_state(&self) -> CameraState {
+        CameraState {
+            position: self.eye.position.into(),
+            yaw: self.eye.yaw.0,
+            pitch: self.eye.pitch.0,
+            speed: Some(self.controller.speed),
+            sensitivity: Some(self.controller.sensitivity),
+        }
+    }
+
+    /// Moves the eye back to `state` and uploads it.
+    pub fn load_state(&mut self, state: &CameraState, queue: &wgpu::Queue) {
+        if let Some(speed) = state.speed {
+            self.controller.set_speed(speed.clamp(Controller::MIN_SPEED, Controller::MAX_SPEED));
+        }
+        if let Some(sensitivity) = state.sensitivity {
+            self.controller.set_sensitivity(sensitivity.clamp(Controller::MIN_SENSITIVITY, Controller::MAX_SENSITIVITY));
+        }
+        self.eye.position = state.position.into();
+        self.eye.yaw = Rad(state.yaw);
+        self.eye.pitch = Rad(state.pitch.clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2));
+        self.update_uniform(queue);
+    }
+
+    fn upload_uniform(&mut self, uploader: &mut BufferUploader) {
+        self.uniform.update_view_proj(&self.eye, &self.projection);
+        uploader.write(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    // Compares rotations by what they do to the axes, q and -q being the same rotation.
+    fn assert_same_rotation(a: Quaternion<f32>, b: Quaternion<f32>) {
+        for axis in [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()] {
+            let (ra, rb) = (a.rotate_vector(axis), b.rotate_vector(axis));
+            assert!((ra - rb).magnitude() < 1e-3, "{:?} and {:?} rotate {:?} differently", a, b, axis);
+        }
+    }
+
+    #[test]
+    fn euler_round_trips_random_rotations() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        for _ in 0..1000 {
+            let q = Quaternion::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            )
+            .normalize();
+            let (yaw, pitch, roll) = quaternion_to_euler(q);
+            assert!(pitch.abs() <= FRAC_PI_2);
+            assert_same_rotation(euler_to_quaternion(yaw, pitch, roll), q);
+        }
+    }
+
+    #[test]
+    fn euler_round_trips_straight_up_and_down() {
+        for pitch in [FRAC_PI_2, -FRAC_PI_2] {
+            for (yaw, roll) in [(0.0, 0.0), (0.7, 0.0), (-2.5, 1.2), (3.0, -0.4)] {
+                let q = euler_to_quaternion(yaw, pitch, roll);
+                let (yaw, round_pitch, roll) = quaternion_to_euler(q);
+                assert!((round_pitch - pitch).abs() < 1e-3);
+                assert_eq!(roll, 0.0);
+                assert_same_rotation(euler_to_quaternion(yaw, round_pitch, roll), q);
+            }
+        }
+    }
 }
@@ -0,0 +1,39 @@
+/// Linear RGBA color.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Color(pub [f32; 4]);
+
+#[allow(dead_code)]
+impl Color {
+    pub const BLACK: Self = Self([0.0, 0.0, 0.0, 1.0]);
+    pub const WHITE: Self = Self([1.0, 1.0, 1.0, 1.0]);
+    pub const GRAY: Self = Self([0.5, 0.5, 0.5, 1.0]);
+    pub const RED: Self = Self([1.0, 0.0, 0.0, 1.0]);
+    pub const GREEN: Self = Self([0.0, 1.0, 0.0, 1.0]);
+    pub const BLUE: Self = Self([0.0, 0.0, 1.0, 1.0]);
+    pub const YELLOW: Self = Self([1.0, 1.0, 0.0, 1.0]);
+    pub const CYAN: Self = Self([0.0, 1.0, 1.0, 1.0]);
+    pub const TRANSPARENT: Self = Self([0.0, 0.0, 0.0, 0.0]);
+
+    /// Parses `0xRRGGBB` with full alpha.
+    pub fn from_hex(hex: u32) -> Self {
+        let channel = |shift: u32| ((hex >> shift) & 0xff) as f32 / 255.0;
+        Self([channel(16), channel(8), channel(0), 1.0])
+    }
+
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        let mut result = self.0;
+        for (a, b) in result.iter_mut().zip(other.0) {
+            *a += (b - *a) * t;
+        }
+        Self(result)
+    }
+
+    pub fn to_wgpu(&self) -> wgpu::Color {
+        wgpu::Color {
+            r: self.0[0] as f64,
+            g: self.0[1] as f64,
+            b: self.0[2] as f64,
+            a: self.0[3] as f64,
+        }
+    }
+}
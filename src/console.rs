@@ -0,0 +1,90 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+type Entries = Arc<Mutex<VecDeque<(Level, String)>>>;
+
+/// Collects recent log output so it can be shown in the GUI.
+pub struct Console {
+    entries: Entries,
+}
+
+impl Console {
+    const CAPACITY: usize = 256;
+
+    /// Installs the console as the global logger, forwarding to `env_logger` as well.
+    /// Messages from this crate are captured down to `Info`, other crates down to `Warn`.
+    pub fn init() -> Self {
+        let entries = Arc::new(Mutex::new(VecDeque::with_capacity(Self::CAPACITY)));
+        let inner = env_logger::Builder::from_default_env().build();
+        let max_level = inner.filter().max(LevelFilter::Info);
+        let logger = ConsoleLogger {
+            inner,
+            entries: entries.clone(),
+        };
+        if log::set_boxed_logger(Box::new(logger)).is_ok() {
+            log::set_max_level(max_level);
+        }
+        Self {
+            entries,
+        }
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .stick_to_bottom()
+            .show(ui, |ui| {
+                for (level, message) in self.entries.lock().unwrap().iter() {
+                    // Monospace, so Gui::set_monospace_font changes the console's font.
+                    let text = egui::RichText::new(format!("[{}] {}", level, message)).monospace();
+                    let text = match level {
+                        Level::Error => text.color(egui::Color32::RED),
+                        Level::Warn => text.color(egui::Color32::YELLOW),
+                        _ => text,
+                    };
+                    ui.label(text);
+                }
+            });
+    }
+}
+
+struct ConsoleLogger {
+    inner: env_logger::Logger,
+    entries: Entries,
+}
+
+impl ConsoleLogger {
+    fn captures(&self, metadata: &Metadata) -> bool {
+        let level = if metadata.target().starts_with(env!("CARGO_CRATE_NAME")) {
+            Level::Info
+        } else {
+            Level::Warn
+        };
+        metadata.level() <= level
+    }
+}
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.captures(metadata) || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+        if self.captures(record.metadata()) {
+            let mut entries = self.entries.lock().unwrap();
+            if entries.len() == Console::CAPACITY {
+                entries.pop_front();
+            }
+            entries.push_back((record.level(), record.args().to_string()));
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
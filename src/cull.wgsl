@@ -0,0 +1,65 @@
+// Compute shader
+
+struct Cull {
+    planes: array<vec4<f32>, 6>;
+    // Bounding sphere in mesh space.
+    center: vec3<f32>;
+    radius: f32;
+    num_instances: u32;
+};
+[[group(0), binding(0)]]
+var<uniform> cull: Cull;
+
+// Matches the tightly packed InstanceRaw layout:
+// model (16 floats), normal (9 floats), color (4 floats).
+struct InstanceData {
+    data: array<f32, 29>;
+};
+
+struct Instances {
+    instances: array<InstanceData>;
+};
+[[group(0), binding(1)]]
+var<storage, read> input: Instances;
+[[group(0), binding(2)]]
+var<storage, read_write> output: Instances;
+
+// Only the instance count is written here, which sits at the same offset
+// for both indexed and non-indexed indirect draw arguments.
+struct DrawArgs {
+    count: u32;
+    instance_count: atomic<u32>;
+};
+[[group(0), binding(3)]]
+var<storage, read_write> args: DrawArgs;
+
+[[stage(compute), workgroup_size(64)]]
+fn cs_main(
+    [[builtin(global_invocation_id)]] id: vec3<u32>,
+) {
+    let index = id.x;
+    if (index >= cull.num_instances) {
+        return;
+    }
+
+    let instance = input.instances[index];
+    let axis_x = vec3<f32>(instance.data[0], instance.data[1], instance.data[2]);
+    let axis_y = vec3<f32>(instance.data[4], instance.data[5], instance.data[6]);
+    let axis_z = vec3<f32>(instance.data[8], instance.data[9], instance.data[10]);
+    let translation = vec3<f32>(instance.data[12], instance.data[13], instance.data[14]);
+    let center = translation + axis_x * cull.center.x + axis_y * cull.center.y + axis_z * cull.center.z;
+
+    // Grow the bounding sphere by the largest axis scale of the model matrix.
+    let scale = max(length(axis_x), max(length(axis_y), length(axis_z)));
+    let radius = cull.radius * scale;
+
+    for (var i = 0; i < 6; i = i + 1) {
+        let plane = cull.planes[i];
+        if (dot(plane.xyz, center) + plane.w < -radius) {
+            return;
+        }
+    }
+
+    let slot = atomicAdd(&args.instance_count, 1u);
+    output.instances[slot] = instance;
+}
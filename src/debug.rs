@@ -0,0 +1,121 @@
+use crate::camera::Frustum;
+use crate::color::Color;
+use crate::line::{LinePass, LineSegment};
+use crate::texture;
+
+/// A box to draw, as (transform, aabb_min, aabb_max) with the bounds in local space.
+pub type DebugBox = (cgmath::Matrix4<f32>, [f32; 3], [f32; 3]);
+
+// Pairs of corner indices making up the 12 edges of a box.
+const BOX_EDGES: [(usize, usize); 12] = [
+    (0, 1), (1, 3), (3, 2), (2, 0),
+    (4, 5), (5, 7), (7, 6), (6, 4),
+    (0, 4), (1, 5), (2, 6), (3, 7),
+];
+
+/// Draws wireframe bounding boxes, rebuilt every frame.
+pub struct DebugPass {
+    pub enabled: bool,
+    line_pass: LinePass,
+}
+
+impl DebugPass {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        Self {
+            enabled: false,
+            line_pass: LinePass::new(device, config, camera_layout),
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        boxes: &[DebugBox],
+    ) {
+        let mut segments: Vec<LineSegment> = Vec::with_capacity(boxes.len() * BOX_EDGES.len());
+        for (transform, min, max) in boxes {
+            // Corner i takes max on the axes whose bit is set (x = 1, y = 2, z = 4).
+            let corners = (0..8)
+                .map(|i| {
+                    let corner = cgmath::Vector4::new(
+                        if i & 1 == 0 { min[0] } else { max[0] },
+                        if i & 2 == 0 { min[1] } else { max[1] },
+                        if i & 4 == 0 { min[2] } else { max[2] },
+                        1.0,
+                    );
+                    (transform * corner).truncate()
+                })
+                .collect::<Vec<_>>();
+            for &(a, b) in BOX_EDGES.iter() {
+                segments.push((corners[a], corners[b], Color::GREEN.0));
+            }
+        }
+
+        self.line_pass.clear();
+        self.line_pass.extend(&segments);
+        self.line_pass.update(device);
+    }
+
+    pub fn render(
+        &self,
+        view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+        depth_texture: &texture::Texture,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        self.line_pass.render(view, encoder, depth_texture, camera_bind_group);
+    }
+}
+
+/// Draws the edges of a captured camera frustum, so it can be inspected from elsewhere.
+/// Only rebuilt when a new frustum is captured.
+pub struct FrustumPass {
+    pub enabled: bool,
+    line_pass: LinePass,
+}
+
+impl FrustumPass {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        Self {
+            enabled: false,
+            line_pass: LinePass::new(device, config, camera_layout),
+        }
+    }
+
+    pub fn capture(
+        &mut self,
+        device: &wgpu::Device,
+        frustum: &Frustum,
+    ) {
+        let corners = frustum.corners();
+        self.line_pass.clear();
+        for &(a, b) in BOX_EDGES.iter() {
+            self.line_pass.push((corners[a], corners[b], Color::YELLOW.0));
+        }
+        self.line_pass.update(device);
+    }
+
+    pub fn render(
+        &self,
+        view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+        depth_texture: &texture::Texture,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        self.line_pass.render(view, encoder, depth_texture, camera_bind_group);
+    }
+}
@@ -0,0 +1,356 @@
+use cgmath::prelude::*;
+use std::ops::Range;
+
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::mesh::{self, Vertex};
+use crate::pipeline::PipelineBuilder;
+use crate::texture;
+use crate::InstanceRaw;
+
+/// Most point lights `DeferredLightingPass` accumulates, must match `deferred_lighting.wgsl`.
+pub const MAX_LIGHTS: usize = 16;
+
+/// Renders instance albedo and world space normals into separate targets,
+/// with depth going to the shared depth texture.
+pub struct GBufferPass {
+    pub albedo: texture::Texture,
+    pub world_normal: texture::Texture,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl GBufferPass {
+    const ALBEDO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+    const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let (albedo, world_normal) = Self::create_targets(device, config);
+
+        let render_pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("G-Buffer Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+            let shader = wgpu::ShaderModuleDescriptor {
+                label: Some("G-Buffer Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("gbuffer.wgsl").into()),
+            };
+            PipelineBuilder::new("G-Buffer Render Pipeline")
+                .color_formats(&[Self::ALBEDO_FORMAT, Self::NORMAL_FORMAT])
+                .depth_format(texture::Texture::DEPTH_FORMAT)
+                .vertex_layouts(&[mesh::MeshVertex::desc(), InstanceRaw::desc()])
+                .shader(shader)
+                .build(device, &layout)
+        };
+
+        Self {
+            albedo,
+            world_normal,
+            render_pipeline,
+        }
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> (texture::Texture, texture::Texture) {
+        (
+            texture::Texture::create_render_texture_with_format(device, config, Self::ALBEDO_FORMAT, "G-Buffer Albedo"),
+            texture::Texture::create_render_texture_with_format(device, config, Self::NORMAL_FORMAT, "G-Buffer Normal"),
+        )
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        let (albedo, world_normal) = Self::create_targets(device, config);
+        self.albedo = albedo;
+        self.world_normal = world_normal;
+    }
+
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        depth_texture: &texture::Texture,
+        camera_bind_group: &wgpu::BindGroup,
+        mesh: &mesh::Mesh,
+        instance_buffer: &wgpu::Buffer,
+        instances: Range<u32>,
+    ) {
+        let clear = wgpu::Operations {
+            load: wgpu::LoadOp::Clear(Color::TRANSPARENT.to_wgpu()),
+            store: true,
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("G-Buffer Render Pass"),
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    view: &self.albedo.view,
+                    resolve_target: None,
+                    ops: clear,
+                },
+                wgpu::RenderPassColorAttachment {
+                    view: &self.world_normal.view,
+                    resolve_target: None,
+                    ops: clear,
+                },
+            ],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0),
+                    store: true,
+                }),
+            }),
+        });
+
+        use crate::mesh::DrawMesh;
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+        render_pass.draw_mesh_instanced(mesh, instances, Some(vec![camera_bind_group]), None);
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PointLight {
+    pub position: cgmath::Vector3<f32>,
+    pub color: Color,
+    pub intensity: f32,
+    /// Distance at which the light's contribution reaches zero.
+    pub radius: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PointLightRaw {
+    position: [f32; 3],
+    radius: f32,
+    color: [f32; 3],
+    intensity: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightsUniform {
+    inv_view_proj: [[f32; 4]; 4],
+    ambient: [f32; 4],
+    lights: [PointLightRaw; MAX_LIGHTS],
+    num_lights: u32,
+    // Uniforms require 16 byte spacing, so we need padding here
+    _padding: [u32; 3],
+}
+
+/// Shades the G-buffer with ambient light plus up to `MAX_LIGHTS` point lights,
+/// reconstructing world positions from the depth texture.
+pub struct DeferredLightingPass {
+    /// Lights past `MAX_LIGHTS` are ignored.
+    pub lights: Vec<PointLight>,
+    pub ambient: Color,
+    gbuffer_layout: wgpu::BindGroupLayout,
+    gbuffer_bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    mesh: mesh::Mesh,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl DeferredLightingPass {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        gbuffer: &GBufferPass,
+        depth_texture: &texture::Texture,
+    ) -> Self {
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type,
+            },
+            count: None,
+        };
+        // Texels are loaded directly, so nothing needs to be filterable.
+        let gbuffer_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("G-Buffer Bind Group Layout"),
+            entries: &[
+                texture_entry(0, wgpu::TextureSampleType::Float { filterable: false }),
+                texture_entry(1, wgpu::TextureSampleType::Float { filterable: false }),
+                texture_entry(2, wgpu::TextureSampleType::Depth),
+            ],
+        });
+        let gbuffer_bind_group = Self::create_gbuffer_bind_group(device, &gbuffer_layout, gbuffer, depth_texture);
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Lights Uniform Buffer"),
+            size: std::mem::size_of::<LightsUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Lights Uniform Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Lights Uniform Bind Group"),
+            layout: &uniform_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let mesh = mesh::Mesh::quad(
+            device,
+            2.0,
+            2.0,
+            true,
+        ).unwrap();
+
+        let render_pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Deferred Lighting Pipeline Layout"),
+                bind_group_layouts: &[
+                    &gbuffer_layout,
+                    &uniform_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+            let shader = wgpu::ShaderModuleDescriptor {
+                label: Some("Deferred Lighting Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("deferred_lighting.wgsl").into()),
+            };
+            PipelineBuilder::new("Deferred Lighting Render Pipeline")
+                .color_format(config.format)
+                .vertex_layouts(&[mesh::MeshVertex::desc()])
+                .shader(shader)
+                .build(device, &layout)
+        };
+
+        Self {
+            lights: Vec::new(),
+            ambient: Color([0.1, 0.1, 0.1, 1.0]),
+            gbuffer_layout,
+            gbuffer_bind_group,
+            uniform_buffer,
+            uniform_bind_group,
+            mesh,
+            render_pipeline,
+        }
+    }
+
+    fn create_gbuffer_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        gbuffer: &GBufferPass,
+        depth_texture: &texture::Texture,
+    ) -> wgpu::BindGroup {
+        // Bindings can only sample one aspect of a depth stencil texture.
+        let depth_view = depth_texture.texture.create_view(&wgpu::TextureViewDescriptor {
+            aspect: wgpu::TextureAspect::DepthOnly,
+            ..Default::default()
+        });
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("G-Buffer Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&gbuffer.albedo.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&gbuffer.world_normal.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&depth_view),
+                },
+            ],
+        })
+    }
+
+    /// Must be called after the G-buffer or depth texture are recreated.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        gbuffer: &GBufferPass,
+        depth_texture: &texture::Texture,
+    ) {
+        self.gbuffer_bind_group = Self::create_gbuffer_bind_group(device, &self.gbuffer_layout, gbuffer, depth_texture);
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera) {
+        let mut uniform = LightsUniform {
+            inv_view_proj: camera.view_proj()
+                .invert()
+                .unwrap_or_else(cgmath::Matrix4::identity)
+                .into(),
+            ambient: self.ambient.0,
+            lights: [bytemuck::Zeroable::zeroed(); MAX_LIGHTS],
+            num_lights: self.lights.len().min(MAX_LIGHTS) as u32,
+            _padding: [0; 3],
+        };
+        for (raw, light) in uniform.lights.iter_mut().zip(&self.lights) {
+            *raw = PointLightRaw {
+                position: light.position.into(),
+                radius: light.radius,
+                color: [light.color.0[0], light.color.0[1], light.color.0[2]],
+                intensity: light.intensity,
+            };
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Clears `view` to `background` and shades every pixel the G-buffer pass covered.
+    pub fn render(
+        &self,
+        view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+        background: Color,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Deferred Lighting Render Pass"),
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(background.to_wgpu()),
+                        store: true,
+                    },
+                }
+            ],
+            depth_stencil_attachment: None,
+        });
+
+        use crate::mesh::DrawMesh;
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.draw_mesh(
+            &self.mesh,
+            Some(vec![
+                &self.gbuffer_bind_group,
+                &self.uniform_bind_group,
+            ]),
+        );
+    }
+}
@@ -0,0 +1,73 @@
+// Vertex shader
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+// Fragment shader
+
+[[group(0), binding(0)]]
+var t_albedo: texture_2d<f32>;
+[[group(0), binding(1)]]
+var t_normal: texture_2d<f32>;
+[[group(0), binding(2)]]
+var t_depth: texture_depth_2d;
+
+struct PointLight {
+    position: vec3<f32>;
+    radius: f32;
+    color: vec3<f32>;
+    intensity: f32;
+};
+
+struct Lights {
+    inv_view_proj: mat4x4<f32>;
+    ambient: vec4<f32>;
+    lights: array<PointLight, 16>;
+    num_lights: u32;
+};
+[[group(1), binding(0)]]
+var<uniform> lights: Lights;
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let coords = vec2<i32>(in.clip_position.xy);
+    let depth = textureLoad(t_depth, coords, 0);
+    // Nothing was drawn here, keep the cleared background.
+    if (depth >= 1.0) {
+        discard;
+    }
+    let albedo = textureLoad(t_albedo, coords, 0);
+    let normal = normalize(textureLoad(t_normal, coords, 0).xyz);
+
+    // Reconstruct the world position from the depth buffer.
+    let uv = in.clip_position.xy / vec2<f32>(textureDimensions(t_depth));
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = lights.inv_view_proj * ndc;
+    let position = world.xyz / world.w;
+
+    var result = lights.ambient.rgb * albedo.rgb;
+    for (var i: u32 = 0u; i < lights.num_lights; i = i + 1u) {
+        let light = lights.lights[i];
+        let to_light = light.position - position;
+        let distance = length(to_light);
+        let diffuse = max(dot(normal, to_light / distance), 0.0);
+        // Falls off smoothly to zero at the light's radius.
+        let falloff = clamp(1.0 - distance / light.radius, 0.0, 1.0);
+        result = result + albedo.rgb * light.color * light.intensity * diffuse * falloff * falloff;
+    }
+    return vec4<f32>(result, albedo.a);
+}
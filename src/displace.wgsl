@@ -47,6 +47,37 @@ var t_diffuse: texture_2d<f32>;
 [[group(0), binding(1)]]
 var s_diffuse: sampler;
 
+struct DisplaceUniform {
+    time: f32;
+    // 0 is linear, 1 is Reinhard, 2 is ACES filmic.
+    tone_map_mode: u32;
+    exposure: f32;
+    gamma: f32;
+    vignette_strength: f32;
+    vignette_radius: f32;
+    noise_strength: f32;
+};
+
+// Width of the falloff from untouched to fully darkened, in uv units.
+let VIGNETTE_SOFTNESS: f32 = 0.45;
+[[group(1), binding(0)]]
+var<uniform> displace: DisplaceUniform;
+// Tiling noise, generated by the noise compute pass.
+[[group(1), binding(1)]]
+var t_noise: texture_2d<f32>;
+[[group(1), binding(2)]]
+var s_noise: sampler;
+
+// Narkowicz's fit of the ACES filmic curve.
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
 [[stage(fragment)]]
 fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
     // let near = 0.1;
@@ -60,16 +91,39 @@ fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
     //     return vec4<f32>(vec3<f32>(0.0), 1.0);
     // }
 
-    let displacement = 0.1;
+    // Pulse the channel separation over time.
+    let displacement = 0.1 * (0.75 + 0.25 * sin(displace.time * 2.0));
     let dispR = 1.0 + displacement;
     let dispB = 1.0 - displacement;
-    let uvR = ((in.tex_coords - vec2<f32>(0.5, 0.5)) * dispR) + vec2<f32>(0.5, 0.5);
-    let uvG = in.tex_coords;
-    let uvB = ((in.tex_coords - vec2<f32>(0.5, 0.5)) * dispB) + vec2<f32>(0.5, 0.5);
+    // Wobble the image with two lookups of slowly scrolling noise, one per axis.
+    let scroll = vec2<f32>(displace.time * 0.05, 0.0);
+    let wobble = vec2<f32>(
+        textureSample(t_noise, s_noise, in.tex_coords + scroll).r,
+        textureSample(t_noise, s_noise, in.tex_coords - scroll + vec2<f32>(0.5, 0.5)).r,
+    ) - vec2<f32>(0.5, 0.5);
+    let tex_coords = in.tex_coords + wobble * displace.noise_strength;
+    let uvR = ((tex_coords - vec2<f32>(0.5, 0.5)) * dispR) + vec2<f32>(0.5, 0.5);
+    let uvG = tex_coords;
+    let uvB = ((tex_coords - vec2<f32>(0.5, 0.5)) * dispB) + vec2<f32>(0.5, 0.5);
     let colR = textureSample(t_diffuse, s_diffuse, uvR).r;
     let colG = textureSample(t_diffuse, s_diffuse, uvG).g;
     let colB = textureSample(t_diffuse, s_diffuse, uvB).b;
-    return vec4<f32>(colR, colG, colB, 1.0);
+
+    var color = vec3<f32>(colR, colG, colB) * displace.exposure;
+    if (displace.tone_map_mode == 1u) {
+        color = color / (color + vec3<f32>(1.0));
+    } else if (displace.tone_map_mode == 2u) {
+        color = aces(color);
+    }
+
+    let vignette = smoothStep(
+        displace.vignette_radius,
+        displace.vignette_radius - VIGNETTE_SOFTNESS,
+        distance(in.tex_coords, vec2<f32>(0.5, 0.5)),
+    );
+    color = color * mix(1.0, vignette, displace.vignette_strength);
+    color = pow(color, vec3<f32>(1.0 / displace.gamma));
+    return vec4<f32>(color, 1.0);
 
     //let texCol = textureSample(t_diffuse, s_diffuse, in.tex_coords);
     //return texCol * vec4<f32>(1.0, 0.0, 0.0, 1.0);
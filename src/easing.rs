@@ -0,0 +1,89 @@
+//! Easing curves mapping `t` in [0, 1] to [0, 1], with f(0) = 0 and f(1) = 1.
+
+use std::f32::consts::PI;
+
+pub fn ease_in_quad(t: f32) -> f32 {
+    t * t
+}
+
+pub fn ease_out_quad(t: f32) -> f32 {
+    1.0 - (1.0 - t) * (1.0 - t)
+}
+
+pub fn ease_in_out_cubic(t: f32) -> f32 {
+    if t < 0.5 {
+        4.0 * t * t * t
+    } else {
+        1.0 - (-2.0 * t + 2.0).powi(3) * 0.5
+    }
+}
+
+pub fn ease_in_out_sine(t: f32) -> f32 {
+    -((PI * t).cos() - 1.0) * 0.5
+}
+
+/// Bounces against 1 with decreasing height. Not monotonic.
+pub fn ease_bounce_out(t: f32) -> f32 {
+    const N: f32 = 7.5625;
+    const D: f32 = 2.75;
+    if t < 1.0 / D {
+        N * t * t
+    } else if t < 2.0 / D {
+        let t = t - 1.5 / D;
+        N * t * t + 0.75
+    } else if t < 2.5 / D {
+        let t = t - 2.25 / D;
+        N * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / D;
+        N * t * t + 0.984375
+    }
+}
+
+/// Overshoots 1 and oscillates back. Not monotonic.
+pub fn ease_elastic_out(t: f32) -> f32 {
+    if t <= 0.0 {
+        0.0
+    } else if t >= 1.0 {
+        1.0
+    } else {
+        let c = 2.0 * PI / 3.0;
+        2.0f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * c).sin() + 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CURVES: [(&str, fn(f32) -> f32); 6] = [
+        ("ease_in_quad", ease_in_quad),
+        ("ease_out_quad", ease_out_quad),
+        ("ease_in_out_cubic", ease_in_out_cubic),
+        ("ease_in_out_sine", ease_in_out_sine),
+        ("ease_bounce_out", ease_bounce_out),
+        ("ease_elastic_out", ease_elastic_out),
+    ];
+
+    #[test]
+    fn endpoints() {
+        for (name, f) in CURVES {
+            assert!(f(0.0).abs() < 1e-5, "{}(0) = {}", name, f(0.0));
+            assert!((f(1.0) - 1.0).abs() < 1e-5, "{}(1) = {}", name, f(1.0));
+        }
+    }
+
+    #[test]
+    fn monotonic() {
+        // Bounce and elastic both turn back by design.
+        for (name, f) in &CURVES[..4] {
+            let mut previous = f(0.0);
+            for i in 1..=1000 {
+                let t = i as f32 / 1000.0;
+                let value = f(t);
+                assert!(value >= previous, "{} decreases at t = {}", name, t);
+                previous = value;
+            }
+        }
+    }
+}
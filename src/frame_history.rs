@@ -0,0 +1,47 @@
+use crate::util::RingBuffer;
+
+/// Keeps the most recent frame times, in seconds.
+pub struct FrameHistory {
+    times: RingBuffer<f32>,
+}
+
+impl FrameHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            times: RingBuffer::new(capacity),
+        }
+    }
+
+    pub fn push(&mut self, dt: std::time::Duration) {
+        self.times.push(dt.as_secs_f32());
+    }
+
+    pub fn clear(&mut self) {
+        self.times.clear();
+    }
+
+    /// Frame times from oldest to newest.
+    pub fn times(&self) -> impl Iterator<Item = f32> + '_ {
+        self.times.iter().copied()
+    }
+
+    pub fn last(&self) -> f32 {
+        self.times.last().copied().unwrap_or(0.0)
+    }
+
+    pub fn min(&self) -> f32 {
+        self.times().reduce(f32::min).unwrap_or(0.0)
+    }
+
+    pub fn max(&self) -> f32 {
+        self.times().reduce(f32::max).unwrap_or(0.0)
+    }
+
+    pub fn mean(&self) -> f32 {
+        if self.times.is_empty() {
+            0.0
+        } else {
+            self.times().sum::<f32>() / self.times.len() as f32
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use crate::util::RingBuffer;
+
+/// Measures GPU time spent in each pass using timestamp queries.
+/// Requires `wgpu::Features::TIMESTAMP_QUERY`.
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    staging_buffer: wgpu::Buffer,
+    labels: Vec<&'static str>,
+    // Recent readbacks for each pass, in milliseconds.
+    history: Vec<RingBuffer<f32>>,
+    period: f32,
+    frame: u32,
+    pending: bool,
+}
+
+impl GpuTimer {
+    // Number of frames between readbacks.
+    const READBACK_INTERVAL: u32 = 60;
+    // Number of readbacks averaged in `results`.
+    const HISTORY: usize = 4;
+
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, labels: &[&'static str]) -> Self {
+        // Every pass writes a start and an end timestamp.
+        let count = labels.len() as u32 * 2;
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("GPU Timer Query Set"),
+            ty: wgpu::QueryType::Timestamp,
+            count,
+        });
+
+        let size = (count as usize * std::mem::size_of::<u64>()) as wgpu::BufferAddress;
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("GPU Timer Resolve Buffer"),
+            size,
+            usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("GPU Timer Staging Buffer"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            query_set,
+            resolve_buffer,
+            staging_buffer,
+            labels: labels.to_vec(),
+            history: labels.iter().map(|_| RingBuffer::new(Self::HISTORY)).collect(),
+            period: queue.get_timestamp_period(),
+            frame: 0,
+            pending: false,
+        }
+    }
+
+    pub fn begin(&self, encoder: &mut wgpu::CommandEncoder, pass: usize) {
+        encoder.write_timestamp(&self.query_set, pass as u32 * 2);
+    }
+
+    pub fn end(&self, encoder: &mut wgpu::CommandEncoder, pass: usize) {
+        encoder.write_timestamp(&self.query_set, pass as u32 * 2 + 1);
+    }
+
+    /// Copies the timestamps into the staging buffer every `READBACK_INTERVAL` frames.
+    /// Call after all passes have been encoded.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        self.frame += 1;
+        if self.frame < Self::READBACK_INTERVAL {
+            return;
+        }
+        self.frame = 0;
+
+        let count = self.labels.len() as u32 * 2;
+        let size = (count as usize * std::mem::size_of::<u64>()) as wgpu::BufferAddress;
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.staging_buffer, 0, size);
+        self.pending = true;
+    }
+
+    /// Reads back resolved timestamps. Call after the encoder passed to `resolve` is submitted.
+    pub fn read_back(&mut self, device: &wgpu::Device) {
+        if !self.pending {
+            return;
+        }
+        self.pending = false;
+
+        let slice = self.staging_buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        if pollster::block_on(mapping).is_err() {
+            return;
+        }
+
+        {
+            let data = slice.get_mapped_range();
+            let timestamps: &[u64] = bytemuck::cast_slice(&data);
+            for (history, pair) in self.history.iter_mut().zip(timestamps.chunks(2)) {
+                let ticks = pair[1].saturating_sub(pair[0]);
+                // The period converts ticks to nanoseconds.
+                history.push(ticks as f32 * self.period / 1_000_000.0);
+            }
+        }
+        self.staging_buffer.unmap();
+    }
+
+    /// Each pass label with its time in milliseconds, averaged over the last `HISTORY` readbacks.
+    pub fn results(&self) -> impl Iterator<Item = (&'static str, f32)> + '_ {
+        self.labels.iter().copied().zip(self.history.iter().map(|history| {
+            if history.is_empty() {
+                0.0
+            } else {
+                history.iter().sum::<f32>() / history.len() as f32
+            }
+        }))
+    }
+}
@@ -0,0 +1,194 @@
+use wgpu::util::DeviceExt;
+
+use crate::color::Color;
+use crate::mesh::{self, Vertex};
+use crate::pipeline::PipelineBuilder;
+use crate::texture;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct GridUniform {
+    color: [f32; 4],
+    fade_distance: f32,
+    // Uniforms require 16 byte spacing, so we need padding here
+    _padding: [f32; 3],
+}
+
+/// Draws a reference grid of lines on the y=0 plane.
+pub struct GridPass {
+    pub visible: bool,
+    extent: f32,
+    cell_size: f32,
+    mesh: mesh::Mesh,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl GridPass {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let extent = 40.0;
+        let cell_size = 1.0;
+        let mesh = Self::build_mesh(device, extent, cell_size);
+
+        let uniform_data = Self::uniform_data(extent);
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Grid Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniform_data]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let uniform_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Grid Uniform Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Grid Uniform Bind Group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let render_pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Grid Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_layout,
+                    &uniform_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+            let shader = wgpu::ShaderModuleDescriptor {
+                label: Some("Grid Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("grid.wgsl").into()),
+            };
+            // Lines fade out, so blend them over the scene without writing depth.
+            PipelineBuilder::new("Grid Render Pipeline")
+                .color_format(config.format)
+                .depth_format(texture::Texture::DEPTH_FORMAT)
+                .depth_write_enabled(false)
+                .vertex_layouts(&[mesh::MeshVertex::desc()])
+                .topology(wgpu::PrimitiveTopology::LineList)
+                .blend(wgpu::BlendState::ALPHA_BLENDING)
+                .shader(shader)
+                .build(device, &layout)
+        };
+
+        Self {
+            visible: true,
+            extent,
+            cell_size,
+            mesh,
+            uniform_buffer,
+            uniform_bind_group,
+            render_pipeline,
+        }
+    }
+
+    pub fn extent(&self) -> f32 {
+        self.extent
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    pub fn set_size(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        extent: f32,
+        cell_size: f32,
+    ) {
+        self.extent = extent;
+        self.cell_size = cell_size;
+        self.mesh = Self::build_mesh(device, extent, cell_size);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[Self::uniform_data(extent)]));
+    }
+
+    fn uniform_data(extent: f32) -> GridUniform {
+        GridUniform {
+            color: Color::GRAY.0,
+            fade_distance: extent * 0.5,
+            _padding: [0.0; 3],
+        }
+    }
+
+    fn build_mesh(device: &wgpu::Device, extent: f32, cell_size: f32) -> mesh::Mesh {
+        let num_cells = (extent / cell_size).round().max(1.0) as u32;
+        let half_extent = num_cells as f32 * cell_size * 0.5;
+
+        let mut positions = Vec::new();
+        for i in 0..=num_cells {
+            let offset = i as f32 * cell_size - half_extent;
+            // Line parallel to the z axis.
+            positions.push(cgmath::Vector3::new(offset, 0.0, -half_extent));
+            positions.push(cgmath::Vector3::new(offset, 0.0, half_extent));
+            // Line parallel to the x axis.
+            positions.push(cgmath::Vector3::new(-half_extent, 0.0, offset));
+            positions.push(cgmath::Vector3::new(half_extent, 0.0, offset));
+        }
+
+        mesh::Mesh::lines(device, &positions).unwrap()
+    }
+
+    pub fn render(
+        &self,
+        view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+        depth_texture: &texture::Texture,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        if !self.visible {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Grid Render Pass"),
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }
+            ],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        use crate::mesh::DrawMesh;
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.draw_mesh(
+            &self.mesh,
+            Some(vec![
+                camera_bind_group,
+                &self.uniform_bind_group,
+            ]),
+        );
+    }
+}
@@ -0,0 +1,43 @@
+// Vertex shader
+
+struct Camera {
+    view_pos: vec4<f32>;
+    view_proj: mat4x4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+struct Grid {
+    color: vec4<f32>;
+    fade_distance: f32;
+};
+[[group(1), binding(0)]]
+var<uniform> grid: Grid;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] depth: f32;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
+    // For a perspective projection, clip w is the view space depth.
+    out.depth = out.clip_position.w;
+    return out;
+}
+
+// Fragment shader
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let fade = 1.0 - smoothStep(0.0, grid.fade_distance, in.depth);
+    return vec4<f32>(grid.color.rgb, grid.color.a * fade);
+}
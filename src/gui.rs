@@ -10,6 +10,10 @@ use egui_winit::State;
 use epi::Frame;
 use winit::{window::Window, event_loop::EventLoop, event::WindowEvent};
 
+use crate::camera::Camera;
+use crate::console::Console;
+use crate::texture;
+
 pub enum GuiEvent {
     RequestRedraw,
 }
@@ -29,6 +33,12 @@ pub struct Gui {
     frame: Frame,
     screen_descriptor: ScreenDescriptor,
     paint_jobs: Option<Vec<ClippedMesh>>,
+    console: Console,
+    dark_mode: bool,
+    // Kept so fonts added one after another all stay registered.
+    font_definitions: egui::FontDefinitions,
+    // Render targets shown in the "Internals" section, by label.
+    debug_textures: Vec<(String, egui::TextureId)>,
     pub using_pointer: bool,
     pub using_keyboard: bool,
 }
@@ -39,7 +49,7 @@ impl Gui {
         event_loop: &EventLoop<GuiEvent>,
         device: &wgpu::Device, 
         config: &wgpu::SurfaceConfiguration,
-
+        console: Console,
     ) -> Self {
         let context = egui::CtxRef::default();
         let state = egui_winit::State::new(&window);
@@ -55,7 +65,7 @@ impl Gui {
                     web_info: None,
                     cpu_usage: None,
                     native_pixels_per_point: Some(window.scale_factor() as _),
-                    prefer_dark_mode: None,
+                    prefer_dark_mode: Some(true),
                 },
                 output: epi::backend::AppOutput::default(),
                 repaint_signal: repaint_signal.clone(),
@@ -75,6 +85,10 @@ impl Gui {
             frame,
             screen_descriptor,
             paint_jobs: None,
+            console,
+            dark_mode: true,
+            font_definitions: egui::FontDefinitions::default(),
+            debug_textures: Vec::new(),
             using_pointer: false,
             using_keyboard: false,
         }
@@ -87,10 +101,110 @@ impl Gui {
         app.setup(&self.context, &self.frame, None);
     }
 
+    pub fn resize(
+        &mut self,
+        new_size: winit::dpi::PhysicalSize<u32>,
+        scale_factor: f64,
+    ) {
+        self.screen_descriptor = ScreenDescriptor {
+            physical_width: new_size.width,
+            physical_height: new_size.height,
+            scale_factor: scale_factor as f32,
+        };
+    }
+
+    pub fn set_dark_mode(&mut self) {
+        self.context.set_visuals(egui::Visuals::dark());
+        self.frame.lock().info.prefer_dark_mode = Some(true);
+        self.dark_mode = true;
+    }
+
+    pub fn set_light_mode(&mut self) {
+        self.context.set_visuals(egui::Visuals::light());
+        self.frame.lock().info.prefer_dark_mode = Some(false);
+        self.dark_mode = false;
+    }
+
+    /// Adds a TTF or OTF font under `name` and makes it the first choice for `family`,
+    /// falling back to the fonts already there for missing glyphs.
+    pub fn set_font(&mut self, font_data: Vec<u8>, family: egui::FontFamily, name: &str) {
+        self.font_definitions.font_data.insert(
+            name.to_string(),
+            egui::FontData::from_owned(font_data),
+        );
+        let fonts = self.font_definitions.fonts_for_family.entry(family).or_default();
+        fonts.retain(|existing| existing != name);
+        fonts.insert(0, name.to_string());
+        self.context.set_fonts(self.font_definitions.clone());
+    }
+
+    /// Font for text shown as code, like the console.
+    pub fn set_monospace_font(&mut self, font_data: Vec<u8>) {
+        self.set_font(font_data, egui::FontFamily::Monospace, "monospace");
+    }
+
+    /// Marks `world_pos` with a dot and `label` on top of everything else.
+    /// `screen_size` is in points, nothing is drawn if the point is behind the camera.
+    pub fn draw_world_label(
+        ctx: &egui::CtxRef,
+        world_pos: cgmath::Point3<f32>,
+        label: &str,
+        camera: &Camera,
+        screen_size: cgmath::Vector2<f32>,
+    ) {
+        let screen_pos = match camera.project_to_screen(world_pos, screen_size) {
+            Some(screen_pos) => egui::pos2(screen_pos.x, screen_pos.y),
+            None => return,
+        };
+        let text_color = if ctx.style().visuals.dark_mode {
+            egui::Color32::WHITE
+        } else {
+            egui::Color32::BLACK
+        };
+        let painter = ctx.debug_painter();
+        painter.circle_filled(screen_pos, 3.0, egui::Color32::YELLOW);
+        painter.text(
+            screen_pos + egui::vec2(5.0, -5.0),
+            egui::Align2::LEFT_BOTTOM,
+            label,
+            egui::TextStyle::Small,
+            text_color,
+        );
+    }
+
+    /// Registers `texture` as an egui user texture and lists it under "Internals".
+    /// Registering the same label again points it at the new texture, for use after a resize.
+    /// The texture must be a filterable color format with `TEXTURE_BINDING` usage.
+    pub fn add_debug_texture(
+        &mut self,
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        texture: &texture::Texture,
+        label: &str,
+    ) -> egui::TextureId {
+        let filter = wgpu::FilterMode::Linear;
+        if let Some((_, id)) = self.debug_textures.iter().find(|(existing, _)| existing == label) {
+            let id = *id;
+            if let Err(e) = self.render_pass.update_egui_texture_from_wgpu_texture(device, &texture.texture, filter, id) {
+                log::warn!("Failed to update debug texture {}: {}", label, e);
+            }
+            return id;
+        }
+        let id = self.render_pass.egui_texture_from_wgpu_texture(device, &texture.texture, filter);
+        self.debug_textures.push((label.to_string(), id));
+        id
+    }
+
+    /// Returns true if egui wants the event to itself.
     pub fn window_event(
         &mut self, 
         event: &WindowEvent
     ) -> bool {
+        // egui_winit picks up the new pixels per point, but the backend draws with the screen descriptor.
+        if let WindowEvent::ScaleFactorChanged { scale_factor, new_inner_size } = event {
+            self.resize(**new_inner_size, *scale_factor);
+            self.frame.lock().info.native_pixels_per_point = Some(*scale_factor as f32);
+        }
         self.state.on_event(&self.context, &event)
     }
 
@@ -101,10 +215,34 @@ impl Gui {
     ) {
         let frame_start = std::time::Instant::now();
         let raw_input = self.state.take_egui_input(window);
+        let dark_mode = self.dark_mode;
+        let debug_textures = &self.debug_textures;
+        let mut toggle_theme = false;
         let (output, shapes) = self.context.run(raw_input, |ctx| {
             // Draw the demo application.
             app.update(ctx, &self.frame);
+
+            egui::Window::new("Console")
+                .default_width(400.0)
+                .show(ctx, |ui| {
+                    let label = if dark_mode { "Light mode" } else { "Dark mode" };
+                    toggle_theme = ui.button(label).clicked();
+                    self.console.ui(ui);
+                    egui::CollapsingHeader::new("Internals").show(ui, |ui| {
+                        for (label, id) in debug_textures {
+                            ui.label(label);
+                            ui.image(*id, [160.0, 90.0]);
+                        }
+                    });
+                });
         });
+        if toggle_theme {
+            if dark_mode {
+                self.set_light_mode();
+            } else {
+                self.set_dark_mode();
+            }
+        }
 
         self.state.handle_output(&window, &self.context, output);
 
@@ -146,7 +284,7 @@ impl Gui {
                     .unwrap();
             },
             None => {
-                println!("No paint jobs, aborting render!");
+                log::warn!("No paint jobs, aborting render!");
             }
         };
     }
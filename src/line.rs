@@ -0,0 +1,236 @@
+use wgpu::util::DeviceExt;
+
+use crate::mesh::Vertex;
+use crate::pipeline::PipelineBuilder;
+use crate::render_pass::RenderPass;
+use crate::texture;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LineVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+impl Vertex for LineVertex {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+// Depth tested against the scene when `depth_format` is set, otherwise drawn over it.
+fn create_pipeline(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    camera_layout: &wgpu::BindGroupLayout,
+    depth_format: Option<wgpu::TextureFormat>,
+) -> wgpu::RenderPipeline {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Line Pipeline Layout"),
+        bind_group_layouts: &[
+            camera_layout,
+        ],
+        push_constant_ranges: &[],
+    });
+    let shader = wgpu::ShaderModuleDescriptor {
+        label: Some("Line Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("line.wgsl").into()),
+    };
+    let vertex_layouts = [LineVertex::desc()];
+    let mut builder = PipelineBuilder::new("Line Render Pipeline")
+        .color_format(config.format)
+        .vertex_layouts(&vertex_layouts)
+        .topology(wgpu::PrimitiveTopology::LineList)
+        .shader(shader);
+    if let Some(depth_format) = depth_format {
+        builder = builder.depth_format(depth_format);
+    }
+    builder.build(device, &layout)
+}
+
+/// A line to draw, as (start, end, color) in world space.
+pub type LineSegment = (cgmath::Vector3<f32>, cgmath::Vector3<f32>, [f32; 4]);
+
+/// Draws colored line segments collected on the CPU, rebuilt every frame.
+pub struct LinePass {
+    segments: Vec<LineSegment>,
+    vertex_buffer: Option<wgpu::Buffer>,
+    num_vertices: u32,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl LinePass {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let render_pipeline = create_pipeline(device, config, camera_layout, Some(texture::Texture::DEPTH_FORMAT));
+
+        Self {
+            segments: Vec::new(),
+            vertex_buffer: None,
+            num_vertices: 0,
+            render_pipeline,
+        }
+    }
+
+    /// Removes the segments queued for the next frame.
+    pub fn clear(&mut self) {
+        self.segments.clear();
+    }
+
+    pub fn push(&mut self, segment: LineSegment) {
+        self.segments.push(segment);
+    }
+
+    pub fn extend(&mut self, segments: &[LineSegment]) {
+        self.segments.extend_from_slice(segments);
+    }
+
+    /// Uploads the queued segments, replacing the previous vertex buffer.
+    pub fn update(&mut self, device: &wgpu::Device) {
+        let vertices = self.segments.iter()
+            .flat_map(|&(start, end, color)| [
+                LineVertex { position: start.into(), color },
+                LineVertex { position: end.into(), color },
+            ])
+            .collect::<Vec<_>>();
+        self.num_vertices = vertices.len() as u32;
+        self.vertex_buffer = if vertices.is_empty() {
+            None
+        } else {
+            Some(device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Line Vertex Buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }))
+        };
+    }
+
+    pub fn render(
+        &self,
+        view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+        depth_texture: &texture::Texture,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        let vertex_buffer = match &self.vertex_buffer {
+            Some(vertex_buffer) => vertex_buffer,
+            None => return,
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Line Render Pass"),
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }
+            ],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.draw(0..self.num_vertices, 0..1);
+    }
+}
+
+/// The world axes through the origin, X red, Y green and Z blue, drawn over the scene.
+/// Added with `State::add_pass` rather than owned by `State`.
+pub struct AxesPass {
+    vertex_buffer: wgpu::Buffer,
+    num_vertices: u32,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl AxesPass {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera_layout: &wgpu::BindGroupLayout,
+        length: f32,
+    ) -> Self {
+        let origin = [0.0; 3];
+        let vertices = [
+            ([length, 0.0, 0.0], [1.0, 0.0, 0.0, 1.0]),
+            ([0.0, length, 0.0], [0.0, 1.0, 0.0, 1.0]),
+            ([0.0, 0.0, length], [0.0, 0.0, 1.0, 1.0]),
+        ]
+            .iter()
+            .flat_map(|&(end, color)| [
+                LineVertex { position: origin, color },
+                LineVertex { position: end, color },
+            ])
+            .collect::<Vec<_>>();
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Axes Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        Self {
+            vertex_buffer,
+            num_vertices: vertices.len() as u32,
+            render_pipeline: create_pipeline(device, config, camera_layout, None),
+        }
+    }
+}
+
+impl RenderPass for AxesPass {
+    fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        camera: &wgpu::BindGroup,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Axes Render Pass"),
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }
+            ],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, camera, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.num_vertices, 0..1);
+    }
+}
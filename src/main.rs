@@ -9,21 +9,90 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 use wgpu::util::DeviceExt;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::collections::{HashSet, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
 
 mod texture;
+mod color;
 mod mesh;
 mod camera;
 mod gui;
+mod console;
+mod grid;
+mod debug;
+mod gpu_timer;
+mod screenshot;
+mod frame_history;
+mod util;
+mod timer;
+#[allow(dead_code)]
+pub mod easing;
+mod settings;
+mod shader_watcher;
+mod args;
+mod postprocess;
+mod render_pass;
+mod pipeline;
+mod transform;
+mod spline;
+mod skybox;
+mod line;
+mod particle;
+mod deferred;
+mod water;
+mod spotlight;
+mod noise;
+mod terrain;
+mod motion_blur;
+mod octree;
+mod minimap;
+mod billboard;
+mod spatial_hash;
+mod shadow;
 
 use camera::Camera;
+use color::Color;
 use mesh::Vertex;
 use gui::{Gui, GuiEvent};
+use grid::GridPass;
+use debug::{DebugPass, FrustumPass};
+use gpu_timer::GpuTimer;
+use frame_history::FrameHistory;
+use settings::AppSettings;
+use spline::BezierPath;
+use util::BufferUploader;
+use shader_watcher::ShaderWatcher;
+use args::Config;
+use postprocess::{PostProcess, PostProcessStack};
+use render_pass::RenderPass;
+use pipeline::{create_pipeline_layout, PipelineBuilder};
+use timer::Timer;
+use transform::Transform;
+use skybox::SkyboxPass;
+use line::{AxesPass, LinePass};
+use particle::ParticlePass;
+use water::WaterPass;
+use motion_blur::MotionBlurPass;
+use minimap::Minimap;
+use billboard::BillboardPass;
+use octree::{Aabb, OctTree};
+use spatial_hash::Grid3D;
+use shadow::CascadedShadowMapPass;
+use spotlight::Spotlight;
+use noise::{NoiseComputePass, NoiseParams};
+use terrain::Terrain;
+use deferred::{DeferredLightingPass, GBufferPass, PointLight};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct InstanceRaw {
     model: [[f32; 4]; 4],
     normal: [[f32; 3]; 3],
+    color: [f32; 4],
 }
 
 impl mesh::Vertex for InstanceRaw {
@@ -68,33 +137,68 @@ impl mesh::Vertex for InstanceRaw {
                     shader_location: 11,
                     format: wgpu::VertexFormat::Float32x3,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 25]>() as wgpu::BufferAddress,
+                    shader_location: 12,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ]
         }
     }
 }
 
 struct Instance {
-    position: cgmath::Vector3<f32>,
-    rotation: cgmath::Quaternion<f32>,
+    transform: Transform,
+    color: cgmath::Vector4<f32>,
+    // Changed since it was last uploaded to the instance buffer.
+    dirty: bool,
+    // Moves the instance along the path, with `path_t` the progress in [0, 1].
+    path: Option<BezierPath>,
+    path_t: f32,
+    // Integrated from the repulsion between instances, ignored while following a path.
+    velocity: cgmath::Vector3<f32>,
+    acceleration: cgmath::Vector3<f32>,
 }
 
 impl Instance {
     fn to_raw(&self) -> InstanceRaw {
-        let model = cgmath::Matrix4::from_translation(self.position) * cgmath::Matrix4::from(self.rotation);
         InstanceRaw {
-            model: model.into(),
-            normal: cgmath::Matrix3::from(self.rotation).into(), 
+            model: self.transform.to_matrix().into(),
+            normal: self.normal_matrix().into(),
+            color: self.color.into(),
         }
     }
+
+    // Use the inverse transpose so normals stay perpendicular under non-uniform scale.
+    fn normal_matrix(&self) -> cgmath::Matrix3<f32> {
+        let model = self.transform.to_matrix();
+        cgmath::Matrix3::from_cols(model.x.truncate(), model.y.truncate(), model.z.truncate())
+            .invert()
+            .unwrap_or_else(cgmath::Matrix3::identity)
+            .transpose()
+    }
 }
 
-fn quat_mul(q: cgmath::Quaternion<f32>, r: cgmath::Quaternion<f32>) -> cgmath::Quaternion<f32> {
-    let w = r.s * q.s - r.v.x * q.v.x - r.v.y * q.v.y - r.v.z * q.v.z;
-    let xi = r.s * q.v.x + r.v.x * q.s - r.v.y * q.v.z + r.v.z * q.v.y;
-    let yj = r.s * q.v.y + r.v.x * q.v.z + r.v.y * q.s - r.v.z * q.v.x;
-    let zk = r.s * q.v.z - r.v.x * q.v.y + r.v.y * q.v.x + r.v.z * q.s;
+fn random_color(rng: &mut impl Rng) -> cgmath::Vector4<f32> {
+    cgmath::Vector4::new(rng.gen(), rng.gen(), rng.gen(), 1.0)
+}
 
-    cgmath::Quaternion::new(w, xi, yj, zk)
+/// Closed path starting and ending at `center`, with its control points within `extent` of it.
+fn random_path(center: cgmath::Vector3<f32>, extent: f32, rng: &mut impl Rng) -> BezierPath {
+    let mut offset = || cgmath::Vector3::new(
+        rng.gen::<f32>() * 2.0 - 1.0,
+        rng.gen::<f32>() * 2.0 - 1.0,
+        rng.gen::<f32>() * 2.0 - 1.0,
+    ) * extent;
+    let center = cgmath::Point3::from_vec(center);
+    let (p1, p2) = (center + offset(), center + offset());
+    BezierPath {
+        p0: center,
+        p1,
+        p2,
+        p3: center,
+        duration: 4.0 + rng.gen::<f32>() * 4.0,
+    }
 }
 
 #[repr(C)]
@@ -103,8 +207,42 @@ struct WireUniform {
     smoothing: f32,
     thickness: f32,
     invert_edges: u32,
+    // Non-zero to color each triangle from the face color texture instead of the instance color.
+    face_colors: u32,
+    // Non-zero to color vertices by their scalar through the heatmap texture.
+    heatmap: u32,
+    // Uniforms require 16 byte spacing, so we need padding here
+    _padding: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct MeshMorphUniform {
+    // 0 draws the coarse mesh, 1 the fine one.
+    blend: f32,
+    // Non-zero while the morph offsets match the mesh being drawn.
+    enabled: u32,
+    // Uniforms require 16 byte spacing, so we need padding here
+    _padding: [u32; 2],
+}
+
+// Cross-fade between icosphere iterations, drawn with the finer of the two meshes.
+struct LodMorph {
+    coarse: Arc<mesh::Mesh>,
+    // Blend towards the fine mesh, otherwise towards `coarse`, which is swapped in at the end.
+    to_fine: bool,
+    elapsed: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullUniform {
+    planes: [[f32; 4]; 6],
+    center: [f32; 3],
+    radius: f32,
+    num_instances: u32,
     // Uniforms require 16 byte spacing, so we need padding here
-    _padding: u32,
+    _padding: [u32; 3],
 }
 
 // #[repr(C)]
@@ -116,144 +254,400 @@ struct WireUniform {
 //     color: [f32; 3],
 // }
 
-fn create_render_pipeline(
-    label: &str,
-    device: &wgpu::Device,
-    layout: &wgpu::PipelineLayout,
-    color_format: wgpu::TextureFormat,
-    depth_format: Option<wgpu::TextureFormat>,
-    vertex_layouts: &[wgpu::VertexBufferLayout],
-    shader: wgpu::ShaderModuleDescriptor,
-) -> wgpu::RenderPipeline {
-    let shader = device.create_shader_module(&shader);
-
-    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some(label),
-        layout: Some(layout),
-        vertex: wgpu::VertexState {
-            module: &shader,
-            entry_point: "vs_main",
-            buffers: vertex_layouts,
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: "fs_main",
-            targets: &[wgpu::ColorTargetState {
-                format: color_format,
-                blend: Some(wgpu::BlendState {
-                    alpha: wgpu::BlendComponent::REPLACE,
-                    color: wgpu::BlendComponent::REPLACE,
-                }),
-                write_mask: wgpu::ColorWrites::ALL,
-            }],
-        }),
-        primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
-            strip_index_format: None,
-            front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
-            //cull_mode: None,
-            // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-            polygon_mode: wgpu::PolygonMode::Fill,
-            // Requires Features::DEPTH_CLIP_CONTROL
-            unclipped_depth: false,
-            // Requires Features::CONSERVATIVE_RASTERIZATION
-            conservative: false,
-        },
-        depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
-            format,
-            depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::Less,
-            stencil: wgpu::StencilState::default(),
-            bias: wgpu::DepthBiasState::default(),
-        }),
-        multisample: wgpu::MultisampleState {
-            count: 1,
-            mask: !0,
-            alpha_to_coverage_enabled: false,
-        },
-        multiview: None,
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum MeshType {
+    Icosphere { radius: f32, iterations: u32 },
+    Cube { size: f32 },
+    Plane { width: f32, height: f32, subdivisions: u32 },
+    // Loaded from a file, with its local space bounding box.
+    Loaded { min: [f32; 3], max: [f32; 3] },
+}
+
+impl MeshType {
+    fn name(&self) -> &'static str {
+        match self {
+            MeshType::Icosphere { .. } => "icosphere",
+            MeshType::Cube { .. } => "cube",
+            MeshType::Plane { .. } => "plane",
+            MeshType::Loaded { .. } => "loaded",
+        }
+    }
+
+    fn bounding_radius(&self) -> f32 {
+        match *self {
+            MeshType::Icosphere { radius, .. } => radius,
+            MeshType::Cube { size } => size * 3.0f32.sqrt() * 0.5,
+            MeshType::Plane { width, height, .. } => (width * width + height * height).sqrt() * 0.5,
+            MeshType::Loaded { min, max } => {
+                let extent = |i: usize| min[i].abs().max(max[i].abs());
+                cgmath::Vector3::new(extent(0), extent(1), extent(2)).magnitude()
+            },
+        }
+    }
+
+    /// Local space bounding box as (min, max).
+    fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        match *self {
+            MeshType::Icosphere { radius, .. } => ([-radius; 3], [radius; 3]),
+            MeshType::Cube { size } => ([-size * 0.5; 3], [size * 0.5; 3]),
+            MeshType::Plane { width, height, .. } => (
+                [-width * 0.5, 0.0, -height * 0.5],
+                [width * 0.5, 0.0, height * 0.5],
+            ),
+            MeshType::Loaded { min, max } => (min, max),
+        }
+    }
+
+    fn build(&self, cache: &mut mesh::MeshCache, device: &wgpu::Device) -> anyhow::Result<Arc<mesh::Mesh>> {
+        match *self {
+            MeshType::Icosphere { radius, iterations } => mesh::Mesh::cached_icosphere(cache, device, radius, iterations, mesh::IcoUvMode::Spherical, false),
+            MeshType::Cube { size } => mesh::Mesh::cached_cube(cache, device, size, false),
+            MeshType::Plane { width, height, subdivisions } => mesh::Mesh::cached_plane(cache, device, width, height, subdivisions, false),
+            MeshType::Loaded { .. } => anyhow::bail!("Loaded meshes can't be rebuilt"),
+        }
+    }
+}
+
+fn create_instance_buffer(device: &wgpu::Device, instance_data: &[InstanceRaw]) -> wgpu::Buffer {
+    // GPU culling reads the instances as storage, when the device has any.
+    let storage = if device.limits().max_storage_buffers_per_shader_stage > 0 {
+        wgpu::BufferUsages::STORAGE
+    } else {
+        wgpu::BufferUsages::empty()
+    };
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Wire Instance Buffer"),
+        contents: bytemuck::cast_slice(instance_data),
+        usage: wgpu::BufferUsages::VERTEX | storage | wgpu::BufferUsages::COPY_DST,
     })
 }
 
+struct CullPass {
+    uniform_buffer: wgpu::Buffer,
+    culled_buffer: wgpu::Buffer,
+    indirect_buffer: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
+}
+
+impl CullPass {
+    fn new(
+        device: &wgpu::Device,
+        instance_buffer: &wgpu::Buffer,
+        capacity: u32,
+    ) -> Self {
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Uniform Buffer"),
+            size: std::mem::size_of::<CullUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // Large enough for either non-indexed (4 x u32) or indexed (5 x u32) arguments.
+        let indirect_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Indirect Buffer"),
+            size: std::mem::size_of::<[u32; 5]>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Cull Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(1, true),
+                storage_entry(2, false),
+                storage_entry(3, false),
+            ],
+        });
+
+        let culled_buffer = Self::create_culled_buffer(device, capacity);
+        let bind_group = Self::create_bind_group(
+            device,
+            &layout,
+            &uniform_buffer,
+            instance_buffer,
+            &culled_buffer,
+            &indirect_buffer,
+        );
+
+        let compute_pipeline = {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Cull Pipeline Layout"),
+                bind_group_layouts: &[
+                    &layout,
+                ],
+                push_constant_ranges: &[],
+            });
+            let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some("Cull Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("cull.wgsl").into()),
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Cull Compute Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "cs_main",
+            })
+        };
+
+        Self {
+            uniform_buffer,
+            culled_buffer,
+            indirect_buffer,
+            layout,
+            bind_group,
+            compute_pipeline,
+        }
+    }
+
+    fn create_culled_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Output Buffer"),
+            size: (capacity.max(1) as usize * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        instance_buffer: &wgpu::Buffer,
+        culled_buffer: &wgpu::Buffer,
+        indirect_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Cull Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: instance_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: culled_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: indirect_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        instance_buffer: &wgpu::Buffer,
+        capacity: u32,
+    ) {
+        self.culled_buffer = Self::create_culled_buffer(device, capacity);
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.layout,
+            &self.uniform_buffer,
+            instance_buffer,
+            &self.culled_buffer,
+            &self.indirect_buffer,
+        );
+    }
+
+    fn update(
+        &self,
+        uploader: &mut BufferUploader,
+        frustum: &camera::Frustum,
+        bounding_sphere: (cgmath::Vector3<f32>, f32),
+        num_instances: u32,
+        num_elements: u32,
+    ) {
+        let (center, radius) = bounding_sphere;
+        let uniform = CullUniform {
+            planes: frustum.planes(),
+            center: center.into(),
+            radius,
+            num_instances,
+            _padding: [0; 3],
+        };
+        uploader.write(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        // Reset the instance count, the compute shader increments it for each visible instance.
+        let args: [u32; 5] = [num_elements, 0, 0, 0, 0];
+        uploader.write(&self.indirect_buffer, 0, bytemuck::cast_slice(&args));
+    }
+
+    fn dispatch(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        num_instances: u32,
+    ) {
+        const WORKGROUP_SIZE: u32 = 64;
+
+        if num_instances == 0 {
+            return;
+        }
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Cull Compute Pass"),
+        });
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.dispatch(num_instances.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+}
+
 struct WirePass {
-    clear_color: wgpu::Color,
+    background_color: [f32; 4],
     radius: f32,
     iterations: u32,
-    mesh: mesh::Mesh,
+    mesh_type: MeshType,
+    lod_group: mesh::LodGroup,
+    // Build coarser icospheres for distant instances, which disables GPU culling.
+    lod_enabled: bool,
+    // Distance from the camera where each coarser tier starts, in multiples of this.
+    lod_distance: f32,
+    // Range of the instance buffer drawn with each tier, in drawing order.
+    lod_draws: Vec<(usize, std::ops::Range<u32>)>,
+    mesh_cache: mesh::MeshCache,
+    grid_dim: u32,
+    grid_spacing: f32,
     instances: Vec<Instance>,
+    // Indices into `instances` left out of the upload, and so never drawn.
+    hidden: HashSet<usize>,
+    // Instance bounds for picking and CPU culling, rebuilt when instances are added, removed or moved.
+    octree: OctTree,
     instance_buffer: wgpu::Buffer,
+    buffer_capacity: u32,
+    visible_count: u32,
+    // Instances `render_overview` draws, the visible ones followed by the rest that aren't hidden.
+    overview_count: u32,
+    cull_pass: Option<CullPass>,
     uniform: WireUniform,
     uniform_buffer: wgpu::Buffer,
+    uniform_bind_group_layout: wgpu::BindGroupLayout,
     uniform_bind_group: wgpu::BindGroup,
+    // Hue per triangle, see `Mesh::face_color_texture`, built for `face_color_count` triangles.
+    face_color_texture: texture::Texture,
+    // Maps vertex scalars to colors, see `Mesh::heatmap_texture`.
+    heatmap_texture: texture::Texture,
+    face_color_count: u32,
+    morph_uniform: MeshMorphUniform,
+    morph_uniform_buffer: wgpu::Buffer,
+    morph_bind_group_layout: wgpu::BindGroupLayout,
+    morph_bind_group: wgpu::BindGroup,
+    lod_morph: Option<LodMorph>,
     rotation_speed: f32,
+    // Restart instance paths once they reach the end, instead of stopping there.
+    loop_paths: bool,
+    // Strength of the push between overlapping instances, 0 disables it.
+    repulsion: f32,
+    // Instance positions for neighbor queries, rebuilt every frame repulsion is enabled.
+    spatial_hash: Grid3D,
+    color_format: wgpu::TextureFormat,
+    // Draw unique edges as lines instead of filled triangles.
+    wireframe: bool,
+    enable_transparency: bool,
+    shader_source: String,
+    render_pipeline_layout: wgpu::PipelineLayout,
     render_pipeline: wgpu::RenderPipeline,
+    // Instance outlined using the stencil buffer, set by picking.
+    selected_instance: Option<usize>,
+    // The selected instance followed by an enlarged copy for the outline.
+    selection_buffer: wgpu::Buffer,
+    outline_pipelines: (wgpu::RenderPipeline, wgpu::RenderPipeline),
+    // Holds the outline color without Features::PUSH_CONSTANTS, which carry it otherwise.
+    outline_uniform: Option<(wgpu::Buffer, wgpu::BindGroup)>,
+    outline_color: [f32; 4],
+    // Draw vertex normals and tangents of visible instances as lines.
+    show_normals: bool,
+    normal_scale: f32,
 }
 
 impl WirePass {
+    // Scale of the outline shell relative to the selected instance.
+    const OUTLINE_SCALE: f32 = 1.05;
+    // Seconds to morph between icosphere iterations.
+    const MORPH_DURATION: f32 = 0.2;
+    // Coarser icospheres added below the selected one when LOD is enabled.
+    const LOD_TIERS: u32 = 2;
+    const OCTREE_DEPTH: u32 = 5;
+    // Fraction of the repulsion velocity lost per second.
+    const REPULSION_DAMPING: f32 = 4.0;
+
+    #[allow(clippy::too_many_arguments)]
     fn new(
         device: &wgpu::Device, 
+        queue: &wgpu::Queue,
         config: &wgpu::SurfaceConfiguration,
         camera_bind_group_layout: &wgpu::BindGroupLayout,
+        spotlight_bind_group_layout: &wgpu::BindGroupLayout,
+        gpu_culling: bool,
+        settings: &AppSettings,
+        rng: &mut impl Rng,
     ) -> Self {
-        let clear_color = wgpu::Color::BLACK;
+        let background_color = settings.background_color;
 
-        let radius = 1.0;
-        let iterations = 2;
+        let radius = settings.radius;
+        let iterations = settings.iterations;
 
-        let mesh = mesh::Mesh::icosphere(
-            &device,
-            radius,
-            iterations,
-            false,
-        ).unwrap();
+        let mesh_type = MeshType::Icosphere { radius, iterations };
+        let mut mesh_cache = mesh::MeshCache::default();
+        let mesh = mesh_type.build(&mut mesh_cache, device).unwrap();
+        let lod_distance = 10.0;
 
-        // const NUM_INSTANCES_PER_DIM: u32 = 10;
-        // const SPACE_BETWEEN: f32 = 3.0;
-        // let instances = (0..NUM_INSTANCES_PER_DIM).flat_map(|z| {
-        //     (0..NUM_INSTANCES_PER_DIM).map(move |x| {
-        //         let x = SPACE_BETWEEN * (x as f32 - NUM_INSTANCES_PER_DIM as f32 / 2.0);
-        //         let z = SPACE_BETWEEN * (z as f32 - NUM_INSTANCES_PER_DIM as f32 / 2.0);
-
-        //         let position = cgmath::Vector3 { x, y: 0.0, z };
-
-        //         let rotation = if position.is_zero() {
-        //             cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0))
-        //         } else {
-        //             cgmath::Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(45.0))
-        //         };
-
-        //         Instance {
-        //             position,
-        //             rotation,
-        //         }
-        //     })
-        // }).collect::<Vec<_>>();
-
-        let instances = vec![
-            Instance {
-                position: cgmath::Vector3::zero(),
-                rotation: cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0)),
-            }
-        ];
+        let grid_dim = 1;
+        let grid_spacing = 3.0;
+        let instances = Self::build_grid(grid_dim, grid_spacing, rng);
 
+        let buffer_capacity = instances.len() as u32;
+        let visible_count = instances.len() as u32;
         let instance_buffer = {
             let instance_data = instances.iter()
                 .map(Instance::to_raw)
                 .collect::<Vec<_>>();
             
-            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Wire Instance Buffer"),
-                contents: bytemuck::cast_slice(&instance_data),
-                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            })
+            create_instance_buffer(device, &instance_data)
+        };
+
+        let cull_pass = if gpu_culling {
+            Some(CullPass::new(device, &instance_buffer, buffer_capacity))
+        } else {
+            None
         };
 
         let uniform_data = WireUniform {
             smoothing: 0.5,
             thickness: 8.0,
             invert_edges: 0,
-            _padding: 0,
+            face_colors: 0,
+            heatmap: 0,
+            _padding: [0; 3],
         };
 
         let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -264,105 +658,950 @@ impl WirePass {
 
         let uniform_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label:None,
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
                 },
-                count: None,
-            }],
+            ],
         });
-        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &uniform_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
-            }],
+        let heatmap_texture = mesh::Mesh::heatmap_texture(device, queue);
+        let face_color_count = mesh.num_triangles();
+        let face_color_texture = mesh::Mesh::face_color_texture(device, queue, face_color_count);
+        let uniform_bind_group = Self::create_uniform_bind_group(
+            device,
+            &uniform_bind_group_layout,
+            &uniform_buffer,
+            &face_color_texture,
+            &heatmap_texture,
+        );
+
+        let morph_uniform = MeshMorphUniform {
+            blend: 1.0,
+            enabled: 0,
+            _padding: [0; 2],
+        };
+        let morph_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Morph Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[morph_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let morph_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Morph Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+            ],
         });
+        let morph_bind_group = Self::create_morph_bind_group(
+            device,
+            &morph_bind_group_layout,
+            &morph_uniform_buffer,
+            &mesh::MorphTarget::empty(device, queue),
+        );
 
-        let rotation_speed = 0.01;
+        // Radians per second.
+        let rotation_speed = settings.rotation_speed;
 
-        let render_pipeline = {
-            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Wire Pipeline Layout"),
-                bind_group_layouts: &[
-                    //&texture_bind_group_layout,
-                    &camera_bind_group_layout,
-                    &uniform_bind_group_layout,
-                ],
-                push_constant_ranges: &[],
-            });
-            let shader = wgpu::ShaderModuleDescriptor {
-                label: Some("Wire Shader"),
-                source: wgpu::ShaderSource::Wgsl(include_str!("wire.wgsl").into()),
-            };
-            create_render_pipeline(
-                "Wire Render Pipeline",
-                &device,
-                &layout,
-                config.format,
-                Some(texture::Texture::DEPTH_FORMAT),
-                // &[model::ModelVertex::desc(), InstanceRaw::desc()],
-                &[mesh::MeshVertex::desc(), InstanceRaw::desc()],
-                shader,
-            )
-        };
+        let wireframe = false;
+        let enable_transparency = false;
 
-        Self {
-            clear_color,
+        let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Wire Pipeline Layout"),
+            bind_group_layouts: &[
+                //&texture_bind_group_layout,
+                &camera_bind_group_layout,
+                &uniform_bind_group_layout,
+                spotlight_bind_group_layout,
+                &morph_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let shader_source = include_str!("wire.wgsl").to_string();
+        let render_pipeline = Self::create_pipeline(
+            device,
+            &render_pipeline_layout,
+            config.format,
+            wireframe,
+            enable_transparency,
+            &shader_source,
+        );
+
+        let selection_buffer = create_instance_buffer(device, &[bytemuck::Zeroable::zeroed(); 2]);
+        let (outline_pipelines, outline_uniform) = Self::create_outline_pipelines(
+            device,
+            config.format,
+            camera_bind_group_layout,
+        );
+
+        let mut wire_pass = Self {
+            background_color,
             radius,
             iterations,
-            mesh,
+            mesh_type,
+            lod_group: mesh::LodGroup::single(mesh),
+            lod_enabled: false,
+            lod_distance,
+            lod_draws: Vec::new(),
+            mesh_cache,
+            grid_dim,
+            grid_spacing,
             instances,
+            hidden: HashSet::new(),
+            octree: OctTree::new(Aabb::from_sphere(cgmath::Vector3::zero(), 0.0), Self::OCTREE_DEPTH),
             instance_buffer,
+            buffer_capacity,
+            visible_count,
+            overview_count: visible_count,
+            cull_pass,
             uniform: uniform_data,
             uniform_buffer,
+            uniform_bind_group_layout,
             uniform_bind_group,
+            face_color_texture,
+            heatmap_texture,
+            face_color_count,
+            morph_uniform,
+            morph_uniform_buffer,
+            morph_bind_group_layout,
+            morph_bind_group,
+            lod_morph: None,
             rotation_speed,
+            loop_paths: true,
+            repulsion: 0.0,
+            spatial_hash: Grid3D::new(radius * 2.0),
+            color_format: config.format,
+            wireframe,
+            enable_transparency,
+            shader_source,
+            render_pipeline_layout,
             render_pipeline,
-        }
+            selected_instance: None,
+            selection_buffer,
+            outline_pipelines,
+            outline_uniform,
+            outline_color: [1.0, 0.6, 0.0, 1.0],
+            show_normals: false,
+            normal_scale: 0.1,
+        };
+        wire_pass.rebuild_octree();
+        wire_pass
     }
 
-    fn remesh(
-        &mut self, 
+    fn create_uniform_bind_group(
         device: &wgpu::Device,
-    )
-    {
-        self.mesh = mesh::Mesh::icosphere(
-            &device,
-            self.radius,
-            self.iterations,
-            false
-        ).unwrap();
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        face_color_texture: &texture::Texture,
+        heatmap_texture: &texture::Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&face_color_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&heatmap_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&heatmap_texture.sampler),
+                },
+            ],
+        })
     }
 
-    fn update(&mut self, _dt: std::time::Duration, queue: &mut wgpu::Queue) {
-        // Update the instances.
-        for instance in &mut self.instances {
-            let amount = cgmath::Quaternion::from_angle_y(cgmath::Rad(self.rotation_speed));
-            let current = instance.rotation;
-            instance.rotation = quat_mul(amount, current);
-        }
-        let instance_data = self.instances.iter()
-            .map(Instance::to_raw)
-            .collect::<Vec<_>>();
-        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instance_data));
+    fn create_morph_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        morph_target: &mesh::MorphTarget,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Morph Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&morph_target.vertex_offsets.view),
+                },
+            ],
+        })
+    }
 
-        // Update the uniforms.
-        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    /// Rebuilds the face color texture once face colors are shown and the mesh's triangle count changed.
+    fn update_face_color_texture(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let num_triangles = self.lod_group.base().num_triangles();
+        if self.uniform.face_colors == 0 || num_triangles == self.face_color_count {
+            return;
+        }
+        self.face_color_texture = mesh::Mesh::face_color_texture(device, queue, num_triangles);
+        self.face_color_count = num_triangles;
+        self.uniform_bind_group = Self::create_uniform_bind_group(
+            device,
+            &self.uniform_bind_group_layout,
+            &self.uniform_buffer,
+            &self.face_color_texture,
+            &self.heatmap_texture,
+        );
     }
 
-    fn render(
-        &self, 
-        view: &wgpu::TextureView, 
-        encoder: &mut wgpu::CommandEncoder,
-        depth_texture: &texture::Texture,
+    /// Creates the pipeline that marks the selected instance with stencil value 1,
+    /// and the one that draws the enlarged outline wherever the stencil isn't 1.
+    /// Without push constants the color is bound from the returned uniform buffer instead.
+    fn create_outline_pipelines(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> ((wgpu::RenderPipeline, wgpu::RenderPipeline), Option<(wgpu::Buffer, wgpu::BindGroup)>) {
+        let color_size = std::mem::size_of::<[f32; 4]>();
+        let (layout, shader_source, outline_uniform) = if device.features().contains(wgpu::Features::PUSH_CONSTANTS) {
+            let layout = create_pipeline_layout(
+                device,
+                "Outline Pipeline Layout",
+                &[camera_bind_group_layout],
+                Some(color_size as u32),
+            );
+            (layout, include_str!("outline.wgsl").to_string(), None)
+        } else {
+            let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Outline Uniform Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Outline Uniform Buffer"),
+                size: color_size as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Outline Uniform Bind Group"),
+                layout: &uniform_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    },
+                ],
+            });
+            let layout = create_pipeline_layout(
+                device,
+                "Outline Pipeline Layout",
+                &[camera_bind_group_layout, &uniform_layout],
+                None,
+            );
+            // Same shader, with the color read from group 1 instead.
+            let shader_source = include_str!("outline.wgsl").replace(
+                "var<push_constant> outline: Outline;",
+                "[[group(1), binding(0)]]\nvar<uniform> outline: Outline;",
+            );
+            (layout, shader_source, Some((buffer, bind_group)))
+        };
+        let shader = || wgpu::ShaderModuleDescriptor {
+            label: Some("Outline Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.as_str().into()),
+        };
+
+        // Mark every pixel covered by the instance, whether or not it passes the depth test,
+        // since the instance was already drawn at the same depth.
+        let mark = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::Always,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Replace,
+            pass_op: wgpu::StencilOperation::Replace,
+        };
+        let stencil_pipeline = PipelineBuilder::new("Stencil Render Pipeline")
+            .color_format(color_format)
+            .color_writes(wgpu::ColorWrites::empty())
+            .depth_format(texture::Texture::DEPTH_FORMAT)
+            .depth_write_enabled(false)
+            .stencil(wgpu::StencilState {
+                front: mark,
+                back: mark,
+                read_mask: 0xff,
+                write_mask: 0xff,
+            })
+            .vertex_layouts(&[mesh::MeshVertex::desc(), InstanceRaw::desc()])
+            .shader(shader())
+            .build(device, &layout);
+
+        let outside = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::NotEqual,
+            ..wgpu::StencilFaceState::IGNORE
+        };
+        let outline_pipeline = PipelineBuilder::new("Outline Render Pipeline")
+            .color_format(color_format)
+            .depth_format(texture::Texture::DEPTH_FORMAT)
+            .depth_write_enabled(false)
+            .stencil(wgpu::StencilState {
+                front: outside,
+                back: outside,
+                read_mask: 0xff,
+                write_mask: 0,
+            })
+            .vertex_layouts(&[mesh::MeshVertex::desc(), InstanceRaw::desc()])
+            .shader(shader())
+            .build(device, &layout);
+
+        ((stencil_pipeline, outline_pipeline), outline_uniform)
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        color_format: wgpu::TextureFormat,
+        wireframe: bool,
+        enable_transparency: bool,
+        shader_source: &str,
+    ) -> wgpu::RenderPipeline {
+        // Transparent instances are blended back to front and don't occlude each other.
+        let (blend, depth_write_enabled) = if enable_transparency {
+            (wgpu::BlendState::ALPHA_BLENDING, false)
+        } else {
+            (wgpu::BlendState::REPLACE, true)
+        };
+        let shader = wgpu::ShaderModuleDescriptor {
+            label: Some("Wire Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        };
+        let topology = if wireframe {
+            wgpu::PrimitiveTopology::LineList
+        } else {
+            wgpu::PrimitiveTopology::TriangleList
+        };
+        PipelineBuilder::new("Wire Render Pipeline")
+            .color_format(color_format)
+            .depth_format(texture::Texture::DEPTH_FORMAT)
+            .depth_write_enabled(depth_write_enabled)
+            .vertex_layouts(&[mesh::MeshVertex::desc(), InstanceRaw::desc()])
+            .topology(topology)
+            .blend(blend)
+            .shader(shader)
+            .build(device, layout)
+    }
+
+    fn set_wireframe(
+        &mut self,
+        device: &wgpu::Device,
+        wireframe: bool,
+    ) {
+        self.wireframe = wireframe;
+        self.rebuild_pipeline(device);
+    }
+
+    fn set_transparency(
+        &mut self,
+        device: &wgpu::Device,
+        enable_transparency: bool,
+    ) {
+        self.enable_transparency = enable_transparency;
+        self.rebuild_pipeline(device);
+    }
+
+    fn rebuild_pipeline(&mut self, device: &wgpu::Device) {
+        self.render_pipeline = Self::create_pipeline(
+            device,
+            &self.render_pipeline_layout,
+            self.color_format,
+            self.wireframe,
+            self.enable_transparency,
+            &self.shader_source,
+        );
+    }
+
+    /// Rebuilds the pipeline from new shader source, keeping the current one if it fails to compile.
+    fn reload_shader(&mut self, device: &wgpu::Device, shader_source: String) -> anyhow::Result<()> {
+        self.render_pipeline = shader_watcher::validated(device, || Self::create_pipeline(
+            device,
+            &self.render_pipeline_layout,
+            self.color_format,
+            self.wireframe,
+            self.enable_transparency,
+            &shader_source,
+        ))?;
+        self.shader_source = shader_source;
+        Ok(())
+    }
+
+    fn active_cull_pass(&self) -> Option<&CullPass> {
+        // GPU culling doesn't preserve instance order, so sorted transparent instances are culled on the CPU.
+        // Its indirect draw also assumes the triangle index count, so wireframes are culled on the CPU too.
+        // Hidden instances are skipped while packing the buffer, which only happens on the CPU,
+        // as does sorting the instances by LOD tier.
+        if self.enable_transparency || self.wireframe || !self.hidden.is_empty() || self.lod_group.len() > 1 {
+            None
+        } else {
+            self.cull_pass.as_ref()
+        }
+    }
+
+    fn remesh(
+        &mut self, 
+        device: &wgpu::Device,
+    )
+    {
+        self.remesh_with(device, self.radius, self.iterations);
+    }
+
+    fn remesh_with(
+        &mut self,
+        device: &wgpu::Device,
+        radius: f32,
+        iterations: u32,
+    ) {
+        self.set_mesh_type(device, MeshType::Icosphere { radius, iterations });
+    }
+
+    /// Remeshes after `iterations` changed from `previous_iterations`. A single step between
+    /// icospheres morphs over `MORPH_DURATION` instead of popping.
+    fn remesh_iterations(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        previous_iterations: u32,
+    ) {
+        let previous_mesh = self.lod_group.base().clone();
+        let was_icosphere = matches!(self.mesh_type, MeshType::Icosphere { .. });
+        self.remesh(device);
+        // The morph offsets only match the finest tier.
+        if !was_icosphere || self.lod_enabled || self.iterations.abs_diff(previous_iterations) != 1 {
+            return;
+        }
+
+        let to_fine = self.iterations > previous_iterations;
+        let (coarse, fine) = if to_fine {
+            (previous_mesh, self.lod_group.base().clone())
+        } else {
+            (self.lod_group.base().clone(), previous_mesh)
+        };
+        let morph_target = mesh::MorphTarget::icosphere(
+            device,
+            queue,
+            self.radius,
+            self.iterations.max(previous_iterations),
+            mesh::IcoUvMode::Spherical,
+        );
+        self.morph_bind_group = Self::create_morph_bind_group(
+            device,
+            &self.morph_bind_group_layout,
+            &self.morph_uniform_buffer,
+            &morph_target,
+        );
+        self.lod_group = mesh::LodGroup::single(fine);
+        self.lod_morph = Some(LodMorph {
+            coarse,
+            to_fine,
+            elapsed: 0.0,
+        });
+    }
+
+    fn set_mesh_type(
+        &mut self,
+        device: &wgpu::Device,
+        mt: MeshType,
+    ) {
+        self.lod_group = self.build_lod_group(device, mt).unwrap();
+        self.mesh_cache.evict_unused();
+        self.mesh_type = mt;
+        self.lod_morph = None;
+        self.rebuild_octree();
+    }
+
+    /// Icospheres get `LOD_TIERS` coarser versions while LOD is enabled, everything else a single mesh.
+    fn build_lod_group(
+        &mut self,
+        device: &wgpu::Device,
+        mt: MeshType,
+    ) -> anyhow::Result<mesh::LodGroup> {
+        let base = mt.build(&mut self.mesh_cache, device)?;
+        let (radius, iterations) = match mt {
+            MeshType::Icosphere { radius, iterations } if self.lod_enabled => (radius, iterations),
+            _ => return Ok(mesh::LodGroup::single(base)),
+        };
+        let mut meshes = vec![base];
+        for level in 1..=Self::LOD_TIERS.min(iterations) {
+            let coarser = MeshType::Icosphere { radius, iterations: iterations - level };
+            meshes.push(coarser.build(&mut self.mesh_cache, device)?);
+        }
+        Ok(mesh::LodGroup::new(meshes, self.lod_thresholds(iterations.min(Self::LOD_TIERS) as usize)))
+    }
+
+    fn lod_thresholds(&self, count: usize) -> Vec<f32> {
+        (1..=count)
+            .map(|tier| self.lod_distance * tier as f32)
+            .collect()
+    }
+
+    fn set_lod_enabled(&mut self, device: &wgpu::Device, enabled: bool) {
+        self.lod_enabled = enabled;
+        // Loaded meshes can't be rebuilt, and never get tiers anyway.
+        if !matches!(self.mesh_type, MeshType::Loaded { .. }) {
+            self.set_mesh_type(device, self.mesh_type);
+        }
+    }
+
+    fn set_lod_distance(&mut self, distance: f32) {
+        self.lod_distance = distance;
+        self.lod_group.thresholds = self.lod_thresholds(self.lod_group.thresholds.len());
+    }
+
+    /// Instances drawn with each LOD tier last frame, finest first.
+    fn lod_counts(&self) -> Vec<u32> {
+        let mut counts = vec![0; self.lod_group.len()];
+        for (tier, range) in &self.lod_draws {
+            counts[*tier] += range.end - range.start;
+        }
+        counts
+    }
+
+    /// Draws `mesh` instead of a generated one, until the mesh type is changed again.
+    fn set_mesh(&mut self, mesh: mesh::Mesh) {
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for vertex in &mesh.vertices {
+            for i in 0..3 {
+                min[i] = min[i].min(vertex.position[i]);
+                max[i] = max[i].max(vertex.position[i]);
+            }
+        }
+        if mesh.vertices.is_empty() {
+            min = [0.0; 3];
+            max = [0.0; 3];
+        }
+        self.lod_group = mesh::LodGroup::single(Arc::new(mesh));
+        self.mesh_cache.evict_unused();
+        self.mesh_type = MeshType::Loaded { min, max };
+        self.lod_morph = None;
+        self.rebuild_octree();
+    }
+
+    fn build_grid(dim: u32, spacing: f32, rng: &mut impl Rng) -> Vec<Instance> {
+        let cells = (0..dim).flat_map(|z| (0..dim).map(move |x| (x, z)));
+        cells.map(|(x, z)| {
+            let offset = (dim - 1) as f32 / 2.0;
+            let x = spacing * (x as f32 - offset);
+            let z = spacing * (z as f32 - offset);
+
+            let position = cgmath::Vector3 { x, y: 0.0, z };
+
+            let rotation = if position.is_zero() {
+                cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0))
+            } else {
+                cgmath::Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(45.0))
+            };
+
+            Instance {
+                transform: Transform {
+                    position,
+                    rotation,
+                    ..Default::default()
+                },
+                color: random_color(rng),
+                dirty: true,
+                path: Some(random_path(position, spacing * 0.5, rng)),
+                path_t: rng.gen(),
+                velocity: cgmath::Vector3::zero(),
+                acceleration: cgmath::Vector3::zero(),
+            }
+        }).collect::<Vec<_>>()
+    }
+
+    fn set_grid(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        dim: u32,
+        spacing: f32,
+        rng: &mut impl Rng,
+    ) {
+        self.grid_dim = dim;
+        self.grid_spacing = spacing;
+        self.set_instances(device, queue, Self::build_grid(dim, spacing, rng));
+    }
+
+    fn set_instances(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mut instances: Vec<Instance>,
+    ) {
+        let instance_data = instances.iter()
+            .map(Instance::to_raw)
+            .collect::<Vec<_>>();
+        for instance in &mut instances {
+            instance.dirty = false;
+        }
+
+        if instances.len() as u32 > self.buffer_capacity {
+            self.instance_buffer = create_instance_buffer(device, &instance_data);
+            self.buffer_capacity = instances.len() as u32;
+            if let Some(cull_pass) = &mut self.cull_pass {
+                cull_pass.resize(device, &self.instance_buffer, self.buffer_capacity);
+            }
+        } else if !instance_data.is_empty() {
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instance_data));
+        }
+
+        self.visible_count = instances.len() as u32;
+        self.overview_count = self.visible_count;
+        self.instances = instances;
+        self.hidden.clear();
+        self.rebuild_octree();
+    }
+
+    /// Bounds that hold the instance at any rotation, and contain its picking sphere.
+    fn instance_aabb(&self, instance: &Instance) -> Aabb {
+        let (center, radius) = self.lod_group.base().bounding_sphere;
+        let extent = (center.magnitude() + radius).max(self.mesh_type.bounding_radius());
+        let scale = instance.transform.scale;
+        let scale = scale.x.abs().max(scale.y.abs()).max(scale.z.abs());
+        Aabb::from_sphere(instance.transform.position, extent * scale)
+    }
+
+    fn rebuild_octree(&mut self) {
+        let entries = self.instances.iter()
+            .enumerate()
+            .map(|(index, instance)| (index, self.instance_aabb(instance)))
+            .collect::<Vec<_>>();
+        self.octree = OctTree::from_entries(&entries, Self::OCTREE_DEPTH);
+    }
+
+    fn hide(&mut self, index: usize) {
+        self.hidden.insert(index);
+    }
+
+    fn show(&mut self, index: usize) {
+        self.hidden.remove(&index);
+    }
+
+    fn update(
+        &mut self,
+        dt: std::time::Duration,
+        uploader: &mut BufferUploader,
+        frustum: &camera::Frustum,
+        view_position: cgmath::Point3<f32>,
+        overview: bool,
+    ) {
+        // Update the instances.
+        let dt = dt.as_secs_f32();
+        if self.rotation_speed != 0.0 {
+            let amount = cgmath::Quaternion::from_angle_y(cgmath::Rad(self.rotation_speed * dt));
+            for instance in &mut self.instances {
+                let current = instance.transform.rotation;
+                instance.transform.rotation = amount * current;
+                instance.dirty = true;
+            }
+        }
+        let loop_paths = self.loop_paths;
+        let mut moved = false;
+        for instance in &mut self.instances {
+            let path = match &instance.path {
+                Some(path) if loop_paths || instance.path_t < 1.0 => path,
+                _ => continue,
+            };
+            instance.path_t += dt / path.duration;
+            if instance.path_t >= 1.0 {
+                instance.path_t = if loop_paths { 0.0 } else { 1.0 };
+            }
+            instance.transform.position = path.evaluate(instance.path_t).to_vec();
+            instance.dirty = true;
+            moved = true;
+        }
+        if self.repulsion > 0.0 && self.apply_repulsion(dt) {
+            moved = true;
+        }
+        if moved {
+            self.rebuild_octree();
+        }
+
+        if self.active_cull_pass().is_some() {
+            // Culling happens in a compute pass before rendering, so the buffer keeps every
+            // instance in order and only the range spanning the dirty ones needs uploading.
+            let first = self.instances.iter().position(|instance| instance.dirty);
+            let last = self.instances.iter().rposition(|instance| instance.dirty);
+            if let (Some(first), Some(last)) = (first, last) {
+                let dirty = &mut self.instances[first..=last];
+                let instance_data = dirty.iter()
+                    .map(Instance::to_raw)
+                    .collect::<Vec<_>>();
+                let offset = (first * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress;
+                uploader.write(&self.instance_buffer, offset, bytemuck::cast_slice(&instance_data));
+                for instance in dirty {
+                    instance.dirty = false;
+                }
+            }
+            let num_instances = self.instances.len() as u32;
+            if let Some(cull_pass) = self.active_cull_pass() {
+                cull_pass.update(
+                    uploader,
+                    frustum,
+                    self.lod_group.base().bounding_sphere,
+                    num_instances,
+                    self.lod_group.base().num_elements,
+                );
+            }
+            self.visible_count = num_instances;
+            self.overview_count = num_instances;
+            self.lod_draws = vec![(0, 0..num_instances)];
+        } else {
+            // Cull the instances, packing the visible ones at the front of the buffer.
+            // The octree narrows them down first, sorted back into instance order.
+            let mut candidates = self.octree.query_frustum(frustum);
+            candidates.sort_unstable();
+            let instances = &self.instances;
+            let mut visible = candidates.into_iter()
+                .filter(|index| !self.hidden.contains(index))
+                .filter(|&index| self.is_visible(&instances[index], frustum))
+                .collect::<Vec<_>>();
+            if self.enable_transparency {
+                // Sort back to front.
+                let view_position = view_position.to_vec();
+                visible.sort_by(|&a, &b| {
+                    let distance_a = (instances[a].transform.position - view_position).magnitude2();
+                    let distance_b = (instances[b].transform.position - view_position).magnitude2();
+                    distance_b.partial_cmp(&distance_a).unwrap_or(std::cmp::Ordering::Equal)
+                });
+            }
+            // Tiers only depend on distance, so they're already contiguous once sorted back to front.
+            let lod_group = &self.lod_group;
+            let tier = |index: usize| {
+                lod_group.tier((instances[index].transform.position - view_position.to_vec()).magnitude())
+            };
+            if !self.enable_transparency && lod_group.len() > 1 {
+                visible.sort_by_key(|&index| tier(index));
+            }
+            self.lod_draws.clear();
+            for (index, &instance) in visible.iter().enumerate() {
+                let (tier, index) = (tier(instance), index as u32);
+                match self.lod_draws.last_mut() {
+                    Some((last, range)) if *last == tier => range.end = index + 1,
+                    _ => self.lod_draws.push((tier, index..index + 1)),
+                }
+            }
+            let mut instance_data = visible.iter()
+                .map(|&index| instances[index].to_raw())
+                .collect::<Vec<_>>();
+            self.visible_count = instance_data.len() as u32;
+            if overview {
+                // The overview camera sees more than the main one, so the instances it
+                // culled go after the visible ones instead of being left out.
+                let mut drawn = vec![false; instances.len()];
+                for &index in &visible {
+                    drawn[index] = true;
+                }
+                instance_data.extend(instances.iter()
+                    .enumerate()
+                    .filter(|(index, _)| !drawn[*index] && !self.hidden.contains(index))
+                    .map(|(_, instance)| instance.to_raw()));
+            }
+            self.overview_count = instance_data.len() as u32;
+            uploader.write(&self.instance_buffer, 0, bytemuck::cast_slice(&instance_data));
+            // The packed buffer no longer matches instance order, so everything
+            // must be uploaded again when switching back to GPU culling.
+            for instance in &mut self.instances {
+                instance.dirty = true;
+            }
+        }
+
+        if let Some(instance) = self.selected_instance.and_then(|index| self.instances.get(index)) {
+            let mut outline = Instance {
+                transform: instance.transform,
+                color: instance.color,
+                dirty: false,
+                path: None,
+                path_t: 0.0,
+                velocity: cgmath::Vector3::zero(),
+                acceleration: cgmath::Vector3::zero(),
+            };
+            outline.transform.scale *= Self::OUTLINE_SCALE;
+            uploader.write(&self.selection_buffer, 0, bytemuck::cast_slice(&[instance.to_raw(), outline.to_raw()]));
+            if let Some((buffer, _)) = &self.outline_uniform {
+                uploader.write(buffer, 0, bytemuck::cast_slice(&self.outline_color));
+            }
+        }
+
+        // Update the uniforms.
+        uploader.write(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+
+        self.morph_uniform.enabled = 0;
+        if let Some(mut lod_morph) = self.lod_morph.take() {
+            lod_morph.elapsed += dt;
+            let t = (lod_morph.elapsed / Self::MORPH_DURATION).min(1.0);
+            if t < 1.0 {
+                self.morph_uniform.blend = if lod_morph.to_fine { t } else { 1.0 - t };
+                self.morph_uniform.enabled = 1;
+                self.lod_morph = Some(lod_morph);
+            } else if !lod_morph.to_fine {
+                self.lod_group = mesh::LodGroup::single(lod_morph.coarse);
+            }
+        }
+        uploader.write(&self.morph_uniform_buffer, 0, bytemuck::cast_slice(&[self.morph_uniform]));
+    }
+
+    fn is_visible(&self, instance: &Instance, frustum: &camera::Frustum) -> bool {
+        let (center, radius) = self.lod_group.base().bounding_sphere;
+        let center = (instance.transform.to_matrix() * center.extend(1.0)).truncate();
+        let scale = instance.transform.scale;
+        let scale = scale.x.abs().max(scale.y.abs()).max(scale.z.abs());
+        frustum.contains_sphere(center, radius * scale)
+    }
+
+    /// Queues a green normal and a cyan tangent line, `normal_scale` long,
+    /// at every vertex of each instance inside `frustum`.
+    fn append_normals(&self, frustum: &camera::Frustum, line_pass: &mut LinePass) {
+        for instance in self.instances.iter().filter(|instance| self.is_visible(instance, frustum)) {
+            let model = instance.transform.to_matrix();
+            let normal_matrix = instance.normal_matrix();
+            for vertex in &self.lod_group.base().vertices {
+                let position = (model * cgmath::Vector3::from(vertex.position).extend(1.0)).truncate();
+                let normal = (normal_matrix * cgmath::Vector3::from(vertex.normal)).normalize();
+                let tangent = (model * cgmath::Vector4::from(vertex.tangent).truncate().extend(0.0)).truncate().normalize();
+                line_pass.push((position, position + normal * self.normal_scale, Color::GREEN.0));
+                line_pass.push((position, position + tangent * self.normal_scale, Color::CYAN.0));
+            }
+        }
+    }
+
+    /// Pushes apart instances closer than two mesh radii, harder the more they overlap.
+    /// Instances following a path push the others but aren't moved themselves.
+    /// Returns whether any instance moved.
+    fn apply_repulsion(&mut self, dt: f32) -> bool {
+        let range = self.radius * 2.0;
+        if self.spatial_hash.cell_size() != range {
+            self.spatial_hash = Grid3D::new(range);
+        }
+        self.spatial_hash.clear();
+        for (index, instance) in self.instances.iter().enumerate() {
+            self.spatial_hash.insert(index, instance.transform.position);
+        }
+
+        // Forces use the positions from before anything moved, so the result doesn't depend on instance order.
+        let accelerations = self.instances.iter().enumerate()
+            .map(|(index, instance)| {
+                let position = instance.transform.position;
+                self.spatial_hash.query_radius(position, range).into_iter()
+                    .filter(|&other| other != index)
+                    .fold(cgmath::Vector3::zero(), |acceleration, other| {
+                        let offset = position - self.instances[other].transform.position;
+                        let distance = offset.magnitude();
+                        // Coincident instances have no direction to separate along.
+                        if distance <= f32::EPSILON {
+                            return acceleration;
+                        }
+                        acceleration + offset / distance * self.repulsion * (1.0 - distance / range)
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        let loop_paths = self.loop_paths;
+        let damping = (-Self::REPULSION_DAMPING * dt).exp();
+        let mut moved = false;
+        for (instance, acceleration) in self.instances.iter_mut().zip(accelerations) {
+            instance.acceleration = acceleration;
+            if instance.path.is_some() && (loop_paths || instance.path_t < 1.0) {
+                continue;
+            }
+            instance.velocity = (instance.velocity + acceleration * dt) * damping;
+            // Let instances come to rest instead of creeping forever.
+            if instance.velocity.magnitude2() < 1e-6 {
+                instance.velocity = cgmath::Vector3::zero();
+                continue;
+            }
+            instance.transform.position += instance.velocity * dt;
+            instance.dirty = true;
+            moved = true;
+        }
+        moved
+    }
+
+    /// Keeps the existing color target contents instead of clearing to
+    /// the background color when `clear_color` is false.
+    fn render(
+        &self, 
+        view: &wgpu::TextureView, 
+        encoder: &mut wgpu::CommandEncoder,
+        depth_texture: &texture::Texture,
         camera_bind_group: &wgpu::BindGroup,
+        spotlight_bind_group: &wgpu::BindGroup,
+        clear_color: bool,
     ) {
+        if let Some(cull_pass) = self.active_cull_pass() {
+            cull_pass.dispatch(encoder, self.instances.len() as u32);
+        }
+
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Wire Render Pass"),
             color_attachments: &[
@@ -370,7 +1609,11 @@ impl WirePass {
                     view: &view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(self.clear_color),
+                        load: if clear_color {
+                            wgpu::LoadOp::Clear(Color(self.background_color).to_wgpu())
+                        } else {
+                            wgpu::LoadOp::Load
+                        },
                         store: true,
                     },
                 }
@@ -381,35 +1624,180 @@ impl WirePass {
                     load: wgpu::LoadOp::Clear(1.0),
                     store: true,
                 }),
-                stencil_ops: None,
+                stencil_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0),
+                    store: true,
+                }),
             }),
         });
         
-        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        use crate::mesh::DrawMesh;
+        render_pass.set_pipeline(&self.render_pipeline);
+        match self.active_cull_pass() {
+            Some(cull_pass) => {
+                render_pass.set_vertex_buffer(1, cull_pass.culled_buffer.slice(..));
+                render_pass.draw_mesh_indirect(
+                    self.lod_group.base(),
+                    &cull_pass.indirect_buffer,
+                    Some(vec![
+                        &camera_bind_group,
+                        &self.uniform_bind_group,
+                        spotlight_bind_group,
+                        &self.morph_bind_group,
+                    ]),
+                );
+            },
+            None => {
+                render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+                let bind_groups = Some(vec![
+                    camera_bind_group,
+                    &self.uniform_bind_group,
+                    spotlight_bind_group,
+                    &self.morph_bind_group,
+                ]);
+                for (tier, range) in &self.lod_draws {
+                    let mesh = &self.lod_group.meshes[*tier];
+                    if self.wireframe {
+                        render_pass.draw_wireframe(mesh, range.clone(), bind_groups.clone());
+                    } else {
+                        render_pass.draw_mesh_instanced(mesh, range.clone(), bind_groups.clone(), None);
+                    }
+                }
+            },
+        }
+
+        let selected = self.selected_instance.is_some_and(|index| {
+            index < self.instances.len() && !self.hidden.contains(&index)
+        });
+        if selected {
+            let (stencil_pipeline, outline_pipeline) = &self.outline_pipelines;
+            let (bind_groups, outline_color) = match &self.outline_uniform {
+                Some((_, outline_bind_group)) => (vec![camera_bind_group, outline_bind_group], None),
+                None => (vec![camera_bind_group], Some(bytemuck::cast_slice(&self.outline_color))),
+            };
+            render_pass.set_vertex_buffer(1, self.selection_buffer.slice(..));
+            render_pass.set_stencil_reference(1);
+            render_pass.set_pipeline(stencil_pipeline);
+            render_pass.draw_mesh_instanced(self.lod_group.base(), 0..1, Some(bind_groups.clone()), outline_color);
+            render_pass.set_pipeline(outline_pipeline);
+            render_pass.draw_mesh_instanced(self.lod_group.base(), 1..2, Some(bind_groups), outline_color);
+        }
+    }
+
+    /// Draws the instances from another camera into `view`, clearing it first. Without GPU culling,
+    /// `update` must have been told about the overview so it uploads the culled instances too.
+    fn render_overview(
+        &self,
+        view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+        depth_texture: &texture::Texture,
+        camera_bind_group: &wgpu::BindGroup,
+        spotlight_bind_group: &wgpu::BindGroup,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Wire Overview Render Pass"),
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(Color(self.background_color).to_wgpu()),
+                        store: true,
+                    },
+                }
+            ],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0),
+                    store: true,
+                }),
+            }),
+        });
 
         use crate::mesh::DrawMesh;
         render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.draw_mesh_instanced(
-            &self.mesh,
-            0..self.instances.len() as u32,
-            Some(vec![
-                &camera_bind_group,
-                &self.uniform_bind_group,
-            ]),
-        );
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        let bind_groups = Some(vec![
+            camera_bind_group,
+            &self.uniform_bind_group,
+            spotlight_bind_group,
+            &self.morph_bind_group,
+        ]);
+        // With GPU culling this is a single draw of every instance. The instances the main
+        // camera culled come last, and are drawn with the coarsest tier as the overview is small.
+        let culled = (self.lod_group.len() - 1, self.visible_count..self.overview_count);
+        for (tier, range) in self.lod_draws.iter().chain(std::iter::once(&culled)) {
+            let mesh = &self.lod_group.meshes[*tier];
+            if self.wireframe {
+                render_pass.draw_wireframe(mesh, range.clone(), bind_groups.clone());
+            } else {
+                render_pass.draw_mesh_instanced(mesh, range.clone(), bind_groups.clone(), None);
+            }
+        }
     }
 }
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DisplaceUniform {
+    time: f32,
+    tone_map_mode: u32,
+    exposure: f32,
+    gamma: f32,
+    vignette_strength: f32,
+    vignette_radius: f32,
+    noise_strength: f32,
+    // Uniforms require 16 byte spacing, so we need padding here
+    _padding: f32,
+}
+
+/// Operator applied in the final composite, values match `tone_map_mode` in `displace.wgsl`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ToneMap {
+    Linear = 0,
+    Reinhard = 1,
+    Aces = 2,
+}
+
 struct DisplacePass {
     texture: texture::Texture,
     layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
+    // Bind groups for the textures given to set_inputs, and the one render samples.
+    input_bind_groups: Vec<wgpu::BindGroup>,
+    input: Option<usize>,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
     mesh: mesh::Mesh,
+    color_format: wgpu::TextureFormat,
+    render_pipeline_layout: wgpu::PipelineLayout,
     render_pipeline: wgpu::RenderPipeline,
+    tone_map: ToneMap,
+    exposure: f32,
+    // Extra gamma on top of the sRGB encoding the render target already applies.
+    gamma: f32,
+    // How much the edges darken, 0 leaves the image unchanged.
+    vignette_strength: f32,
+    // Distance from the center, in uv units, where the darkening starts.
+    vignette_radius: f32,
+    // How far, in uv units, the noise texture pushes the image around. 0 leaves it in place.
+    noise_strength: f32,
+    // Kept so passes made with `chain` sample the same noise.
+    noise: Arc<texture::Texture>,
 }
 
 impl DisplacePass {
-    fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+    /// `noise` is a tiling texture, e.g. `NoiseComputePass::output`, that wobbles the image.
+    fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        noise: Arc<texture::Texture>,
+    ) -> Self {
         let texture = texture::Texture::create_render_texture(device, config, "Displace Texture");
 
         let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -434,17 +1822,68 @@ impl DisplacePass {
             ],
         });
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Displace Bind Group"),
-            layout: &layout,
+        let bind_group = Self::create_bind_group(device, &layout, &texture);
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Displace Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[DisplaceUniform {
+                time: 0.0,
+                tone_map_mode: ToneMap::Linear as u32,
+                exposure: 1.0,
+                gamma: 1.0,
+                vignette_strength: 0.0,
+                vignette_radius: 0.0,
+                noise_strength: 0.0,
+                _padding: 0.0,
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Displace Uniform Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    count: None,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    count: None,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                },
+            ],
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Displace Uniform Bind Group"),
+            layout: &uniform_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                    resource: uniform_buffer.as_entire_binding(),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                    resource: wgpu::BindingResource::TextureView(&noise.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&noise.sampler),
                 },
             ],
         });
@@ -456,149 +1895,792 @@ impl DisplacePass {
             true,
         ).unwrap();
 
-        let render_pipeline = {
-            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Displace Pipeline Layout"),
-                bind_group_layouts: &[
-                    &layout,
-                ],
-                push_constant_ranges: &[],
-            });
-            let shader = wgpu::ShaderModuleDescriptor {
-                label: Some("Displace Shader"),
-                source: wgpu::ShaderSource::Wgsl(include_str!("displace.wgsl").into()),
-            };
-            create_render_pipeline(
-                "Displace Render Pipeline",
-                &device,
+        let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Displace Pipeline Layout"),
+            bind_group_layouts: &[
                 &layout,
-                config.format,
-                None,
-                &[mesh::MeshVertex::desc()],
-                shader,
-            )
-        };
+                &uniform_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let render_pipeline = Self::create_pipeline(
+            device,
+            &render_pipeline_layout,
+            config.format,
+            include_str!("displace.wgsl"),
+        );
 
         Self {
             texture,
             layout,
             bind_group,
+            input_bind_groups: Vec::new(),
+            input: None,
+            uniform_buffer,
+            uniform_bind_group,
             mesh,
+            color_format: config.format,
+            render_pipeline_layout,
             render_pipeline,
+            tone_map: ToneMap::Linear,
+            exposure: 1.0,
+            gamma: 1.0,
+            vignette_strength: 0.3,
+            vignette_radius: 0.7,
+            noise_strength: 0.0,
+            noise,
         }
     }
 
+    /// Creates a pass that reads from `previous.texture`. To run the two in sequence, render
+    /// `previous` into its own texture first. Call `set_input` again after resizing `previous`.
+    fn chain(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        previous: &DisplacePass,
+    ) -> Self {
+        let mut pass = Self::new(device, config, previous.noise.clone());
+        pass.set_input(device, &previous.texture);
+        pass
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        color_format: wgpu::TextureFormat,
+        shader_source: &str,
+    ) -> wgpu::RenderPipeline {
+        let shader = wgpu::ShaderModuleDescriptor {
+            label: Some("Displace Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        };
+        PipelineBuilder::new("Displace Render Pipeline")
+            .color_format(color_format)
+            .vertex_layouts(&[mesh::MeshVertex::desc()])
+            .shader(shader)
+            .build(device, layout)
+    }
+
+    /// Rebuilds the pipeline from new shader source, keeping the current one if it fails to compile.
+    fn reload_shader(&mut self, device: &wgpu::Device, shader_source: &str) -> anyhow::Result<()> {
+        self.render_pipeline = shader_watcher::validated(device, || Self::create_pipeline(
+            device,
+            &self.render_pipeline_layout,
+            self.color_format,
+            shader_source,
+        ))?;
+        Ok(())
+    }
+
+    fn update(&self, queue: &wgpu::Queue, timer: &Timer) {
+        let uniform = DisplaceUniform {
+            time: timer.elapsed_secs,
+            tone_map_mode: self.tone_map as u32,
+            exposure: self.exposure,
+            gamma: self.gamma,
+            vignette_strength: self.vignette_strength,
+            vignette_radius: self.vignette_radius,
+            noise_strength: self.noise_strength,
+            _padding: 0.0,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
     fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
         self.texture = texture::Texture::create_render_texture(device, config, "Displace Texture");
-        self.bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        self.bind_group = Self::create_bind_group(device, &self.layout, &self.texture);
+    }
+
+    /// Samples from `texture` instead of the pass's own render target.
+    /// Call again whenever `texture` is recreated.
+    fn set_input(&mut self, device: &wgpu::Device, texture: &texture::Texture) {
+        self.set_inputs(device, &[texture]);
+        self.select_input(Some(0));
+    }
+
+    /// Creates a bind group for each of `textures`, for `select_input` to choose from.
+    /// Call again whenever they are recreated.
+    fn set_inputs(&mut self, device: &wgpu::Device, textures: &[&texture::Texture]) {
+        self.input_bind_groups = textures.iter()
+            .map(|texture| Self::create_bind_group(device, &self.layout, texture))
+            .collect();
+    }
+
+    /// Samples from the texture at `index` in the last `set_inputs`,
+    /// or from the pass's own render target with None.
+    fn select_input(&mut self, index: Option<usize>) {
+        self.input = index;
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        texture: &texture::Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Displace Bind Group"),
-            layout: &self.layout,
+            layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&self.texture.view),
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&self.texture.sampler),
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
                 },
             ],
+        })
+    }
+
+    fn render(
+        &self, 
+        view: &wgpu::TextureView, 
+        encoder: &mut wgpu::CommandEncoder
+    ) {
+        let bind_group = match self.input {
+            Some(index) => &self.input_bind_groups[index],
+            None => &self.bind_group,
+        };
+        self.draw(view, encoder, bind_group);
+    }
+
+    fn draw(
+        &self,
+        view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_group: &wgpu::BindGroup,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Displace Render Pass"),
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }
+            ],
+            depth_stencil_attachment: None,
         });
+
+        use crate::mesh::DrawMesh;
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.draw_mesh(
+            &self.mesh,
+            Some(vec![bind_group, &self.uniform_bind_group]),
+        );
     }
+}
+
+impl PostProcess for DisplacePass {
+    fn create_bind_group(&self, device: &wgpu::Device, input: &texture::Texture) -> wgpu::BindGroup {
+        Self::create_bind_group(device, &self.layout, input)
+    }
+
+    fn apply(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::BindGroup,
+        output: &texture::Texture,
+    ) {
+        self.draw(&output.view, encoder, input);
+    }
+
+    fn update(&self, queue: &wgpu::Queue, timer: &Timer) {
+        DisplacePass::update(self, queue, timer);
+    }
+}
+
+// Seeds the random instance colors, paths, terrain and particles when rendering headless.
+const HEADLESS_SEED: u64 = 0;
+// Edge length in texels of the generated skybox faces.
+const SKYBOX_FACE_SIZE: u32 = 64;
+// Size of each staging belt chunk, larger uploads get a chunk of their own.
+const STAGING_BELT_CHUNK_SIZE: wgpu::BufferAddress = 1 << 20;
+// Labels for the passes measured by the GPU timer, in the order they're indexed in State::render.
+const GPU_TIMER_PASSES: [&str; 4] = ["wire", "debug", "grid", "post process"];
+
+struct State {
+    // None when rendering headless.
+    surface: Option<wgpu::Surface>,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    adapter_info: wgpu::AdapterInfo,
+    config: wgpu::SurfaceConfiguration,
+    size: winit::dpi::PhysicalSize<u32>,
+    depth_texture: texture::Texture,
+    depth_bind_group: wgpu::BindGroup,
+    // Staging memory for per-frame buffer uploads, see `update`.
+    staging_belt: wgpu::util::StagingBelt,
+    // Pending `StagingBelt::recall` futures, completed once the GPU is done with their chunks.
+    staging_recalls: Vec<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    camera: Camera,
+    // light_uniform: LightUniform,
+    // light_buffer: wgpu::Buffer,
+    // light_bind_group: wgpu::BindGroup,
+    // light_render_pipeline: wgpu::RenderPipeline,
+    skybox_pass: SkyboxPass,
+    // Draws the skybox behind the scene instead of clearing to the background color.
+    show_skybox: bool,
+    wire_pass: WirePass,
+    grid_pass: GridPass,
+    terrain: Terrain,
+    debug_pass: DebugPass,
+    frustum_pass: FrustumPass,
+    line_pass: LinePass,
+    billboard_pass: BillboardPass,
+    // None without compute shaders.
+    particle_pass: Option<ParticlePass>,
+    water_pass: WaterPass,
+    spotlight: Spotlight,
+    // Point the spotlight stays aimed at as its position is edited.
+    spotlight_target: cgmath::Point3<f32>,
+    spotlight_buffer: wgpu::Buffer,
+    spotlight_bind_group: wgpu::BindGroup,
+    // Shadows cast along the spotlight's direction, bound with it.
+    shadow_pass: CascadedShadowMapPass,
+    // Shade the instances with point lights from a G-buffer instead of the wire pass.
+    deferred_shading: bool,
+    gbuffer_pass: GBufferPass,
+    deferred_lighting_pass: DeferredLightingPass,
+    displace_pass: DisplacePass,
+    // Reads what `displace_pass` rendered into its own texture, displacing the image a second time.
+    chained_displace_pass: Option<DisplacePass>,
+    noise_pass: NoiseComputePass,
+    post_process: PostProcessStack,
+    motion_blur_pass: MotionBlurPass,
+    minimap: Minimap,
+    gpu_timer: Option<GpuTimer>,
+    custom_passes: Vec<Box<dyn RenderPass>>,
+    screenshot_requested: bool,
+    // Composited into for screenshots and GIF frames, kept until the window is resized.
+    screenshot_texture: Option<texture::Texture>,
+    // GIF frames still being copied back, oldest first.
+    gif_readbacks: VecDeque<screenshot::Readback>,
+    // Frames captured for a GIF until F10 is pressed again, see `start_gif_recording`.
+    gif_recording: Option<screenshot::GifRecording>,
+    gif_frame_requested: bool,
+    // Threads encoding stopped recordings, joined before exiting so no GIF is cut short.
+    gif_encoders: Vec<std::thread::JoinHandle<()>>,
+    timer: Timer,
+    // Everything random in the scene draws from this, so a fixed seed reproduces the scene.
+    rng: StdRng,
+    frame_history: FrameHistory,
+    // Mean frame time measured with each present mode that has been used.
+    present_mode_frame_times: std::collections::HashMap<wgpu::PresentMode, f32>,
+    gui_cpu_usage: Option<f32>,
+    // Draws each instance's index over it.
+    show_instance_labels: bool,
+    // Labels each instance with its position, in the scene rather than the GUI.
+    show_position_labels: bool,
+    wire_shader_watcher: ShaderWatcher,
+    displace_shader_watcher: ShaderWatcher,
+    shader_status: Option<String>,
+    camera_path: camera::CameraPath,
+    // Playback time along camera_path, None when not playing.
+    camera_path_time: Option<f32>,
+    mouse_pressed: bool,
+    cursor_position: winit::dpi::PhysicalPosition<f64>,
+    selected_instance: usize,
+    // Dropped on the window and waiting to be loaded once a frame has shown it's loading.
+    dropped_file: Option<std::path::PathBuf>,
+    // Last image dropped on the window, kept alive for the GUI.
+    dropped_texture: Option<texture::Texture>,
+    // Size of the offscreen targets relative to the surface.
+    resolution_scale: f32,
+    // Set when the offscreen targets change, so the GUI can pick up the new ones.
+    render_targets_recreated: bool,
+}
+
+impl epi::App for State {
+    fn name(&self) -> &str {
+        "easy"
+    }
+
+    fn update(&mut self, ctx: &egui::CtxRef, frame: &epi::Frame) {
+        self.gui_cpu_usage = frame.lock().info.cpu_usage;
+        egui::Window::new(self.name())
+            //.frame(egui::containers::Frame::dark_canvas(&ctx.style()))
+            .show(ctx, |ui| self.ui(ui));
+
+        if self.show_instance_labels {
+            let screen_size = cgmath::Vector2::new(self.config.width as f32, self.config.height as f32)
+                / ctx.pixels_per_point();
+            for (index, instance) in self.wire_pass.instances.iter().enumerate() {
+                let position = cgmath::Point3::from_vec(instance.transform.position);
+                Gui::draw_world_label(ctx, position, &index.to_string(), &self.camera, screen_size);
+            }
+        }
+
+        // The camera moves after the GUI runs, so the labels face last frame's camera.
+        let position_labels = if self.show_position_labels {
+            self.wire_pass.instances.iter()
+                .map(|instance| {
+                    let position = instance.transform.position;
+                    let label = format!("{:.1}, {:.1}, {:.1}", position.x, position.y, position.z);
+                    (cgmath::Point3::from_vec(position), label)
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        self.billboard_pass.set_labels(&self.device, &self.queue, ctx, &self.camera, self.config.height, position_labels);
+
+        if let Some(recording) = &self.gif_recording {
+            egui::Window::new("Recording")
+                .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-10.0, 10.0))
+                .title_bar(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.colored_label(
+                        egui::Color32::RED,
+                        format!("● REC {:.1} s, {} frames (F10 to stop)", recording.elapsed(), recording.frame_count()),
+                    );
+                });
+        }
+
+        if let (true, Some(texture_id)) = (self.minimap.visible, self.minimap.texture_id) {
+            egui::Window::new("Minimap")
+                .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -10.0))
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.image(texture_id, [Minimap::SIZE as f32, Minimap::SIZE as f32]);
+                });
+        }
+    }
+}
+
+impl State {
+    async fn new(window: &Window, app_config: &Config) -> anyhow::Result<Self> {
+        let size = window.inner_size();
+
+        // The instance is a handle to our GPU
+        // Backends::all => Vulkan + Metal + DX12 + Browser WGPU
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let surface = unsafe { instance.create_surface(window) };
+        let adapter = Self::request_adapter(&instance, Some(&surface), app_config.power_preference).await?;
+        let (device, queue) = Self::request_device(&adapter, app_config.compat).await?;
+
+        let config = Self::create_surface_config(&surface, &adapter, size);
+        surface.configure(&device, &config);
+
+        Ok(Self::with_device(Some(surface), &adapter, device, queue, config, AppSettings::load(), StdRng::from_entropy()))
+    }
+
+    /// Creates a state without a window, rendering into textures of the given size.
+    /// Saved settings are ignored and the random seed is fixed, so every run renders the same frame.
+    async fn new_headless(width: u32, height: u32, app_config: &Config) -> anyhow::Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = Self::request_adapter(&instance, None, app_config.power_preference).await?;
+        let (device, queue) = Self::request_device(&adapter, app_config.compat).await?;
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: texture::Texture::RENDER_FORMAT,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+
+        Ok(Self::with_device(None, &adapter, device, queue, config, AppSettings::default(), StdRng::seed_from_u64(HEADLESS_SEED)))
+    }
+
+    fn with_device(
+        surface: Option<wgpu::Surface>,
+        adapter: &wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        settings: AppSettings,
+        mut rng: StdRng,
+    ) -> Self {
+        let size = winit::dpi::PhysicalSize::new(config.width, config.height);
+
+        let (depth_texture, depth_bind_group, _depth_bind_group_layout) = Self::create_depth_resources(&device, &config);
+
+        let mut camera = Self::create_camera_resources(&device, &config);
+
+        // let light_uniform = LightUniform {
+        //     position: [2.0, 2.0, 2.0],
+        //     _padding: 0,
+        //     color: [1.0, 1.0, 1.0],
+        // };
+
+        // let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        //     label: Some("Light VB"),
+        //     contents: bytemuck::cast_slice(&[light_uniform]),
+        //     usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        // });
+
+        // let light_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        //     label:None,
+        //     entries: &[wgpu::BindGroupLayoutEntry {
+        //         binding: 0,
+        //         visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+        //         ty: wgpu::BindingType::Buffer {
+        //             ty: wgpu::BufferBindingType::Uniform,
+        //             has_dynamic_offset: false,
+        //             min_binding_size: None,
+        //         },
+        //         count: None,
+        //     }],
+        // });
+        // let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        //     label: None,
+        //     layout: &light_bind_group_layout,
+        //     entries: &[wgpu::BindGroupEntry {
+        //         binding: 0,
+        //         resource: light_buffer.as_entire_binding(),
+        //     }],
+        // });
+
+        // Cull instances on the GPU when compute shaders are available.
+        let compute_shaders = Self::audit_downlevel(adapter, &device);
+        let gpu_culling = compute_shaders;
+
+        if let Some(camera_state) = &settings.camera {
+            camera.load_state(camera_state, &queue);
+        }
+
+        // Off until its intensity is raised from the GUI.
+        let spotlight_target = cgmath::Point3::new(0.0, 0.0, 0.0);
+        let spotlight = Spotlight::new(
+            cgmath::Point3::new(0.0, 6.0, 6.0),
+            spotlight_target,
+            cgmath::Deg(25.0),
+            [1.0, 1.0, 1.0],
+            0.0,
+        );
+        let spotlight_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Spotlight Buffer"),
+            contents: bytemuck::cast_slice(&[spotlight]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let shadow_pass = CascadedShadowMapPass::new(&device, shadow::MAX_CASCADES as u32);
+        let spotlight_bind_group_layout = Spotlight::create_bind_group_layout(&device);
+        let [shadow_uniform, shadow_maps, shadow_sampler] = shadow_pass.bind_group_entries();
+        let spotlight_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Spotlight Bind Group"),
+            layout: &spotlight_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: spotlight_buffer.as_entire_binding(),
+                },
+                shadow_uniform,
+                shadow_maps,
+                shadow_sampler,
+            ],
+        });
+
+        let sky_faces = skybox::gradient_faces(
+            SKYBOX_FACE_SIZE,
+            Color::from_hex(0x2f5f9f),
+            Color::from_hex(0xb8cde0),
+            Color::from_hex(0x3a3a40),
+        );
+        let skybox_pass = SkyboxPass::new(&device, &queue, &sky_faces, &config, &camera.layout).unwrap();
+
+        let wire_pass = WirePass::new(
+            &device, 
+            &queue,
+            &config,
+            &camera.layout,
+            &spotlight_bind_group_layout,
+            gpu_culling,
+            &settings,
+            &mut rng,
+        );
+
+        let terrain = Terrain::new(
+            &device,
+            &config,
+            &camera.layout,
+            16,
+            32,
+            1.0,
+            &mut rng,
+        );
+
+        let grid_pass = GridPass::new(
+            &device,
+            &config,
+            &camera.layout,
+        );
+
+        let debug_pass = DebugPass::new(
+            &device,
+            &config,
+            &camera.layout,
+        );
+
+        let frustum_pass = FrustumPass::new(
+            &device,
+            &config,
+            &camera.layout,
+        );
 
-    fn render(
-        &self, 
-        view: &wgpu::TextureView, 
-        encoder: &mut wgpu::CommandEncoder
-    ) {
-        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("Displace Render Pass"),
-            color_attachments: &[
-                wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Load,
-                        store: true,
-                    },
+        let line_pass = LinePass::new(
+            &device,
+            &config,
+            &camera.layout,
+        );
+
+        let billboard_pass = BillboardPass::new(
+            &device,
+            &config,
+            &camera.layout,
+            &depth_texture,
+        );
+
+        // Particles are simulated in a compute shader, there is no CPU path.
+        let particle_pass = if compute_shaders {
+            Some(ParticlePass::new(
+                &device,
+                &config,
+                &camera.layout,
+                4096,
+                rng.gen(),
+            ))
+        } else {
+            None
+        };
+
+        let gbuffer_pass = GBufferPass::new(
+            &device,
+            &config,
+            &camera.layout,
+        );
+        let mut deferred_lighting_pass = DeferredLightingPass::new(
+            &device,
+            &config,
+            &gbuffer_pass,
+            &depth_texture,
+        );
+        // Spread the lights in a ring above the instances.
+        let light_colors = [Color::RED, Color::GREEN, Color::BLUE, Color::YELLOW, Color::CYAN, Color::WHITE];
+        deferred_lighting_pass.lights = (0..deferred::MAX_LIGHTS)
+            .map(|i| {
+                let angle = i as f32 / deferred::MAX_LIGHTS as f32 * std::f32::consts::TAU;
+                PointLight {
+                    position: cgmath::Vector3::new(angle.cos() * 8.0, 2.0, angle.sin() * 8.0),
+                    color: light_colors[i % light_colors.len()],
+                    intensity: 1.0,
+                    radius: 10.0,
                 }
-            ],
-            depth_stencil_attachment: None,
-        });
+            })
+            .collect();
 
-        use crate::mesh::DrawMesh;
-        render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.draw_mesh(
-            &self.mesh,
-            Some(vec![&self.bind_group]),
+        let noise_pass = NoiseComputePass::new(&device, 512, NoiseParams::default(), compute_shaders);
+        let mut displace_pass = DisplacePass::new(
+            &device,
+            &config,
+            noise_pass.output.clone(),
         );
-    }
-}
 
-struct State {
-    surface: wgpu::Surface,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
-    config: wgpu::SurfaceConfiguration,
-    size: winit::dpi::PhysicalSize<u32>,
-    depth_texture: texture::Texture,
-    depth_bind_group: wgpu::BindGroup,
-    camera: Camera,
-    // light_uniform: LightUniform,
-    // light_buffer: wgpu::Buffer,
-    // light_bind_group: wgpu::BindGroup,
-    // light_render_pipeline: wgpu::RenderPipeline,
-    wire_pass: WirePass,
-    displace_pass: DisplacePass,
-    mouse_pressed: bool,
-}
+        let post_process = PostProcessStack::new(
+            &device,
+            &config,
+        );
+        let mut motion_blur_pass = MotionBlurPass::new(&device, &config);
+        Self::bind_composite_inputs(&device, &post_process, &mut motion_blur_pass, &mut displace_pass);
+        let mut minimap = Minimap::new(&device, &config);
+        minimap.fit_grid(&queue, wire_pass.grid_dim, wire_pass.grid_spacing);
 
-impl epi::App for State {
-    fn name(&self) -> &str {
-        "easy"
-    }
+        let water_pass = WaterPass::new(
+            &device,
+            &queue,
+            &config,
+            &camera.layout,
+            &mut rng,
+        );
 
-    fn update(&mut self, ctx: &egui::CtxRef, _frame: &epi::Frame) {
-        egui::Window::new(self.name())
-            //.frame(egui::containers::Frame::dark_canvas(&ctx.style()))
-            .show(ctx, |ui| self.ui(ui));
-    }
-}
+        let gpu_timer = Self::create_gpu_timer(&device, &queue);
 
-impl State {
-    async fn new(window: &Window) -> Self {
-        let size = window.inner_size();
+        // let light_render_pipeline = {
+        //     let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        //         label: Some("Light Pipeline Layout"),
+        //         bind_group_layouts: &[&camera_bind_group_layout, &light_bind_group_layout],
+        //         push_constant_ranges: &[],
+        //     });
+        //     let shader = wgpu::ShaderModuleDescriptor {
+        //         label: Some("Light Shader"),
+        //         source: wgpu::ShaderSource::Wgsl(include_str!("light.wgsl").into()),
+        //     };
+        //     create_render_pipeline(
+        //         &device,
+        //         &layout,
+        //         config.format,
+        //         Some(texture::Texture::DEPTH_FORMAT),
+        //         &[model::ModelVertex::desc()],
+        //         shader,
+        //     )
+        // };
 
-        // The instance is a handle to our GPU
-        // Backends::all => Vulkan + Metal + DX12 + Browser WGPU
-        let instance = wgpu::Instance::new(wgpu::Backends::all());
-        let surface = unsafe { instance.create_surface(window) };
-        let adapter = instance.request_adapter(
+        Self {
+            surface,
+            device,
+            queue,
+            adapter_info: adapter.get_info(),
+            config,
+            size,
+            depth_texture,
+            depth_bind_group,
+            staging_belt: wgpu::util::StagingBelt::new(STAGING_BELT_CHUNK_SIZE),
+            staging_recalls: Vec::new(),
+            camera,
+            // light_uniform,
+            // light_buffer,
+            // light_bind_group,
+            // light_render_pipeline,
+            skybox_pass,
+            show_skybox: false,
+            wire_pass,
+            grid_pass,
+            terrain,
+            debug_pass,
+            frustum_pass,
+            line_pass,
+            billboard_pass,
+            particle_pass,
+            water_pass,
+            spotlight,
+            spotlight_target,
+            spotlight_buffer,
+            spotlight_bind_group,
+            shadow_pass,
+            deferred_shading: false,
+            gbuffer_pass,
+            deferred_lighting_pass,
+            displace_pass,
+            chained_displace_pass: None,
+            noise_pass,
+            post_process,
+            motion_blur_pass,
+            minimap,
+            gpu_timer,
+            custom_passes: Vec::new(),
+            screenshot_requested: false,
+            screenshot_texture: None,
+            gif_readbacks: VecDeque::new(),
+            gif_recording: None,
+            gif_frame_requested: false,
+            gif_encoders: Vec::new(),
+            timer: Timer::default(),
+            rng,
+            frame_history: FrameHistory::new(128),
+            present_mode_frame_times: std::collections::HashMap::new(),
+            gui_cpu_usage: None,
+            show_instance_labels: false,
+            show_position_labels: false,
+            wire_shader_watcher: ShaderWatcher::new(concat!(env!("CARGO_MANIFEST_DIR"), "/src/wire.wgsl")),
+            displace_shader_watcher: ShaderWatcher::new(concat!(env!("CARGO_MANIFEST_DIR"), "/src/displace.wgsl")),
+            shader_status: None,
+            camera_path: camera::CameraPath::default(),
+            camera_path_time: None,
+            mouse_pressed: false,
+            cursor_position: winit::dpi::PhysicalPosition::new(0.0, 0.0),
+            selected_instance: 0,
+            dropped_file: None,
+            dropped_texture: None,
+            resolution_scale: 1.0,
+            render_targets_recreated: false,
+        }
+    }
+
+    async fn request_adapter(
+        instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface>,
+        power_preference: wgpu::PowerPreference,
+    ) -> anyhow::Result<wgpu::Adapter> {
+        instance.request_adapter(
             &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
+                power_preference,
+                compatible_surface: surface,
                 force_fallback_adapter: false,
             },
-        ).await.unwrap();
+        ).await.ok_or_else(|| anyhow::anyhow!("No suitable graphics adapter found"))
+    }
+
+    /// With `compat`, asks for no optional features and no more than WebGL2 allows,
+    /// so the fallback paths run even on a desktop adapter.
+    async fn request_device(adapter: &wgpu::Adapter, compat: bool) -> anyhow::Result<(wgpu::Device, wgpu::Queue)> {
+        let (features, limits) = if compat {
+            let limits = wgpu::Limits::downlevel_webgl2_defaults()
+                .using_resolution(adapter.limits());
+            (wgpu::Features::empty(), limits)
+        } else {
+            // Only request optional features the adapter actually supports.
+            let features = adapter.features()
+                & (wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::PUSH_CONSTANTS);
+            let limits = wgpu::Limits {
+                // The default is 0, 128 bytes is the most every backend guarantees.
+                max_push_constant_size: adapter.limits().max_push_constant_size.min(128),
+                ..wgpu::Limits::default()
+            };
+            (features, limits)
+        };
 
         let (device, queue) = adapter.request_device(
             &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
-                limits: wgpu::Limits::default(),
+                features,
+                limits,
                 label: None,
             },
             None, 
-        ).await.unwrap();
+        ).await?;
+        Ok((device, queue))
+    }
 
-        let config = wgpu::SurfaceConfiguration {
+    /// Whether compute shaders can run, they need storage buffers as well in this app.
+    /// Logs what else the device is missing that some pass falls back from.
+    fn audit_downlevel(adapter: &wgpu::Adapter, device: &wgpu::Device) -> bool {
+        let flags = adapter.get_downlevel_properties().flags;
+        let limits = device.limits();
+        let compute_shaders = flags.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+            && limits.max_compute_workgroups_per_dimension > 0
+            && limits.max_storage_buffers_per_shader_stage > 0
+            && limits.max_storage_textures_per_shader_stage > 0;
+        if !compute_shaders {
+            log::warn!("No compute shaders: culling on the CPU, noise on the CPU, no particles");
+        }
+        if !flags.contains(wgpu::DownlevelFlags::INDIRECT_EXECUTION) {
+            log::warn!("No indirect draws");
+        }
+        if !flags.contains(wgpu::DownlevelFlags::BASE_VERTEX) {
+            log::warn!("No base vertex, instance offsets may be ignored");
+        }
+        if !device.features().contains(wgpu::Features::PUSH_CONSTANTS) {
+            log::warn!("No push constants: the selection outline color goes through a uniform buffer");
+        }
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            log::warn!("No timestamp queries: GPU timings are disabled");
+        }
+        compute_shaders
+    }
+
+    fn create_surface_config(
+        surface: &wgpu::Surface,
+        adapter: &wgpu::Adapter,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> wgpu::SurfaceConfiguration {
+        wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface.get_preferred_format(&adapter).unwrap(),
+            format: surface.get_preferred_format(adapter).unwrap(),
             width: size.width,
             height: size.height,
             present_mode: wgpu::PresentMode::Fifo,
-        };
-        surface.configure(&device, &config);
+        }
+    }
 
+    fn create_depth_resources(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> (texture::Texture, wgpu::BindGroup, wgpu::BindGroupLayout) {
         let depth_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Depth Bind Group Layout"),
             entries: &[
@@ -641,7 +2723,12 @@ impl State {
             ],
         });
 
-        let depth_texture = texture::Texture::create_depth_texture(&device, &config, "depth_texture"); 
+        let depth_texture = texture::Texture::create_depth_texture(device, config, "depth_texture"); 
+        // Bindings can only sample one aspect of a depth stencil texture.
+        let depth_view = depth_texture.texture.create_view(&wgpu::TextureViewDescriptor {
+            aspect: wgpu::TextureAspect::DepthOnly,
+            ..Default::default()
+        });
 
         let depth_bind_group = device.create_bind_group(
             &wgpu::BindGroupDescriptor {
@@ -650,7 +2737,7 @@ impl State {
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+                        resource: wgpu::BindingResource::TextureView(&depth_view),
                     },
                     wgpu::BindGroupEntry {
                         binding: 1,
@@ -660,95 +2747,38 @@ impl State {
             }
         );  
 
-        let camera = Camera::new(
-            &device,
+        (depth_texture, depth_bind_group, depth_bind_group_layout)
+    }
+
+    fn create_camera_resources(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> Camera {
+        Camera::new(
+            device,
             (0.0, 0.0, 5.0),
             cgmath::Deg(-90.0), cgmath::Deg(0.0),
             config.width, config.height, cgmath::Deg(45.0), 0.1, 100.0,
-        );
-
-        // let light_uniform = LightUniform {
-        //     position: [2.0, 2.0, 2.0],
-        //     _padding: 0,
-        //     color: [1.0, 1.0, 1.0],
-        // };
-
-        // let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        //     label: Some("Light VB"),
-        //     contents: bytemuck::cast_slice(&[light_uniform]),
-        //     usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        // });
-
-        // let light_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        //     label:None,
-        //     entries: &[wgpu::BindGroupLayoutEntry {
-        //         binding: 0,
-        //         visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-        //         ty: wgpu::BindingType::Buffer {
-        //             ty: wgpu::BufferBindingType::Uniform,
-        //             has_dynamic_offset: false,
-        //             min_binding_size: None,
-        //         },
-        //         count: None,
-        //     }],
-        // });
-        // let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        //     label: None,
-        //     layout: &light_bind_group_layout,
-        //     entries: &[wgpu::BindGroupEntry {
-        //         binding: 0,
-        //         resource: light_buffer.as_entire_binding(),
-        //     }],
-        // });
-
-        let wire_pass = WirePass::new(
-            &device, 
-            &config,
-            &camera.layout
-        );
-
-        let displace_pass = DisplacePass::new(
-            &device,
-            &config,
-        );
+        )
+    }
 
-        // let light_render_pipeline = {
-        //     let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        //         label: Some("Light Pipeline Layout"),
-        //         bind_group_layouts: &[&camera_bind_group_layout, &light_bind_group_layout],
-        //         push_constant_ranges: &[],
-        //     });
-        //     let shader = wgpu::ShaderModuleDescriptor {
-        //         label: Some("Light Shader"),
-        //         source: wgpu::ShaderSource::Wgsl(include_str!("light.wgsl").into()),
-        //     };
-        //     create_render_pipeline(
-        //         &device,
-        //         &layout,
-        //         config.format,
-        //         Some(texture::Texture::DEPTH_FORMAT),
-        //         &[model::ModelVertex::desc()],
-        //         shader,
-        //     )
-        // };
+    fn create_gpu_timer(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<GpuTimer> {
+        if device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            Some(GpuTimer::new(device, queue, &GPU_TIMER_PASSES))
+        } else {
+            None
+        }
+    }
 
-        Self {
-            surface,
-            device,
-            queue,
-            config,
-            size,
-            depth_texture,
-            depth_bind_group,
-            camera,
-            // light_uniform,
-            // light_buffer,
-            // light_bind_group,
-            // light_render_pipeline,
-            wire_pass,
-            displace_pass,
-            mouse_pressed: false,
+    /// Reconfigures the surface with `present_mode`.
+    /// Modes the surface doesn't support fall back to `Fifo` with a warning from wgpu.
+    fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        self.config.present_mode = present_mode;
+        if let Some(surface) = &self.surface {
+            surface.configure(&self.device, &self.config);
         }
+        // Start measuring the new mode from scratch.
+        self.frame_history.clear();
     }
 
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -756,14 +2786,73 @@ impl State {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.config);
+            }
 
             self.camera.projection.resize(new_size.width, new_size.height);
 
-            self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
-        
-            self.displace_pass.resize(&self.device, &self.config);
+            // Every frame of a GIF has to be the same size.
+            if self.gif_recording.is_some() {
+                self.stop_gif_recording();
+            }
+            self.screenshot_texture = None;
+            self.recreate_render_targets();
+        }
+    }
+
+    /// Switches `window` between borderless fullscreen on its current monitor and windowed.
+    fn toggle_fullscreen(&mut self, window: &Window) {
+        if window.fullscreen().is_none() {
+            window.set_fullscreen(Some(winit::window::Fullscreen::Borderless(None)));
+        } else {
+            window.set_fullscreen(None);
+        }
+        self.resize(window.inner_size());
+    }
+
+    /// Renders the scene at `scale` times the surface size, upscaled by the final composite.
+    /// Values above 1.0 supersample.
+    fn set_resolution_scale(&mut self, scale: f32) {
+        self.resolution_scale = scale;
+        self.recreate_render_targets();
+    }
+
+    /// Recreates the offscreen targets at the surface size times `resolution_scale`.
+    fn recreate_render_targets(&mut self) {
+        let mut config = self.config.clone();
+        config.width = ((config.width as f32 * self.resolution_scale) as u32).max(1);
+        config.height = ((config.height as f32 * self.resolution_scale) as u32).max(1);
+
+        self.depth_texture = texture::Texture::create_depth_texture(&self.device, &config, "depth_texture");
+        self.gbuffer_pass.resize(&self.device, &config);
+        self.deferred_lighting_pass.resize(&self.device, &self.gbuffer_pass, &self.depth_texture);
+
+        self.displace_pass.resize(&self.device, &config);
+        if let Some(chained) = &mut self.chained_displace_pass {
+            chained.resize(&self.device, &config);
+            chained.set_input(&self.device, &self.displace_pass.texture);
         }
+        self.post_process.resize(&self.device, &config);
+        self.motion_blur_pass.resize(&self.device, &config);
+        Self::bind_composite_inputs(&self.device, &self.post_process, &mut self.motion_blur_pass, &mut self.displace_pass);
+        self.water_pass.resize(&self.device, &config);
+        self.billboard_pass.resize(&self.device, &self.depth_texture);
+        self.render_targets_recreated = true;
+    }
+
+    /// Points the motion blur pass at the post-processing result, and the displace pass at
+    /// that result and both motion blur histories. Call after any of them are resized.
+    fn bind_composite_inputs(
+        device: &wgpu::Device,
+        post_process: &PostProcessStack,
+        motion_blur_pass: &mut MotionBlurPass,
+        displace_pass: &mut DisplacePass,
+    ) {
+        let result = post_process.output();
+        motion_blur_pass.set_input(device, result);
+        let [history_0, history_1] = motion_blur_pass.history();
+        displace_pass.set_inputs(device, &[result, history_0, history_1]);
     }
 
     fn input(&mut self, event: &DeviceEvent) -> bool {
@@ -776,22 +2865,47 @@ impl State {
                 }
             ) => {
                 match key {
-                    // VirtualKeyCode::Numpad0 | VirtualKeyCode::Key0 => {
-                    //     self.wire_pass.remesh(&self.device, 1.0, 0);
-                    //     true
-                    // }
-                    // VirtualKeyCode::Numpad1 | VirtualKeyCode::Key1 => {
-                    //     self.wire_pass.remesh(&self.device, 1.0, 1);
-                    //     true
-                    // }
-                    // VirtualKeyCode::Numpad2 | VirtualKeyCode::Key2 => {
-                    //     self.wire_pass.remesh(&self.device, 1.0, 2);
-                    //     true
-                    // }
-                    // VirtualKeyCode::Numpad3 | VirtualKeyCode::Key3 => {
-                    //     self.wire_pass.remesh(&self.device, 1.0, 3);
-                    //     true
-                    // }
+                    VirtualKeyCode::Numpad0 | VirtualKeyCode::Key0 if *state == ElementState::Pressed => {
+                        self.wire_pass.remesh_with(&self.device, 1.0, 0);
+                        true
+                    }
+                    VirtualKeyCode::Numpad1 | VirtualKeyCode::Key1 if *state == ElementState::Pressed => {
+                        self.wire_pass.remesh_with(&self.device, 1.0, 1);
+                        true
+                    }
+                    VirtualKeyCode::Numpad2 | VirtualKeyCode::Key2 if *state == ElementState::Pressed => {
+                        self.wire_pass.remesh_with(&self.device, 1.0, 2);
+                        true
+                    }
+                    VirtualKeyCode::Numpad3 | VirtualKeyCode::Key3 if *state == ElementState::Pressed => {
+                        self.wire_pass.remesh_with(&self.device, 1.0, 3);
+                        true
+                    }
+                    VirtualKeyCode::F5 if *state == ElementState::Pressed => {
+                        if let Err(e) = self.settings().save() {
+                            log::error!("Failed to save settings: {:?}", e);
+                        }
+                        true
+                    }
+                    VirtualKeyCode::F9 if *state == ElementState::Pressed => {
+                        if let Some(camera_state) = AppSettings::load().camera {
+                            self.camera.load_state(&camera_state, &self.queue);
+                        }
+                        true
+                    }
+                    VirtualKeyCode::F10 if *state == ElementState::Pressed => {
+                        if self.gif_recording.is_some() {
+                            self.stop_gif_recording();
+                        } else if let Ok(timestamp) = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+                            let path = std::path::PathBuf::from(format!("recording-{}.gif", timestamp.as_secs()));
+                            self.start_gif_recording(path, 15);
+                        }
+                        true
+                    }
+                    VirtualKeyCode::F12 if *state == ElementState::Pressed => {
+                        self.screenshot_requested = true;
+                        true
+                    }
                     _ => self.camera.controller.process_keyboard(*key, *state),
                 }
             },
@@ -807,6 +2921,27 @@ impl State {
                 state,
             } => {
                 self.mouse_pressed = *state == ElementState::Pressed;
+                if self.mouse_pressed {
+                    let picked = self.pick_instance();
+                    if let Some(index) = picked {
+                        self.selected_instance = index;
+                    }
+                    self.wire_pass.selected_instance = picked;
+                }
+                true
+            }
+            DeviceEvent::Button {
+                button: 3, // Right
+                state: ElementState::Pressed,
+            } => {
+                // Hidden instances can still be picked, so they can be shown again.
+                if let Some(index) = self.pick_instance() {
+                    if self.wire_pass.hidden.contains(&index) {
+                        self.wire_pass.show(index);
+                    } else {
+                        self.wire_pass.hide(index);
+                    }
+                }
                 true
             }
             DeviceEvent::MouseMotion { 
@@ -821,12 +2956,165 @@ impl State {
         }
     }
 
+    /// Returns the index of the nearest instance under the cursor, using bounding spheres
+    /// of the instances whose octree bounds the ray passes through.
+    fn pick_instance(&self) -> Option<usize> {
+        let inv_view_proj = self.camera.view_proj().invert()?;
+        let mouse_ndc = cgmath::Vector2::new(
+            (2.0 * self.cursor_position.x / self.config.width as f64 - 1.0) as f32,
+            (1.0 - 2.0 * self.cursor_position.y / self.config.height as f64) as f32,
+        );
+        let ray = camera::Ray::from_screen(mouse_ndc, &inv_view_proj);
+        let mesh_radius = self.wire_pass.mesh_type.bounding_radius();
+        self.wire_pass.octree.query_ray(&ray)
+            .into_iter()
+            .filter_map(|index| {
+                let instance = &self.wire_pass.instances[index];
+                let scale = instance.transform.scale;
+                let radius = mesh_radius * scale.x.abs().max(scale.y.abs()).max(scale.z.abs());
+                let center = cgmath::Point3::from_vec(instance.transform.position);
+                ray.intersect_sphere(center, radius).map(|t| (index, t))
+            })
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(index, _)| index)
+    }
+
     fn ui(&mut self, ui: &mut Ui) {
+        let frame_time = self.frame_history.last();
+        ui.label(format!(
+            "fps: {:.1} ({:.2} ms)",
+            if frame_time > 0.0 { 1.0 / frame_time } else { 0.0 },
+            frame_time * 1000.0,
+        ));
+        ui.label(format!(
+            "min: {:.2} ms | max: {:.2} ms | mean: {:.2} ms",
+            self.frame_history.min() * 1000.0,
+            self.frame_history.max() * 1000.0,
+            self.frame_history.mean() * 1000.0,
+        ));
+        let frame_times = self.frame_history.times()
+            .map(|t| t * 1000.0)
+            .collect::<Vec<_>>();
+        egui::plot::Plot::new("frame times")
+            .height(48.0)
+            .show_axes([false, true])
+            .allow_drag(false)
+            .allow_zoom(false)
+            .include_y(0.0)
+            .show(ui, |plot_ui| {
+                plot_ui.line(egui::plot::Line::new(egui::plot::Values::from_ys_f32(&frame_times)));
+            });
+        if let Some(cpu_usage) = self.gui_cpu_usage {
+            ui.label(format!("gui cpu: {:.2} ms", cpu_usage * 1000.0));
+        }
+        self.present_mode_frame_times.insert(self.config.present_mode, self.frame_history.mean());
+        let present_modes = [
+            (wgpu::PresentMode::Fifo, "fifo (vsync)"),
+            (wgpu::PresentMode::Mailbox, "mailbox"),
+            (wgpu::PresentMode::Immediate, "immediate (uncapped)"),
+        ];
+        let mut present_mode = self.config.present_mode;
+        ui.horizontal(|ui| {
+            for (mode, name) in present_modes {
+                ui.radio_value(&mut present_mode, mode, name);
+            }
+        });
+        if present_mode != self.config.present_mode {
+            self.set_present_mode(present_mode);
+        }
+        // wgpu can't tell which modes the surface supports, and silently uses Fifo for the others,
+        // so the times below are only known to belong to the mode that was asked for.
+        if self.config.present_mode != wgpu::PresentMode::Fifo {
+            ui.label("unsupported modes fall back to fifo, with a warning in the console");
+        }
+        ui.label("mean frame time by requested mode:");
+        for (mode, name) in present_modes {
+            if let Some(frame_time) = self.present_mode_frame_times.get(&mode) {
+                ui.label(format!("{}: {:.2} ms", name, frame_time * 1000.0));
+            }
+        }
+        let mut resolution_scale = self.resolution_scale;
+        egui::ComboBox::from_label("resolution scale")
+            .selected_text(format!("{:.0}%", resolution_scale * 100.0))
+            .show_ui(ui, |ui| {
+                for scale in [0.25, 0.5, 0.75, 1.0, 1.5, 2.0] {
+                    ui.selectable_value(&mut resolution_scale, scale, format!("{:.0}%", scale * 100.0));
+                }
+            });
+        if resolution_scale != self.resolution_scale {
+            self.set_resolution_scale(resolution_scale);
+        }
+        if let Some(shader_status) = &self.shader_status {
+            ui.label(shader_status);
+        }
+        if let Some(path) = &self.dropped_file {
+            ui.label(format!("Loading {}…", path.file_name().unwrap_or_default().to_string_lossy()));
+        }
+        ui.separator();
+        let radius = self.wire_pass.radius;
+        let iterations = self.wire_pass.iterations;
+        let mesh_types = [
+            MeshType::Icosphere { radius, iterations },
+            MeshType::Cube { size: radius * 2.0 },
+            MeshType::Plane { width: radius * 2.0, height: radius * 2.0, subdivisions: 1 << iterations },
+        ];
+        egui::ComboBox::from_label("mesh")
+            .selected_text(self.wire_pass.mesh_type.name())
+            .show_ui(ui, |ui| {
+                for mt in mesh_types {
+                    let selected = mt.name() == self.wire_pass.mesh_type.name();
+                    if ui.selectable_label(selected, mt.name()).clicked() && !selected {
+                        self.wire_pass.set_mesh_type(&self.device, mt);
+                    }
+                }
+            });
+        // Random cells, best seen with the heatmap on.
+        if ui.button("voronoi").clicked() {
+            let size = radius * 2.0;
+            let sites = (0..32)
+                .map(|_| cgmath::Vector2::new(
+                    (self.rng.gen::<f32>() - 0.5) * size,
+                    (self.rng.gen::<f32>() - 0.5) * size,
+                ))
+                .collect::<Vec<_>>();
+            match mesh::Mesh::voronoi_2d(&self.device, &sites, size, size, false) {
+                Ok(mesh) => self.wire_pass.set_mesh(mesh),
+                Err(e) => log::error!("Failed to build the Voronoi mesh: {}", e),
+            }
+        }
         if ui.add(Slider::new(&mut self.wire_pass.radius, 0.0..=5.0).text("radius")).changed() {
             self.wire_pass.remesh(&self.device);
         }
+        let previous_iterations = self.wire_pass.iterations;
         if ui.add(Slider::new(&mut self.wire_pass.iterations, 0..=4).text("iterations")).changed() {
-            self.wire_pass.remesh(&self.device);
+            self.wire_pass.remesh_iterations(&self.device, &self.queue, previous_iterations);
+        }
+        let num_triangles = self.wire_pass.lod_group.base().num_triangles();
+        let num_instances = self.wire_pass.instances.len() as u32;
+        ui.label(format!(
+            "Vertices: {} | Triangles: {} | Instances: {}",
+            self.wire_pass.lod_group.base().num_vertices,
+            num_triangles,
+            num_instances,
+        ));
+        ui.label(format!("Total triangles: {}", num_triangles * num_instances));
+        ui.label(format!("Mesh cache: {:.1} MiB", self.wire_pass.mesh_cache.used_bytes() as f64 / (1 << 20) as f64));
+        ui.horizontal(|ui| {
+            let mut lod_enabled = self.wire_pass.lod_enabled;
+            if ui.add(Checkbox::new(&mut lod_enabled, "lod")).changed() {
+                self.wire_pass.set_lod_enabled(&self.device, lod_enabled);
+            }
+            let mut lod_distance = self.wire_pass.lod_distance;
+            if ui.add(Slider::new(&mut lod_distance, 1.0..=50.0).text("lod distance")).changed() {
+                self.wire_pass.set_lod_distance(lod_distance);
+            }
+        });
+        if self.wire_pass.lod_group.len() > 1 {
+            let counts = self.wire_pass.lod_counts().iter()
+                .enumerate()
+                .map(|(tier, count)| format!("{}: {}", tier, count))
+                .collect::<Vec<_>>();
+            ui.label(format!("LOD instances: {}", counts.join(" | ")));
         }
         ui.add(Slider::new(&mut self.wire_pass.uniform.smoothing, 0.0..=5.0).text("smoothing"));
         ui.add(Slider::new(&mut self.wire_pass.uniform.thickness, 0.0..=64.0).text("thickness"));
@@ -834,11 +3122,504 @@ impl State {
         if ui.add(Checkbox::new(&mut invert_flag, "invert edges")).changed() {
             self.wire_pass.uniform.invert_edges = if invert_flag { 1 } else { 0 };
         }
-        ui.add(Slider::new(&mut self.wire_pass.rotation_speed, (-1.0 * std::f32::consts::PI / 60.0)..=(std::f32::consts::PI / 60.0)).text("speed"));
+        let mut face_colors = self.wire_pass.uniform.face_colors > 0;
+        if ui.add(Checkbox::new(&mut face_colors, "face colors")).changed() {
+            self.wire_pass.uniform.face_colors = face_colors as u32;
+        }
+        let mut heatmap = self.wire_pass.uniform.heatmap > 0;
+        if ui.add(Checkbox::new(&mut heatmap, "heatmap")).changed() {
+            self.wire_pass.uniform.heatmap = heatmap as u32;
+        }
+        let mut wireframe = self.wire_pass.wireframe;
+        if ui.add(Checkbox::new(&mut wireframe, "wireframe")).changed() {
+            self.wire_pass.set_wireframe(&self.device, wireframe);
+        }
+        let mut transparency = self.wire_pass.enable_transparency;
+        if ui.add(Checkbox::new(&mut transparency, "transparency")).changed() {
+            self.wire_pass.set_transparency(&self.device, transparency);
+        }
+        ui.add(Checkbox::new(&mut self.debug_pass.enabled, "bounding boxes"));
+        ui.horizontal(|ui| {
+            let toggled = ui.add(Checkbox::new(&mut self.frustum_pass.enabled, "camera frustum")).changed();
+            let capture = ui.button("capture").clicked();
+            if (toggled && self.frustum_pass.enabled) || capture {
+                self.frustum_pass.capture(&self.device, &self.camera.frustum());
+            }
+        });
+        ui.add(Checkbox::new(&mut self.deferred_shading, format!("deferred shading ({} lights)", self.deferred_lighting_pass.lights.len())));
+        ui.add(Checkbox::new(&mut self.show_instance_labels, "instance labels"));
+        ui.add(Checkbox::new(&mut self.show_position_labels, "position labels"));
+        ui.add(Checkbox::new(&mut self.minimap.visible, "minimap"));
+        ui.horizontal(|ui| {
+            ui.add(Checkbox::new(&mut self.wire_pass.show_normals, "normals"));
+            ui.add_enabled(
+                self.wire_pass.show_normals,
+                Slider::new(&mut self.wire_pass.normal_scale, 0.01..=1.0).text("length"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.color_edit_button_rgba_unmultiplied(&mut self.wire_pass.background_color);
+            ui.label("background");
+            ui.checkbox(&mut self.show_skybox, "skybox");
+        });
+        ui.horizontal(|ui| {
+            ui.color_edit_button_rgba_unmultiplied(&mut self.wire_pass.outline_color);
+            ui.label("selection outline");
+        });
+        ui.add(Slider::new(&mut self.wire_pass.rotation_speed, (-1.0 * std::f32::consts::PI)..=std::f32::consts::PI).text("speed"));
+        ui.add(Checkbox::new(&mut self.wire_pass.loop_paths, "loop paths"));
+        ui.add(Slider::new(&mut self.wire_pass.repulsion, 0.0..=20.0).text("repulsion"));
+        ui.separator();
+        let mut grid_dim = self.wire_pass.grid_dim;
+        let mut grid_spacing = self.wire_pass.grid_spacing;
+        let grid_dim_changed = ui.add(Slider::new(&mut grid_dim, 1..=20).text("grid size")).changed();
+        let grid_spacing_changed = ui.add(Slider::new(&mut grid_spacing, 1.0..=10.0).text("grid spacing")).changed();
+        if grid_dim_changed || grid_spacing_changed {
+            self.wire_pass.set_grid(&self.device, &self.queue, grid_dim, grid_spacing, &mut self.rng);
+            self.minimap.fit_grid(&self.queue, grid_dim, grid_spacing);
+        }
+        ui.horizontal(|ui| {
+            ui.label(format!("instances: {}", self.wire_pass.instances.len()));
+            if ui.button("+").clicked() {
+                let mut instances = std::mem::take(&mut self.wire_pass.instances);
+                instances.push(Instance {
+                    transform: Transform {
+                        position: cgmath::Vector3::new(
+                            self.rng.gen::<f32>() * 10.0 - 5.0,
+                            self.rng.gen::<f32>() * 10.0 - 5.0,
+                            self.rng.gen::<f32>() * -10.0,
+                        ),
+                        ..Default::default()
+                    },
+                    color: random_color(&mut self.rng),
+                    dirty: true,
+                    path: None,
+                    path_t: 0.0,
+                    velocity: cgmath::Vector3::zero(),
+                    acceleration: cgmath::Vector3::zero(),
+                });
+                self.wire_pass.set_instances(&self.device, &self.queue, instances);
+            }
+            if ui.button("-").clicked() {
+                let mut instances = std::mem::take(&mut self.wire_pass.instances);
+                instances.pop();
+                self.wire_pass.set_instances(&self.device, &self.queue, instances);
+            }
+        });
+        let last_instance = self.wire_pass.instances.len().saturating_sub(1);
+        if ui.add(Slider::new(&mut self.selected_instance, 0..=last_instance).text("instance")).changed() {
+            self.wire_pass.selected_instance = Some(self.selected_instance);
+        }
+        if let Some(instance) = self.wire_pass.instances.get_mut(self.selected_instance) {
+            let mut color: [f32; 4] = instance.color.into();
+            ui.horizontal(|ui| {
+                if ui.color_edit_button_rgba_unmultiplied(&mut color).changed() {
+                    instance.color = color.into();
+                    instance.dirty = true;
+                }
+                ui.label("color");
+            });
+            let (yaw, pitch, roll) = camera::quaternion_to_euler(instance.transform.rotation);
+            let mut angles = [yaw, pitch, roll].map(|angle| angle.to_degrees());
+            let changed = ui.horizontal(|ui| {
+                let yaw = ui.add(egui::DragValue::new(&mut angles[0]).suffix("°").prefix("yaw: ")).changed();
+                let pitch = ui.add(egui::DragValue::new(&mut angles[1]).clamp_range(-90.0..=90.0).suffix("°").prefix("pitch: ")).changed();
+                let roll = ui.add(egui::DragValue::new(&mut angles[2]).suffix("°").prefix("roll: ")).changed();
+                yaw || pitch || roll
+            }).inner;
+            if changed {
+                let [yaw, pitch, roll] = angles.map(|angle| angle.to_radians());
+                instance.transform.rotation = camera::euler_to_quaternion(yaw, pitch, roll);
+                instance.dirty = true;
+            }
+        }
+        ui.separator();
+        egui::CollapsingHeader::new("Camera").show(ui, |ui| {
+            let position = self.camera.eye.position;
+            ui.label(format!("position: ({:.3}, {:.3}, {:.3})", position.x, position.y, position.z));
+            ui.label(format!("yaw: {:.3} rad", self.camera.eye.yaw().0));
+            ui.label(format!("pitch: {:.3} rad", self.camera.eye.pitch().0));
+            let mut fov = cgmath::Deg::from(self.camera.projection.fov_y()).0;
+            let mut z_near = self.camera.projection.z_near();
+            let mut z_far = self.camera.projection.z_far();
+            if ui.add(Slider::new(&mut fov, 10.0..=120.0).text("fov")).changed() {
+                self.camera.projection.set_fov(cgmath::Deg(fov));
+                self.camera.update_uniform(&self.queue);
+            }
+            let z_near_changed = ui.add(Slider::new(&mut z_near, 0.01..=10.0).logarithmic(true).text("near")).changed();
+            let z_far_changed = ui.add(Slider::new(&mut z_far, 10.0..=1000.0).logarithmic(true).text("far")).changed();
+            if z_near_changed || z_far_changed {
+                self.camera.projection.set_near_far(z_near, z_far);
+                self.camera.update_uniform(&self.queue);
+            }
+            let mut speed = self.camera.controller.speed();
+            if ui.add(Slider::new(&mut speed, camera::Controller::MIN_SPEED..=camera::Controller::MAX_SPEED).logarithmic(true).text("speed")).changed() {
+                self.camera.controller.set_speed(speed);
+            }
+            let mut sensitivity = self.camera.controller.sensitivity();
+            if ui.add(Slider::new(&mut sensitivity, camera::Controller::MIN_SENSITIVITY..=camera::Controller::MAX_SENSITIVITY).text("sensitivity")).changed() {
+                self.camera.controller.set_sensitivity(sensitivity);
+            }
+            ui.horizontal(|ui| {
+                ui.label(format!("keyframes: {}", self.camera_path.len()));
+                if ui.button("Add Keyframe").clicked() {
+                    // Keyframes are recorded one second apart.
+                    let time = if self.camera_path.is_empty() { 0.0 } else { self.camera_path.duration() + 1.0 };
+                    self.camera_path.add_keyframe(time, self.camera.eye.position, self.camera.eye.rotation());
+                }
+                if ui.button("Clear").clicked() {
+                    self.camera_path.clear();
+                    self.camera_path_time = None;
+                }
+            });
+            match self.camera_path_time {
+                Some(t) => {
+                    ui.label(format!("playing: {:.1} / {:.1} s", t, self.camera_path.duration()));
+                    if ui.button("Stop").clicked() {
+                        self.camera_path_time = None;
+                    }
+                }
+                None => {
+                    if ui.add_enabled(self.camera_path.len() > 1, egui::Button::new("Play")).clicked() {
+                        self.camera_path_time = Some(0.0);
+                    }
+                }
+            }
+        });
+        egui::CollapsingHeader::new("About").show(ui, |ui| {
+            ui.label(format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
+            ui.label(format!("adapter: {}", self.adapter_info.name));
+            ui.label(format!("backend: {:?}", self.adapter_info.backend));
+            ui.label(format!("device type: {:?}", self.adapter_info.device_type));
+            ui.label(format!("vendor: {:#06x} | device: {:#06x}", self.adapter_info.vendor, self.adapter_info.device));
+        });
+        ui.separator();
+        match &self.gpu_timer {
+            Some(timer) => {
+                for (label, ms) in timer.results() {
+                    ui.label(format!("{}: {:.3} ms", label, ms));
+                }
+            },
+            None => {
+                ui.label("gpu timing unsupported");
+            },
+        }
+        ui.separator();
+        ui.horizontal(|ui| {
+            let terrain_label = format!("terrain ({} patches)", self.terrain.num_patches());
+            ui.add(Checkbox::new(&mut self.terrain.visible, terrain_label));
+            ui.add(Slider::new(&mut self.terrain.view_distance, 10.0..=200.0).text("view distance"));
+        });
+        ui.add(Checkbox::new(&mut self.grid_pass.visible, "ground grid"));
+        let mut grid_extent = self.grid_pass.extent();
+        let mut grid_cell_size = self.grid_pass.cell_size();
+        let grid_extent_changed = ui.add(Slider::new(&mut grid_extent, 1.0..=200.0).text("ground extent")).changed();
+        let grid_cell_size_changed = ui.add(Slider::new(&mut grid_cell_size, 0.1..=10.0).text("ground cell size")).changed();
+        if grid_extent_changed || grid_cell_size_changed {
+            self.grid_pass.set_size(&self.device, &self.queue, grid_extent, grid_cell_size);
+        }
+        ui.separator();
+        if let Some(particle_pass) = &mut self.particle_pass {
+            let particles_label = format!("particles ({})", particle_pass.capacity());
+            ui.add(Checkbox::new(&mut particle_pass.visible, particles_label));
+            ui.horizontal(|ui| {
+                ui.label("emitter");
+                ui.add(egui::DragValue::new(&mut particle_pass.emitter_pos.x).speed(0.1).prefix("x: "));
+                ui.add(egui::DragValue::new(&mut particle_pass.emitter_pos.y).speed(0.1).prefix("y: "));
+                ui.add(egui::DragValue::new(&mut particle_pass.emitter_pos.z).speed(0.1).prefix("z: "));
+            });
+            ui.add(Slider::new(&mut particle_pass.lifetime, 0.1..=10.0).text("particle lifetime"));
+            ui.add(Slider::new(&mut particle_pass.speed, 0.0..=20.0).text("particle speed"));
+            let max_rate = particle_pass.capacity() as f32;
+            ui.add(Slider::new(&mut particle_pass.rate, 0.0..=max_rate).text("particle rate"));
+            if ui.button("burst").clicked() {
+                let spawned = particle_pass.burst(&self.queue, 256);
+                if spawned < 256 {
+                    log::info!("Particle ring is full, burst spawned {} of 256", spawned);
+                }
+            }
+        } else {
+            ui.label("particles need compute shaders");
+        }
+        ui.horizontal(|ui| {
+            ui.color_edit_button_rgb(&mut self.spotlight.color);
+            ui.add(Slider::new(&mut self.spotlight.intensity, 0.0..=5.0).text("spotlight"));
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut self.spotlight.position[0]).speed(0.1).prefix("x: "));
+            ui.add(egui::DragValue::new(&mut self.spotlight.position[1]).speed(0.1).prefix("y: "));
+            ui.add(egui::DragValue::new(&mut self.spotlight.position[2]).speed(0.1).prefix("z: "));
+            ui.label("position");
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut self.spotlight_target.x).speed(0.1).prefix("x: "));
+            ui.add(egui::DragValue::new(&mut self.spotlight_target.y).speed(0.1).prefix("y: "));
+            ui.add(egui::DragValue::new(&mut self.spotlight_target.z).speed(0.1).prefix("z: "));
+            ui.label("target");
+        });
+        let mut spot_angle = self.spotlight.angle_cos.acos().to_degrees();
+        if ui.add(Slider::new(&mut spot_angle, 1.0..=89.0).text("spotlight angle")).changed() {
+            self.spotlight.angle_cos = spot_angle.to_radians().cos();
+        }
+        ui.horizontal(|ui| {
+            let label = format!("shadows ({} cascades)", self.shadow_pass.num_cascades());
+            ui.add(Checkbox::new(&mut self.shadow_pass.enabled, label));
+            ui.add_enabled(
+                self.shadow_pass.enabled,
+                Slider::new(&mut self.shadow_pass.split_lambda, 0.0..=1.0).text("split"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.add(Checkbox::new(&mut self.water_pass.visible, "water"));
+            ui.add_enabled(
+                self.water_pass.visible,
+                Slider::new(&mut self.water_pass.height, -10.0..=10.0).text("height"),
+            );
+        });
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("tone map");
+            ui.radio_value(&mut self.displace_pass.tone_map, ToneMap::Linear, "linear");
+            ui.radio_value(&mut self.displace_pass.tone_map, ToneMap::Reinhard, "reinhard");
+            ui.radio_value(&mut self.displace_pass.tone_map, ToneMap::Aces, "aces");
+        });
+        ui.add(Slider::new(&mut self.displace_pass.exposure, 0.1..=8.0).logarithmic(true).text("exposure"));
+        ui.add(Slider::new(&mut self.displace_pass.gamma, 0.5..=3.0).text("gamma"));
+        ui.add(Slider::new(&mut self.displace_pass.vignette_strength, 0.0..=1.0).text("vignette strength"));
+        ui.add(Slider::new(&mut self.displace_pass.vignette_radius, 0.0..=1.0).text("vignette radius"));
+        ui.add(Slider::new(&mut self.displace_pass.noise_strength, 0.0..=0.1).text("noise"));
+        let mut chained = self.chained_displace_pass.is_some();
+        if ui.add(Checkbox::new(&mut chained, "displace twice")).changed() {
+            self.chained_displace_pass = chained.then(|| {
+                let mut pass = DisplacePass::chain(&self.device, &self.config, &self.displace_pass);
+                // The first pass already darkened the edges.
+                pass.vignette_strength = 0.0;
+                pass
+            });
+        }
+        if let Some(chained) = &mut self.chained_displace_pass {
+            ui.add(Slider::new(&mut chained.noise_strength, 0.0..=0.1).text("second noise"));
+        }
+        let mut haze = !self.post_process.is_empty();
+        if ui.add(Checkbox::new(&mut haze, "heat haze")).changed() {
+            if haze {
+                let mut pass = DisplacePass::new(&self.device, &self.config, self.noise_pass.output.clone());
+                // Only wobble the image, the final composite does the rest.
+                pass.vignette_strength = 0.0;
+                pass.noise_strength = 0.02;
+                self.post_process.push(&self.device, Box::new(pass));
+            } else {
+                self.post_process.pop();
+            }
+            Self::bind_composite_inputs(&self.device, &self.post_process, &mut self.motion_blur_pass, &mut self.displace_pass);
+        }
+        ui.add(Slider::new(&mut self.motion_blur_pass.strength, 0.0..=0.95).text("motion blur"));
+        ui.add_enabled(
+            self.motion_blur_pass.strength > 0.0,
+            Slider::new(&mut self.motion_blur_pass.decay, 0.01..=1.0).logarithmic(true).text("motion blur decay"),
+        );
+        ui.label(format!("Motion blur weight: {:.2}", self.motion_blur_pass.blend_factor()));
+        let mut noise_params = self.noise_pass.params();
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut noise_params.frequency).speed(0.1).clamp_range(1.0..=64.0).prefix("frequency: "));
+            ui.add(egui::DragValue::new(&mut noise_params.octaves).clamp_range(1..=8).prefix("octaves: "));
+            ui.add(egui::DragValue::new(&mut noise_params.seed).prefix("seed: "));
+        });
+        self.noise_pass.set_params(&self.queue, noise_params);
+    }
+
+    /// Adds a pass drawn into the scene every frame, after the built-in passes.
+    pub fn add_pass(&mut self, pass: Box<dyn RenderPass>) {
+        self.custom_passes.push(pass);
+    }
+
+    /// Loads the dropped file based on its extension: OBJ files replace the
+    /// wire pass mesh, images are shown in the GUI.
+    fn load_dropped_file(&mut self, gui: &mut Gui) {
+        let path = match self.dropped_file.take() {
+            Some(path) => path,
+            None => return,
+        };
+        let extension = path.extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+        match extension.as_deref() {
+            Some("obj") => match mesh::Mesh::from_obj(&self.device, &path, 60.0) {
+                Ok(mesh) => {
+                    self.wire_pass.set_mesh(mesh);
+                    log::info!("Loaded mesh {}", path.display());
+                },
+                Err(e) => log::error!("Failed to load {}: {}", path.display(), e),
+            },
+            Some("png") | Some("jpg") | Some("jpeg") => match texture::Texture::load(&self.device, &self.queue, &path, false) {
+                Ok(texture) => {
+                    let label = path.file_name().unwrap_or_default().to_string_lossy();
+                    gui.add_debug_texture(&self.device, &self.queue, &texture, &label);
+                    self.dropped_texture = Some(texture);
+                    log::info!("Loaded texture {}", path.display());
+                },
+                Err(e) => log::error!("Failed to load {}: {}", path.display(), e),
+            },
+            _ => log::warn!("Unsupported file format: {}", path.display()),
+        }
+    }
+
+    /// Shows the intermediate render targets in the GUI. Must be called again
+    /// whenever `recreate_render_targets` runs.
+    fn register_debug_textures(&mut self, gui: &mut Gui) {
+        gui.add_debug_texture(&self.device, &self.queue, self.post_process.input(), "scene");
+        self.minimap.texture_id = Some(gui.add_debug_texture(&self.device, &self.queue, &self.minimap.render_texture, "minimap"));
+        gui.add_debug_texture(&self.device, &self.queue, &self.gbuffer_pass.albedo, "g-buffer albedo");
+        gui.add_debug_texture(&self.device, &self.queue, &self.gbuffer_pass.world_normal, "g-buffer normal");
+    }
+
+    /// Rebuilds the wire and displace pipelines when their shader files change on disk.
+    fn reload_shaders(&mut self) {
+        if let Some(source) = self.wire_shader_watcher.poll() {
+            let result = self.wire_pass.reload_shader(&self.device, source);
+            self.report_shader_reload(self.wire_shader_watcher.file_name(), result);
+        }
+        if let Some(source) = self.displace_shader_watcher.poll() {
+            let result = self.displace_pass.reload_shader(&self.device, &source);
+            self.report_shader_reload(self.displace_shader_watcher.file_name(), result);
+        }
+    }
+
+    fn report_shader_reload(&mut self, file_name: String, result: anyhow::Result<()>) {
+        let status = match result {
+            Ok(()) => {
+                log::info!("Shader reloaded: {}", file_name);
+                format!("Shader reloaded: {}", file_name)
+            },
+            Err(e) => {
+                log::error!("Failed to reload {}: {}", file_name, e);
+                format!("Shader reload failed: {}", file_name)
+            },
+        };
+        self.shader_status = Some(status);
+    }
+
+    fn settings(&self) -> AppSettings {
+        AppSettings {
+            radius: self.wire_pass.radius,
+            iterations: self.wire_pass.iterations,
+            rotation_speed: self.wire_pass.rotation_speed,
+            background_color: self.wire_pass.background_color,
+            camera: Some(self.camera.save_state()),
+        }
+    }
+
+    /// Saves the screenshot `render` composited after F12, then adds GIF frames once the GPU
+    /// has finished copying them back, usually a frame or two later. Call after submitting the frame.
+    fn save_screenshot(&mut self) {
+        if std::mem::take(&mut self.screenshot_requested) {
+            if let Some(screenshot_texture) = &self.screenshot_texture {
+                let (width, height) = (self.config.width, self.config.height);
+                let result = screenshot::capture_screenshot(&self.device, &self.queue, &screenshot_texture.texture, &self.config)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|pixels| screenshot::save_screenshot(&pixels, width, height));
+                match result {
+                    Ok(path) => log::info!("Saved screenshot to {}", path),
+                    Err(e) => log::error!("Failed to save screenshot: {:?}", e),
+                }
+            }
+        }
+
+        for readback in &mut self.gif_readbacks {
+            readback.map();
+        }
+        while let Some(result) = self.gif_readbacks.front_mut().and_then(screenshot::Readback::try_read) {
+            self.gif_readbacks.pop_front();
+            let pixels = match result {
+                Ok(pixels) => pixels,
+                Err(e) => {
+                    log::error!("Failed to read back GIF frame: {:?}", e);
+                    continue;
+                }
+            };
+            let full = self.gif_recording.as_mut().is_some_and(|recording| !recording.push(pixels));
+            if full {
+                self.gif_readbacks.clear();
+                self.stop_gif_recording();
+            }
+        }
+    }
+
+    /// Captures the composited frame `fps` times a second, at the window size, until `stop_gif_recording`
+    /// or until `GifRecording::MAX_DURATION` or `GifRecording::MAX_BYTES` is reached.
+    fn start_gif_recording(&mut self, path: std::path::PathBuf, fps: u32) {
+        if self.gif_recording.is_some() {
+            self.stop_gif_recording();
+        }
+        log::info!("Recording {}", path.display());
+        self.gif_recording = Some(screenshot::GifRecording::new(path, fps, self.config.width, self.config.height));
+    }
+
+    /// Encodes and saves the recording on another thread, so the window doesn't stall.
+    fn stop_gif_recording(&mut self) {
+        self.gif_frame_requested = false;
+        let mut recording = match self.gif_recording.take() {
+            Some(recording) => recording,
+            None => return,
+        };
+        // Waits for the frames still being copied back, there are only ever a few.
+        for readback in self.gif_readbacks.drain(..) {
+            match readback.read(&self.device) {
+                Ok(pixels) => if !recording.push(pixels) {
+                    break;
+                },
+                Err(e) => log::error!("Failed to read back GIF frame: {:?}", e),
+            }
+        }
+        log::info!("Encoding {} frames to {}", recording.frame_count(), recording.path().display());
+        self.gif_encoders.retain(|encoder| !encoder.is_finished());
+        self.gif_encoders.push(std::thread::spawn(move || match recording.save() {
+            Ok(path) => log::info!("Saved recording to {}", path.display()),
+            Err(e) => log::error!("Failed to save recording: {:?}", e),
+        }));
+    }
+
+    /// Stops the recording, if any, and waits until every recording has been saved. Call before exiting.
+    fn finish_gif_recordings(&mut self) {
+        self.stop_gif_recording();
+        for encoder in self.gif_encoders.drain(..) {
+            if encoder.join().is_err() {
+                log::error!("Recording encoder panicked");
+            }
+        }
+    }
+
+    /// Returns staging chunks the GPU has finished with to the belt. Call at the start of each frame.
+    fn recall_staging_belt(&mut self) {
+        self.staging_recalls.push(Box::pin(self.staging_belt.recall()));
+        self.device.poll(wgpu::Maintain::Poll);
+        self.staging_recalls.retain_mut(|recall| util::poll_once(recall.as_mut()).is_pending());
     }
 
     fn update(&mut self, dt: std::time::Duration) {
-        self.camera.update(dt, &mut self.queue);
+        self.timer.tick(dt);
+        if let Some(recording) = &mut self.gif_recording {
+            self.gif_frame_requested |= recording.advance(dt.as_secs_f32());
+        }
+        self.frame_history.push(dt);
+        self.wire_pass.update_face_color_texture(&self.device, &self.queue);
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Upload Encoder"),
+        });
+        let mut uploader = BufferUploader {
+            device: &self.device,
+            encoder: &mut encoder,
+            belt: &mut self.staging_belt,
+        };
+
+        self.camera.update(dt, &mut uploader);
+        if let Some(t) = self.camera_path_time.as_mut() {
+            *t += dt.as_secs_f32();
+            self.camera.play_path(&self.camera_path, *t, &mut uploader);
+            if *t >= self.camera_path.duration() {
+                self.camera_path_time = None;
+            }
+        }
     
         // Update the light.
         // let prev_pos: cgmath::Vector3<_> = self.light_uniform.position.into();
@@ -846,7 +3627,45 @@ impl State {
         //     * prev_pos).into();
         // self.queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[self.light_uniform]));
 
-        self.wire_pass.update(dt, &mut self.queue);
+        let frustum = self.camera.frustum();
+        self.wire_pass.update(dt, &mut uploader, &frustum, self.camera.eye.position, self.minimap.visible);
+        self.staging_belt.finish();
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        self.displace_pass.update(&self.queue, &self.timer);
+        if let Some(chained) = &self.chained_displace_pass {
+            chained.update(&self.queue, &self.timer);
+        }
+        self.post_process.update(&self.queue, &self.timer);
+        self.motion_blur_pass.update(&self.queue, &self.timer);
+        if let Some(particle_pass) = &mut self.particle_pass {
+            particle_pass.update(&self.queue, &self.timer);
+        }
+        self.water_pass.update(&self.queue, &self.timer);
+        if self.terrain.visible {
+            self.terrain.update(&self.device, self.camera.eye.position);
+        }
+        self.spotlight.point_at(self.spotlight_target);
+        self.queue.write_buffer(&self.spotlight_buffer, 0, bytemuck::cast_slice(&[self.spotlight]));
+        self.shadow_pass.light_direction = self.spotlight.direction.into();
+        self.shadow_pass.update(&self.queue, &self.camera);
+        if self.deferred_shading {
+            self.deferred_lighting_pass.update(&self.queue, &self.camera);
+        }
+
+        self.line_pass.clear();
+        if self.wire_pass.show_normals {
+            self.wire_pass.append_normals(&frustum, &mut self.line_pass);
+        }
+        self.line_pass.update(&self.device);
+
+        if self.debug_pass.enabled {
+            let (min, max) = self.wire_pass.mesh_type.bounds();
+            let boxes = self.wire_pass.instances.iter()
+                .map(|instance| (instance.transform.to_matrix(), min, max))
+                .collect::<Vec<_>>();
+            self.debug_pass.update(&self.device, &boxes);
+        }
     }
 
     fn render(
@@ -854,18 +3673,164 @@ impl State {
         output_view: &wgpu::TextureView,
         encoder: &mut wgpu::CommandEncoder,
     ) -> Result<(), wgpu::SurfaceError> {
-        self.wire_pass.render(
-            //&view,
-            &self.displace_pass.texture.view,
+        self.noise_pass.dispatch(&self.queue, encoder);
+        // Before the wire pass, which samples the shadow maps.
+        self.shadow_pass.render(
+            encoder,
+            self.wire_pass.lod_group.base(),
+            &self.wire_pass.instance_buffer,
+            0..self.wire_pass.visible_count,
+        );
+
+        if let Some(timer) = &self.gpu_timer { timer.begin(encoder, 0); }
+        // The skybox must be drawn first, the wire pass then draws over it.
+        if self.show_skybox {
+            self.skybox_pass.render(
+                &self.post_process.input().view,
+                encoder,
+                &self.camera.bind_group,
+            );
+        }
+        if self.deferred_shading {
+            // With GPU culling the instance buffer holds every instance, otherwise the visible ones.
+            self.gbuffer_pass.render(
+                encoder,
+                &self.depth_texture,
+                &self.camera.bind_group,
+                self.wire_pass.lod_group.base(),
+                &self.wire_pass.instance_buffer,
+                0..self.wire_pass.visible_count,
+            );
+            self.deferred_lighting_pass.render(
+                &self.post_process.input().view,
+                encoder,
+                Color(self.wire_pass.background_color),
+            );
+        } else {
+            self.wire_pass.render(
+                //&view,
+                &self.post_process.input().view,
+                encoder,
+                &self.depth_texture,
+                &self.camera.bind_group,
+                &self.spotlight_bind_group,
+                !self.show_skybox,
+            );
+        }
+        if self.minimap.visible {
+            self.wire_pass.render_overview(
+                &self.minimap.render_texture.view,
+                encoder,
+                self.minimap.depth_texture(),
+                &self.minimap.camera.bind_group,
+                &self.spotlight_bind_group,
+            );
+        }
+        if let Some(timer) = &self.gpu_timer { timer.end(encoder, 0); }
+
+        if let Some(timer) = &self.gpu_timer { timer.begin(encoder, 1); }
+        self.debug_pass.render(
+            &self.post_process.input().view,
+            encoder,
+            &self.depth_texture,
+            &self.camera.bind_group,
+        );
+        self.frustum_pass.render(
+            &self.post_process.input().view,
+            encoder,
+            &self.depth_texture,
+            &self.camera.bind_group,
+        );
+        self.line_pass.render(
+            &self.post_process.input().view,
+            encoder,
+            &self.depth_texture,
+            &self.camera.bind_group,
+        );
+        if let Some(timer) = &self.gpu_timer { timer.end(encoder, 1); }
+
+        if let Some(timer) = &self.gpu_timer { timer.begin(encoder, 2); }
+        self.terrain.render(
+            &self.post_process.input().view,
+            encoder,
+            &self.depth_texture,
+            &self.camera.bind_group,
+        );
+        self.grid_pass.render(
+            &self.post_process.input().view,
+            encoder,
+            &self.depth_texture,
+            &self.camera.bind_group,
+        );
+        self.water_pass.render(
+            self.post_process.input(),
             encoder,
             &self.depth_texture,
             &self.camera.bind_group,
         );
+        // Particles are blended, so draw them after the opaque passes.
+        if let Some(particle_pass) = &self.particle_pass {
+            particle_pass.dispatch(encoder);
+            particle_pass.render(
+                &self.post_process.input().view,
+                encoder,
+                &self.depth_texture,
+                &self.camera.bind_group,
+            );
+        }
+        // Labels go on top of everything else they aren't hidden behind.
+        self.billboard_pass.render(
+            &self.post_process.input().view,
+            encoder,
+            &self.camera.bind_group,
+        );
+        if let Some(timer) = &self.gpu_timer { timer.end(encoder, 2); }
+
+        for pass in &self.custom_passes {
+            pass.render(encoder, &self.post_process.input().view, &self.camera.bind_group);
+        }
 
-        self.displace_pass.render(
+        // Composite the post-processed result to the screen.
+        if let Some(timer) = &self.gpu_timer { timer.begin(encoder, 3); }
+        self.post_process.apply(encoder);
+        // Inputs as bound by bind_composite_inputs, the post-processing result then the histories.
+        let input = match self.motion_blur_pass.render(encoder) {
+            Some(history) => 1 + history,
+            None => 0,
+        };
+        self.displace_pass.select_input(Some(input));
+        let last_pass = match &self.chained_displace_pass {
+            Some(chained) => {
+                self.displace_pass.render(&self.displace_pass.texture.view, encoder);
+                chained
+            }
+            None => &self.displace_pass,
+        };
+        last_pass.render(
             &output_view,
             encoder,
         );
+        if let Some(timer) = &self.gpu_timer { timer.end(encoder, 3); }
+
+        // The surface texture can't be copied from, so composite a second time into a texture that can.
+        if self.screenshot_requested || self.gif_frame_requested {
+            let screenshot_texture = self.screenshot_texture.get_or_insert_with(|| {
+                texture::Texture::create_render_texture(&self.device, &self.config, "Screenshot Texture")
+            });
+            last_pass.render(
+                &screenshot_texture.view,
+                encoder,
+            );
+            // Screenshots are read back by `save_screenshot` once this frame is submitted.
+            if std::mem::take(&mut self.gif_frame_requested) {
+                let (width, height) = (self.config.width, self.config.height);
+                self.gif_readbacks.push_back(screenshot::Readback::new(&self.device, encoder, &screenshot_texture.texture, width, height));
+            }
+        }
+
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.resolve(encoder);
+        }
 
         {
             // use crate::model::DrawLight;
@@ -881,8 +3846,69 @@ impl State {
     }
 }
 
+/// Lost surfaces this many frames in a row are taken to mean reconfiguring won't fix it.
+const MAX_SURFACE_LOST: u8 = 3;
+
+/// Reconfigures a lost surface, giving up after `MAX_SURFACE_LOST` tries without a frame in between.
+fn handle_surface_error(
+    error: wgpu::SurfaceError,
+    state: &mut State,
+    surface_lost_count: &mut u8,
+    control_flow: &mut ControlFlow,
+) {
+    match error {
+        wgpu::SurfaceError::Lost => {
+            *surface_lost_count += 1;
+            if *surface_lost_count == 1 {
+                log::warn!("Surface lost, reconfiguring");
+            }
+            if *surface_lost_count >= MAX_SURFACE_LOST {
+                log::error!("Surface lost {} times in a row, exiting", surface_lost_count);
+                *control_flow = ControlFlow::Exit;
+            } else {
+                state.resize(state.size);
+            }
+        }
+        // Quit if the system is out of memory
+        wgpu::SurfaceError::OutOfMemory => *control_flow = ControlFlow::Exit,
+        // All other errors (Outdated, Timeout) should be resolved by the next frame
+        e => log::warn!("{:?}", e),
+    }
+}
+
+/// Renders a single frame offscreen and saves it as a PNG, for visual regression tests.
+fn run_headless(app_config: &Config) -> anyhow::Result<()> {
+    let output_path = app_config.output_path.as_str();
+    let mut state = pollster::block_on(State::new_headless(800, 600, app_config))?;
+    state.update(std::time::Duration::from_secs_f32(1.0 / 60.0));
+
+    let target = texture::Texture::create_render_texture(&state.device, &state.config, "Headless Target");
+    let mut encoder = state.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Headless Encoder"),
+    });
+    state.render(&target.view, &mut encoder)?;
+    state.queue.submit(std::iter::once(encoder.finish()));
+
+    let pixels = screenshot::capture_screenshot(&state.device, &state.queue, &target.texture, &state.config)?;
+    screenshot::save_png(output_path, &pixels, state.config.width, state.config.height)?;
+    log::info!("Saved headless frame to {}", output_path);
+    Ok(())
+}
+
 fn main() {
-    env_logger::init();
+    let console = console::Console::init();
+
+    let app_config = Config::from_args();
+    if app_config.headless {
+        match run_headless(&app_config) {
+            Ok(()) => std::process::exit(0),
+            Err(e) => {
+                log::error!("Headless render failed: {:?}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
     //let event_loop = EventLoop::new();
     let event_loop = EventLoop::with_user_event();
     let window = WindowBuilder::new()
@@ -893,8 +3919,17 @@ fn main() {
     let mut is_cursor_in_window = true;
 
     // Wait for State::new to finish...
-    let mut state = pollster::block_on(State::new(&window));
+    let mut state = match pollster::block_on(State::new(&window, &app_config)) {
+        Ok(state) => state,
+        Err(e) => {
+            log::error!("Failed to initialize the renderer: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+    let axes_pass = AxesPass::new(&state.device, &state.config, &state.camera.layout, 1.0);
+    state.add_pass(Box::new(axes_pass));
     let mut last_render_time = std::time::Instant::now();
+    let mut surface_lost_count = 0u8;
 
     // Set up gui.
     let mut gui = Gui::new(
@@ -902,10 +3937,19 @@ fn main() {
         &event_loop,
         &state.device, 
         &state.config,
+        console,
     );
 
+    if let Some(path) = &app_config.console_font {
+        match std::fs::read(path) {
+            Ok(font_data) => gui.set_monospace_font(font_data),
+            Err(e) => log::error!("Failed to read console font {}: {:?}", path.display(), e),
+        }
+    }
+
     // Call epi setup once.
     gui.setup(&mut state);
+    state.register_debug_textures(&mut gui);
 
     event_loop.run(move |event, _, control_flow| {
         match event {
@@ -915,6 +3959,13 @@ fn main() {
             } => {
                 if is_focused && is_cursor_in_window {
                     match event {
+                        DeviceEvent::Key(KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::F11),
+                            state: ElementState::Pressed,
+                            ..
+                        }) => {
+                            state.toggle_fullscreen(&window);
+                        }
                         DeviceEvent::Key(..) => {
                             if !gui.using_keyboard {
                                 state.input(event);
@@ -950,6 +4001,12 @@ fn main() {
                         is_cursor_in_window = false;
                         println!("cursor in {}", is_cursor_in_window);
                     }
+                    // Handled even without focus, the window may be dragged to another display in the background.
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        gui.window_event(event);
+                        // new_inner_size is &&mut so we have to dereference 2x
+                        state.resize(**new_inner_size);
+                    }
                     _ => {
                         if is_focused {
                             if !gui.window_event(&event) {
@@ -963,13 +4020,16 @@ fn main() {
                                         },
                                         ..
                                     } => *control_flow = ControlFlow::Exit,
+                                    WindowEvent::CursorMoved { position, .. } => {
+                                        state.cursor_position = *position;
+                                    }
+                                    WindowEvent::DroppedFile(path) => {
+                                        state.dropped_file = Some(path.clone());
+                                    }
                                     WindowEvent::Resized(physical_size) => {
+                                        gui.resize(*physical_size, window.scale_factor());
                                         state.resize(*physical_size);
                                     }
-                                    WindowEvent::ScaleFactorChanged { new_inner_size,.. } => {
-                                        // new_inner_size is &&mut so we have to dereference 2x
-                                        state.resize(**new_inner_size);
-                                    }
                                     _ => {}
                                 }
                             }
@@ -982,36 +4042,96 @@ fn main() {
                 let dt = now - last_render_time;
                 last_render_time = now;
 
+                state.recall_staging_belt();
                 gui.update(&window, &mut state);
+                if std::mem::take(&mut state.render_targets_recreated) {
+                    state.register_debug_textures(&mut gui);
+                }
                 state.update(dt);
 
-                let output = state.surface.get_current_texture().unwrap();
+                let output = match state.surface.as_ref().unwrap().get_current_texture() {
+                    Ok(output) => output,
+                    Err(e) => {
+                        handle_surface_error(e, &mut state, &mut surface_lost_count, control_flow);
+                        return;
+                    }
+                };
                 let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
                 let mut encoder = state.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
                     label: Some("Render Encoder"),
                 });
 
-                match state.render(&view, &mut encoder) {
-                    Ok(_) => {}
-                    // Reconfigure the surface if lost
-                    Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
-                    // Quit if the system is out of memory
-                    Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-                    // All other errors (Outdated, Timeout) should be resolved by the next frame
-                    Err(e) => eprintln!("{:?}", e),
-                }
+                let rendered = match state.render(&view, &mut encoder) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        handle_surface_error(e, &mut state, &mut surface_lost_count, control_flow);
+                        false
+                    }
+                };
 
                 gui.render(&state.device, &state.queue, &view, &mut encoder);
 
                 state.queue.submit(std::iter::once(encoder.finish()));
 
+                if let Some(timer) = &mut state.gpu_timer {
+                    timer.read_back(&state.device);
+                }
+                state.save_screenshot();
+
                 output.present();
+                if rendered {
+                    surface_lost_count = 0;
+                }
+
+                // The frame just presented shows the loading label, so parse the file now.
+                state.load_dropped_file(&mut gui);
+            }
+            Event::LoopDestroyed => {
+                state.finish_gif_recordings();
+                if let Err(e) = state.settings().save() {
+                    log::error!("Failed to save settings: {:?}", e);
+                }
             }
             Event::MainEventsCleared | Event::UserEvent(GuiEvent::RequestRedraw) => {
+                state.reload_shaders();
+
                 // Manually request a redraw
                 window.request_redraw();
             }
             _ => {}
         }
     });
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Rotation, Rotation3};
+    // Fails to compile if Gui moves out of the gui module again.
+    #[allow(unused_imports)]
+    use crate::gui::Gui;
+
+    // The hand-written product WirePass::update used before switching to cgmath's Mul.
+    fn quat_mul(q: cgmath::Quaternion<f32>, r: cgmath::Quaternion<f32>) -> cgmath::Quaternion<f32> {
+        let w = r.s * q.s - r.v.x * q.v.x - r.v.y * q.v.y - r.v.z * q.v.z;
+        let xi = r.s * q.v.x + r.v.x * q.s - r.v.y * q.v.z + r.v.z * q.v.y;
+        let yj = r.s * q.v.y + r.v.x * q.v.z + r.v.y * q.s - r.v.z * q.v.x;
+        let zk = r.s * q.v.z - r.v.x * q.v.y + r.v.y * q.v.x + r.v.z * q.s;
+
+        cgmath::Quaternion::new(w, xi, yj, zk)
+    }
+
+    #[test]
+    fn quat_mul_matches_cgmath() {
+        let amount = cgmath::Quaternion::from_angle_y(cgmath::Deg(30.0));
+        let current = cgmath::Quaternion::from_axis_angle(
+            cgmath::Vector3::new(1.0, 2.0, 3.0).normalize(),
+            cgmath::Deg(45.0),
+        );
+        let old = quat_mul(amount, current);
+        let new = amount * current;
+        assert!((old.s - new.s).abs() < 1e-6);
+        assert!((old.v - new.v).magnitude() < 1e-6);
+
+        let v = cgmath::Vector3::new(0.3, -1.0, 2.0);
+        assert!((old.rotate_vector(v) - new.rotate_vector(v)).magnitude() < 1e-5);
+    }
+}
@@ -1,11 +1,16 @@
 use anyhow::*;
 use cgmath::num_traits::Float;
 use cgmath::{InnerSpace, BaseNum, BaseFloat};
+use std::collections::HashMap;
 use std::ops::Range;
+use std::sync::Arc;
 use std::mem;
 use std::f32::consts;
 use wgpu::util::DeviceExt;
 
+use crate::color::Color;
+use crate::texture;
+
 pub trait Vertex {
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a>;
 }
@@ -13,13 +18,18 @@ pub trait Vertex {
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct MeshVertex {
-    position: [f32; 3],
-    tex_coords: [f32; 2],
-    normal: [f32; 3],
-    tangent: [f32; 3],
-    bitangent: [f32; 3],
+    pub position: [f32; 3],
+    pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
+    /// The bitangent is `cross(normal, tangent.xyz) * tangent.w`, with w either 1 or -1.
+    pub tangent: [f32; 4],
+    /// Arbitrary value, e.g. curvature or a simulation result, shown by the wire pass's heatmap.
+    pub scalar: f32,
 }
 
+// Size of a MeshVertex in 32-bit words, for comparing vertices bit for bit.
+const VERTEX_WORDS: usize = std::mem::size_of::<MeshVertex>() / 4;
+
 impl Vertex for MeshVertex {
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         wgpu::VertexBufferLayout {
@@ -44,28 +54,31 @@ impl Vertex for MeshVertex {
                 wgpu::VertexAttribute {
                     offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
                     shader_location: 3,
-                    format: wgpu::VertexFormat::Float32x3,
+                    format: wgpu::VertexFormat::Float32x4,
                 },
+                // Instance attributes start at 5.
                 wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 11]>() as wgpu::BufferAddress,
+                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
                     shader_location: 4,
-                    format: wgpu::VertexFormat::Float32x3,
+                    format: wgpu::VertexFormat::Float32,
                 },
             ],
         }
     }
 }
 
-#[derive(Copy, Clone)]
-struct ComputedVectors {
-    tangent: cgmath::Vector3<f32>,
-    bitangent: cgmath::Vector3<f32>,
+/// Tangent frame of a vertex, pointing along increasing u and v.
+#[derive(Copy, Clone, Debug)]
+pub struct ComputedVectors {
+    pub tangent: cgmath::Vector3<f32>,
+    pub bitangent: cgmath::Vector3<f32>,
 }
 
-fn calculate_tangents_bitangents(
-    positions: & Vec<cgmath::Vector3<f32>>,
-    tex_coords: & Vec<cgmath::Vector2<f32>>,
-    indices: & Vec<u32>,
+/// Computes a tangent frame per vertex, averaged over the triangles in `indices` that use it.
+pub fn calculate_tangents_bitangents(
+    positions: &[cgmath::Vector3<f32>],
+    tex_coords: &[cgmath::Vector2<f32>],
+    indices: &[u32],
 ) -> Vec<ComputedVectors> {
     // Calculate tangents and bitangents using triangles.
     let mut computed_vectors: Vec<ComputedVectors> = vec![ComputedVectors {
@@ -118,13 +131,503 @@ fn calculate_tangents_bitangents(
     computed_vectors
 }
 
+/// Averages the tangent frame of each vertex with those of the vertices it shares a triangle with,
+/// evening out frames that change sharply between neighbours.
+#[allow(dead_code)]
+pub fn smooth_tangents(cvs: &[ComputedVectors], indices: &[u32]) -> Vec<ComputedVectors> {
+    let mut smoothed = cvs.to_vec();
+    for tri in indices.chunks(3) {
+        for &a in tri {
+            for &b in tri {
+                if a != b {
+                    smoothed[a as usize].tangent += cvs[b as usize].tangent;
+                    smoothed[a as usize].bitangent += cvs[b as usize].bitangent;
+                }
+            }
+        }
+    }
+    let unit = |v: cgmath::Vector3<f32>| if v.magnitude2() > 0.0 { v.normalize() } else { v };
+    smoothed.into_iter()
+        .map(|vectors| ComputedVectors {
+            tangent: unit(vectors.tangent),
+            bitangent: unit(vectors.bitangent),
+        })
+        .collect()
+}
+
+/// Packs each frame into a tangent with the handedness of the bitangent relative to the normal in w.
+/// Only the bitangent's side of the normal/tangent plane is kept, the shader rebuilds the rest.
+fn pack_tangents(cvs: &[ComputedVectors], normals: &[cgmath::Vector3<f32>]) -> Vec<cgmath::Vector4<f32>> {
+    cvs.iter()
+        .zip(normals)
+        .map(|(vectors, normal)| {
+            let sign = if normal.cross(vectors.tangent).dot(vectors.bitangent) < 0.0 { -1.0 } else { 1.0 };
+            vectors.tangent.extend(sign)
+        })
+        .collect()
+}
+
+/// Maps longitude and latitude to UVs, duplicating the vertices along the seam where u wraps
+/// so triangles crossing it don't interpolate across the whole texture.
+fn spherical_tex_coords(
+    positions: &mut Vec<cgmath::Vector3<f32>>,
+    indices: &mut [u32],
+) -> Vec<cgmath::Vector2<f32>> {
+    let mut tex_coords = Vec::new();
+    for pos in positions.iter() {
+        let r0 = (pos.x * pos.x + pos.z * pos.z).sqrt();
+        let alpha = pos.z.atan2(pos.x);
+        let u = alpha / (consts::PI * 2.0) + 0.5;
+        let v = pos.y.atan2(r0) / consts::PI + 0.5;
+        tex_coords.push(cgmath::Vector2::new(1.0 - u, 1.0 - v));
+    }
+
+    let mut indices_to_split = Vec::new();
+    for c in indices.chunks(3) {
+        let t0 = tex_coords[c[0] as usize];
+        let t1 = tex_coords[c[1] as usize];
+        let t2 = tex_coords[c[2] as usize];
+
+        if (t2.x - t0.x).abs() > 0.5 {
+            if t0.x < 0.5 {
+                indices_to_split.push(c[0]);
+            } else {
+                indices_to_split.push(c[2]);
+            }
+        }
+        if (t1.x - t0.x).abs() > 0.5 {
+            if t0.x < 0.5 {
+                indices_to_split.push(c[0]);
+            } else {
+                indices_to_split.push(c[1]);
+            }
+        }
+        if (t2.x - t1.x).abs() > 0.5 {
+            if t1.x < 0.5 {
+                indices_to_split.push(c[1]);
+            } else {
+                indices_to_split.push(c[2]);
+            }
+        }
+    }
+
+    // Split verts.
+    for idx in indices_to_split.iter() {
+        let i: usize = idx.clone().try_into().unwrap();
+        let position = positions[i];
+        let tex_coord = tex_coords[i] + cgmath::Vector2::new(1.0, 0.0);
+        positions.push(position);
+        tex_coords.push(tex_coord);
+        let new_index = (positions.len() - 1) as u32;
+        for j in 0..indices.len() {
+            if i == indices[j] as usize {
+                let ndx1 = indices[(j + 1) % 3 + (j / 3) * 3] as usize;
+                let ndx2 = indices[(j + 2) % 3 + (j / 3) * 3] as usize;
+                if tex_coords[ndx1].x > 0.5 || tex_coords[ndx2].x > 0.5 {
+                    indices[j] = new_index;
+                }
+            }
+        }
+    }
+
+    tex_coords
+}
+
+/// Projects each triangle onto the cube face its centroid points at, unwrapped into a 3x2 atlas
+/// with +x, -x, +y in the first row and -y, +z, -z in the second one. Vertices shared by triangles
+/// on different faces are duplicated, since they need a UV per face.
+fn cubic_tex_coords(
+    positions: &mut Vec<cgmath::Vector3<f32>>,
+    indices: &mut [u32],
+) -> Vec<cgmath::Vector2<f32>> {
+    let face_of = |p: cgmath::Vector3<f32>| {
+        let abs = [p.x.abs(), p.y.abs(), p.z.abs()];
+        let axis = if abs[0] >= abs[1] && abs[0] >= abs[2] {
+            0
+        } else if abs[1] >= abs[2] {
+            1
+        } else {
+            2
+        };
+        axis * 2 + (p[axis] < 0.0) as usize
+    };
+    // Same orientation as cubemap faces.
+    let face_uv = |p: cgmath::Vector3<f32>, face: usize| {
+        let (sc, tc, ma) = match face {
+            0 => (-p.z, -p.y, p.x),
+            1 => (p.z, -p.y, -p.x),
+            2 => (p.x, p.z, p.y),
+            3 => (p.x, -p.z, -p.y),
+            4 => (p.x, -p.y, p.z),
+            _ => (-p.x, -p.y, -p.z),
+        };
+        let u = (sc / ma + 1.0) * 0.5;
+        let v = (tc / ma + 1.0) * 0.5;
+        cgmath::Vector2::new(
+            ((face % 3) as f32 + u) / 3.0,
+            ((face / 3) as f32 + v) / 2.0,
+        )
+    };
+
+    let source = mem::take(positions);
+    let mut tex_coords = Vec::new();
+    let mut split = HashMap::new();
+    for tri in indices.chunks_mut(3) {
+        let centroid = tri.iter()
+            .map(|&i| source[i as usize])
+            .fold(cgmath::Vector3::new(0.0, 0.0, 0.0), |sum, p| sum + p);
+        let face = face_of(centroid);
+        for index in tri.iter_mut() {
+            *index = *split.entry((*index, face)).or_insert_with(|| {
+                let position = source[*index as usize];
+                positions.push(position);
+                tex_coords.push(face_uv(position, face));
+                positions.len() as u32 - 1
+            });
+        }
+    }
+    tex_coords
+}
+
+/// Builds one vertex per position, with tangents computed from the triangles in `indices`.
+fn build_vertices(
+    positions: &[cgmath::Vector3<f32>],
+    tex_coords: &[cgmath::Vector2<f32>],
+    normals: &[cgmath::Vector3<f32>],
+    indices: &[u32],
+) -> Vec<MeshVertex> {
+    let computed_vectors = calculate_tangents_bitangents(
+        positions,
+        tex_coords,
+        indices,
+    );
+    let tangents = pack_tangents(&computed_vectors, normals);
+
+    (0..positions.len())
+        .map(|i| MeshVertex {
+            position: positions[i].into(),
+            tex_coords: tex_coords[i].into(),
+            normal: normals[i].into(),
+            tangent: tangents[i].into(),
+            scalar: 0.0,
+        })
+        .collect()
+}
+
+/// Sutherland-Hodgman clipping of a convex polygon, keeping the part where `distance` is at most 0.
+fn clip_polygon(
+    polygon: &[cgmath::Vector2<f32>],
+    distance: impl Fn(cgmath::Vector2<f32>) -> f32,
+) -> Vec<cgmath::Vector2<f32>> {
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+    for (k, &current) in polygon.iter().enumerate() {
+        let next = polygon[(k + 1) % polygon.len()];
+        let (d_current, d_next) = (distance(current), distance(next));
+        if d_current <= 0.0 {
+            clipped.push(current);
+        }
+        if (d_current <= 0.0) != (d_next <= 0.0) {
+            let t = d_current / (d_current - d_next);
+            clipped.push(current + (next - current) * t);
+        }
+    }
+    clipped
+}
+
+/// How `Mesh::icosphere` maps positions to texture coordinates.
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum IcoUvMode {
+    /// Longitude and latitude, stretched towards the poles.
+    Spherical,
+    /// Projected onto the faces of the enclosing cube.
+    Cubic,
+}
+
+/// Counts describing a generated icosphere.
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
+pub struct IcoStats {
+    /// Vertices uploaded to the GPU, one per corner for non-indexed meshes.
+    pub vertex_count: u32,
+    pub triangle_count: u32,
+    /// Vertices duplicated so each side of a texture seam gets its own UVs.
+    pub seam_splits: u32,
+}
+
+/// Position and normal of a vertex relative to where it sits on a coarser mesh,
+/// stored as two texels that wire.wgsl loads by vertex index.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MorphOffset {
+    // w is unused, the texture only comes in four channel float formats.
+    pub position: [f32; 4],
+    pub normal: [f32; 4],
+}
+
+/// Per-vertex offsets that move a mesh back onto a coarser version of itself,
+/// so the two can be blended instead of swapped.
+pub struct MorphTarget {
+    /// `MorphOffset` for each vertex, `ROW_VERTICES` to a row. A texture rather than a
+    /// storage buffer, so morphing also works on devices without vertex storage.
+    pub vertex_offsets: texture::Texture,
+}
+
+impl MorphTarget {
+    /// Vertices per texture row, must match `MORPH_ROW_VERTICES` in wire.wgsl.
+    /// Two texels each keeps rows within the smallest texture size WebGL2 allows.
+    pub const ROW_VERTICES: u32 = 1024;
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+
+    // Tessellation splits each triangle into four, in this order. Every corner of the new
+    // triangles lies between two corners of the one it was split from.
+    const SPLITS: [[(usize, usize); 3]; 4] = [
+        [(0, 0), (0, 1), (0, 2)],
+        [(1, 1), (1, 2), (0, 1)],
+        [(2, 2), (0, 2), (1, 2)],
+        [(0, 1), (1, 2), (0, 2)],
+    ];
+
+    /// A single zero offset, to bind while nothing is morphing.
+    pub fn empty(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        Self::from_offsets(device, queue, &[bytemuck::Zeroable::zeroed()])
+    }
+
+    /// Offsets from the icosphere with `iterations - 1` to the one with `iterations`,
+    /// for the non-indexed mesh `Mesh::icosphere` builds with the same parameters.
+    pub fn icosphere(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        radius: f32,
+        iterations: u32,
+        uv_mode: IcoUvMode,
+    ) -> Self {
+        assert!(iterations > 0, "The coarsest icosphere has nothing to morph from");
+        let unrolled = |iterations| {
+            let (vertices, indices) = Mesh::icosphere_cpu(radius, iterations, uv_mode);
+            indices.iter()
+                .map(|&i| vertices[i as usize])
+                .collect::<Vec<_>>()
+        };
+        let coarse = unrolled(iterations - 1);
+        let fine = unrolled(iterations);
+
+        let offsets = fine.iter()
+            .enumerate()
+            .map(|(i, vertex)| {
+                let triangle = i / 3;
+                let (a, b) = Self::SPLITS[triangle % 4][i % 3];
+                let a = &coarse[triangle / 4 * 3 + a];
+                let b = &coarse[triangle / 4 * 3 + b];
+                // Midpoints stay on the flat coarse triangle, before projection onto the sphere.
+                let position = (cgmath::Vector3::from(a.position) + cgmath::Vector3::from(b.position)) * 0.5;
+                let normal = (cgmath::Vector3::from(a.normal) + cgmath::Vector3::from(b.normal)).normalize();
+                MorphOffset {
+                    position: (cgmath::Vector3::from(vertex.position) - position).extend(0.0).into(),
+                    normal: (cgmath::Vector3::from(vertex.normal) - normal).extend(0.0).into(),
+                }
+            })
+            .collect::<Vec<_>>();
+        Self::from_offsets(device, queue, &offsets)
+    }
+
+    fn from_offsets(device: &wgpu::Device, queue: &wgpu::Queue, offsets: &[MorphOffset]) -> Self {
+        // Whole rows only, the last one padded with zero offsets.
+        let rows = (offsets.len() as u32).div_ceil(Self::ROW_VERTICES);
+        let mut texels = offsets.to_vec();
+        texels.resize((rows * Self::ROW_VERTICES) as usize, bytemuck::Zeroable::zeroed());
+
+        let size = wgpu::Extent3d {
+            width: Self::ROW_VERTICES * 2,
+            height: rows,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Morph Offset Texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: Self::FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            },
+            bytemuck::cast_slice(&texels),
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // Only ever read with textureLoad.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+        Self {
+            vertex_offsets: texture::Texture { texture, view, sampler },
+        }
+    }
+}
+
+/// Identifies a generated mesh by its factory and parameters.
+/// Floats are stored as bits so the key can be hashed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MeshCacheKey {
+    Icosphere { radius: u32, iterations: u32, uv_mode: IcoUvMode, use_indices: bool },
+    Cube { size: u32, use_indices: bool },
+    Plane { width: u32, height: u32, subdivisions: u32, use_indices: bool },
+}
+
+/// Versions of a mesh with decreasing detail, picked per instance by distance from the camera.
+pub struct LodGroup {
+    /// Finest first, never empty.
+    pub meshes: Vec<Arc<Mesh>>,
+    /// Distance from which `meshes[i + 1]` replaces `meshes[i]`, ascending.
+    pub thresholds: Vec<f32>,
+}
+
+impl LodGroup {
+    pub fn new(meshes: Vec<Arc<Mesh>>, thresholds: Vec<f32>) -> Self {
+        assert!(!meshes.is_empty(), "A LOD group needs at least one mesh");
+        assert_eq!(thresholds.len() + 1, meshes.len(), "Every mesh but the first needs a threshold");
+        Self { meshes, thresholds }
+    }
+
+    /// A group that always draws `mesh`.
+    pub fn single(mesh: Arc<Mesh>) -> Self {
+        Self::new(vec![mesh], Vec::new())
+    }
+
+    /// The finest mesh.
+    pub fn base(&self) -> &Arc<Mesh> {
+        &self.meshes[0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.meshes.len()
+    }
+
+    /// Index of the mesh to draw at `distance` from the camera.
+    pub fn tier(&self, distance: f32) -> usize {
+        self.thresholds.iter()
+            .take_while(|&&threshold| distance >= threshold)
+            .count()
+    }
+}
+
+struct MeshCacheEntry {
+    mesh: Arc<Mesh>,
+    bytes: u64,
+    // Value of `MeshCache::clock` when the mesh was last requested.
+    last_used: u64,
+}
+
+/// Shares generated meshes between users that request identical parameters.
+/// Keeps the GPU memory of its meshes under `max_bytes` by evicting the least recently used
+/// ones nobody else holds, but goes over budget rather than drop a mesh still in use.
+pub struct MeshCache {
+    entries: HashMap<MeshCacheKey, MeshCacheEntry>,
+    max_bytes: u64,
+    used_bytes: u64,
+    // Incremented on every request, orders the entries by use.
+    clock: u64,
+}
+
+impl Default for MeshCache {
+    fn default() -> Self {
+        Self::with_max_bytes(Self::DEFAULT_MAX_BYTES)
+    }
+}
+
+impl MeshCache {
+    const DEFAULT_MAX_BYTES: u64 = 256 << 20;
+
+    pub fn with_max_bytes(max_bytes: u64) -> Self {
+        Self {
+            entries: HashMap::new(),
+            max_bytes,
+            used_bytes: 0,
+            clock: 0,
+        }
+    }
+
+    /// GPU memory taken by the cached meshes.
+    pub fn used_bytes(&self) -> u64 {
+        self.used_bytes
+    }
+
+    fn get_or_build(
+        &mut self,
+        key: MeshCacheKey,
+        build: impl FnOnce() -> Result<Mesh>,
+    ) -> Result<Arc<Mesh>> {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(&key) {
+            entry.last_used = self.clock;
+            return Ok(entry.mesh.clone());
+        }
+        let mesh = Arc::new(build()?);
+        let bytes = mesh.gpu_bytes();
+        self.make_room(bytes);
+        self.used_bytes += bytes;
+        self.entries.insert(key, MeshCacheEntry {
+            mesh: mesh.clone(),
+            bytes,
+            last_used: self.clock,
+        });
+        Ok(mesh)
+    }
+
+    /// Evicts unused meshes, least recently used first, until `bytes` more fit or none are left.
+    fn make_room(&mut self, bytes: u64) {
+        while self.used_bytes + bytes > self.max_bytes {
+            let lru = self.entries.iter()
+                .filter(|(_, entry)| Arc::strong_count(&entry.mesh) == 1)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| *key);
+            match lru.and_then(|key| self.entries.remove(&key)) {
+                Some(entry) => self.used_bytes -= entry.bytes,
+                None => break,
+            }
+        }
+    }
+
+    /// Drops meshes that are no longer referenced outside the cache.
+    pub fn evict_unused(&mut self) {
+        let used_bytes = &mut self.used_bytes;
+        self.entries.retain(|_, entry| {
+            let keep = Arc::strong_count(&entry.mesh) > 1;
+            if !keep {
+                *used_bytes -= entry.bytes;
+            }
+            keep
+        });
+    }
+}
+
 pub struct Mesh {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: Option<wgpu::Buffer>,
     pub num_elements: u32,
+    pub num_vertices: u32,
+    /// CPU copy of the unique vertices, before any unrolling for non-indexed drawing.
+    pub vertices: Vec<MeshVertex>,
+    /// `LineList` indices for each unique edge, drawn by `DrawMesh::draw_wireframe`.
+    pub edge_buffer: Option<wgpu::Buffer>,
+    pub num_edge_indices: u32,
+    /// Local space (center, radius) enclosing every vertex.
+    pub bounding_sphere: (cgmath::Vector3<f32>, f32),
 }
 
 impl Mesh {
+    /// Number of triangles drawn, whether or not the mesh is indexed.
+    pub fn num_triangles(&self) -> u32 {
+        self.num_elements / 3
+    }
+
+    /// Size of the vertex, index and edge buffers.
+    pub fn gpu_bytes(&self) -> u64 {
+        let index_size = mem::size_of::<u32>() as u64;
+        let indices = if self.index_buffer.is_some() { self.num_elements as u64 } else { 0 };
+        self.num_vertices as u64 * mem::size_of::<MeshVertex>() as u64
+            + (indices + self.num_edge_indices as u64) * index_size
+    }
+
     pub fn quad(
         device: &wgpu::Device,
         width: f32,
@@ -144,70 +647,211 @@ impl Mesh {
         tex_coords.push(cgmath::Vector2::new(0.0, 1.0));
 
         const NORMAL: cgmath::Vector3<f32> = cgmath::Vector3::new(0.0, 0.0, 1.0);
+        let normals = vec![NORMAL; positions.len()];
 
         let indices: Vec<u32> = vec![0, 1, 2, 2, 3, 0];
 
-        let computed_vectors = calculate_tangents_bitangents(
+        Self::from_geometry(
+            device,
             &positions,
-            &tex_coords, 
-            &indices
-        );
+            &tex_coords,
+            &normals,
+            &indices,
+            use_indices,
+        )
+    }
 
-        if use_indices {
-            let mut vertices = Vec::new();
-            for i in 0..positions.len() {
-                vertices.push(MeshVertex {
-                    position: positions[i].into(),
-                    tex_coords: tex_coords[i].into(),
-                    normal: NORMAL.into(),
-                    tangent: computed_vectors[i].tangent.into(),
-                    bitangent: computed_vectors[i].bitangent.into(),
-                });
-            }
+    pub fn cube(
+        device: &wgpu::Device,
+        size: f32,
+        use_indices: bool,
+    ) -> Result<Self> {
+        // Each face is listed as (normal, u axis, v axis), with u x v = normal.
+        let faces = [
+            (cgmath::Vector3::unit_x(), -cgmath::Vector3::unit_z(), cgmath::Vector3::unit_y()),
+            (-cgmath::Vector3::unit_x(), cgmath::Vector3::unit_z(), cgmath::Vector3::unit_y()),
+            (cgmath::Vector3::unit_y(), cgmath::Vector3::unit_x(), -cgmath::Vector3::unit_z()),
+            (-cgmath::Vector3::unit_y(), cgmath::Vector3::unit_x(), cgmath::Vector3::unit_z()),
+            (cgmath::Vector3::unit_z(), cgmath::Vector3::unit_x(), cgmath::Vector3::unit_y()),
+            (-cgmath::Vector3::unit_z(), -cgmath::Vector3::unit_x(), cgmath::Vector3::unit_y()),
+        ];
 
-            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
-            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(&indices),
-                usage: wgpu::BufferUsages::INDEX,
-            });
-            let num_elements  = indices.len() as u32;
+        let half_size = size * 0.5;
+        let mut positions = Vec::new();
+        let mut tex_coords = Vec::new();
+        let mut normals = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        for &(normal, u, v) in faces.iter() {
+            let base = positions.len() as u32;
+            let center = normal * half_size;
+            positions.push(center + (-u - v) * half_size);
+            positions.push(center + ( u - v) * half_size);
+            positions.push(center + ( u + v) * half_size);
+            positions.push(center + (-u + v) * half_size);
 
-            Ok(Self {
-                vertex_buffer,
-                index_buffer: Some(index_buffer),
-                num_elements,
+            tex_coords.push(cgmath::Vector2::new(0.0, 0.0));
+            tex_coords.push(cgmath::Vector2::new(1.0, 0.0));
+            tex_coords.push(cgmath::Vector2::new(1.0, 1.0));
+            tex_coords.push(cgmath::Vector2::new(0.0, 1.0));
+
+            normals.extend_from_slice(&[normal; 4]);
+
+            indices.extend_from_slice(&[base, base + 1, base + 2, base + 2, base + 3, base]);
+        }
+
+        Self::from_geometry(
+            device,
+            &positions,
+            &tex_coords,
+            &normals,
+            &indices,
+            use_indices,
+        )
+    }
+
+    pub fn plane(
+        device: &wgpu::Device,
+        width: f32,
+        height: f32,
+        subdivisions: u32,
+        use_indices: bool,
+    ) -> Result<Self> {
+        let cells = subdivisions.max(1);
+        let heights = vec![0.0; ((cells + 1) * (cells + 1)) as usize];
+        Self::plane_with_heights(device, cgmath::Vector3::new(0.0, 0.0, 0.0), width, height, cells, &heights, use_indices)
+    }
+
+    /// Like `plane`, centered on `center` and with each vertex raised by the matching entry of
+    /// `heights`, given row by row along x, with `subdivisions + 1` entries per side.
+    /// Normals along the border only see heights inside the plane.
+    pub fn plane_with_heights(
+        device: &wgpu::Device,
+        center: cgmath::Vector3<f32>,
+        width: f32,
+        height: f32,
+        subdivisions: u32,
+        heights: &[f32],
+        use_indices: bool,
+    ) -> Result<Self> {
+        // The plane lies on XZ facing up, split into subdivisions x subdivisions cells.
+        let cells = subdivisions.max(1);
+        let row = cells + 1;
+        ensure!(heights.len() == (row * row) as usize, "Expected {} heights, got {}", row * row, heights.len());
+        let mut positions = Vec::new();
+        let mut tex_coords = Vec::new();
+        for z in 0..=cells {
+            for x in 0..=cells {
+                let u = x as f32 / cells as f32;
+                let v = z as f32 / cells as f32;
+                let y = heights[(z * row + x) as usize];
+                positions.push(center + cgmath::Vector3::new(width * (u - 0.5), y, height * (v - 0.5)));
+                tex_coords.push(cgmath::Vector2::new(u, v));
+            }
+        }
+
+        // Central differences, one sided along the border.
+        let at = |x: u32, z: u32| heights[(z * row + x) as usize];
+        let normals = (0..row * row)
+            .map(|i| {
+                let (x, z) = (i % row, i / row);
+                let (x0, x1) = (x.saturating_sub(1), (x + 1).min(cells));
+                let (z0, z1) = (z.saturating_sub(1), (z + 1).min(cells));
+                let dx = (at(x1, z) - at(x0, z)) / ((x1 - x0) as f32 * width / cells as f32);
+                let dz = (at(x, z1) - at(x, z0)) / ((z1 - z0) as f32 * height / cells as f32);
+                cgmath::Vector3::new(-dx, 1.0, -dz).normalize()
             })
-        } else {
-            let mut vertices = Vec::new();
-            for idx in 0..indices.len() {
-                let i = indices[idx] as usize;
-                vertices.push(MeshVertex {
-                    position: positions[i].into(),
-                    tex_coords: tex_coords[i].into(),
-                    normal: NORMAL.into(),
-                    tangent: computed_vectors[i].tangent.into(),
-                    bitangent: computed_vectors[i].bitangent.into(),
-                });
+            .collect::<Vec<_>>();
+
+        let mut indices: Vec<u32> = Vec::new();
+        let row = cells + 1;
+        for z in 0..cells {
+            for x in 0..cells {
+                let i = z * row + x;
+                indices.extend_from_slice(&[i, i + row, i + 1]);
+                indices.extend_from_slice(&[i + 1, i + row, i + row + 1]);
             }
+        }
 
-            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
-            let num_elements  = vertices.len() as u32;
+        Self::from_geometry(
+            device,
+            &positions,
+            &tex_coords,
+            &normals,
+            &indices,
+            use_indices,
+        )
+    }
 
-            Ok(Self {
-                vertex_buffer,
-                index_buffer: None,
-                num_elements,
-            })
+    /// A flat Voronoi diagram of `sites` on XZ facing up, clipped to a `width` by `height`
+    /// rectangle centered on the origin. Site coordinates are (x, z). Each cell is a fan
+    /// around its centroid, with its vertices' scalar set to the site index mapped to [0, 1]
+    /// so the heatmap colors every cell differently. Cells left empty, like those of repeated
+    /// sites or sites far outside the rectangle, are skipped.
+    pub fn voronoi_2d(
+        device: &wgpu::Device,
+        sites: &[cgmath::Vector2<f32>],
+        width: f32,
+        height: f32,
+        use_indices: bool,
+    ) -> Result<Self> {
+        ensure!(!sites.is_empty(), "A Voronoi diagram needs at least one site");
+        let (half_width, half_height) = (width * 0.5, height * 0.5);
+
+        let mut positions = Vec::new();
+        let mut tex_coords = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        // Vertex range and scalar of each cell.
+        let mut cells = Vec::new();
+        for (i, site) in sites.iter().enumerate() {
+            // Brute force, the rectangle cut down by the bisector with every other site.
+            let mut cell = vec![
+                cgmath::Vector2::new(-half_width, -half_height),
+                cgmath::Vector2::new(half_width, -half_height),
+                cgmath::Vector2::new(half_width, half_height),
+                cgmath::Vector2::new(-half_width, half_height),
+            ];
+            for (j, other) in sites.iter().enumerate() {
+                if i == j || cell.is_empty() {
+                    continue;
+                }
+                let midpoint = (site + other) * 0.5;
+                let normal = other - site;
+                if normal.magnitude2() == 0.0 {
+                    // The first of the repeated sites keeps the cell.
+                    if j < i {
+                        cell.clear();
+                    }
+                    continue;
+                }
+                cell = clip_polygon(&cell, |p| (p - midpoint).dot(normal));
+            }
+            if cell.len() < 3 {
+                continue;
+            }
+
+            let centroid = cell.iter().fold(cgmath::Vector2::new(0.0, 0.0), |sum, p| sum + p) / cell.len() as f32;
+            let first = positions.len() as u32;
+            for p in std::iter::once(&centroid).chain(&cell) {
+                positions.push(cgmath::Vector3::new(p.x, 0.0, p.y));
+                tex_coords.push(cgmath::Vector2::new(p.x / width + 0.5, p.y / height + 0.5));
+            }
+            // The cell winds counter clockwise in (x, z), which faces down, so the fan goes the other way.
+            let corners = cell.len() as u32;
+            for k in 0..corners {
+                indices.extend_from_slice(&[first, first + 1 + (k + 1) % corners, first + 1 + k]);
+            }
+            let scalar = i as f32 / (sites.len() - 1).max(1) as f32;
+            cells.push((first as usize..positions.len(), scalar));
         }
+
+        let normals = vec![cgmath::Vector3::unit_y(); positions.len()];
+        let mut vertices = build_vertices(&positions, &tex_coords, &normals, &indices);
+        for (range, scalar) in cells {
+            for vertex in &mut vertices[range] {
+                vertex.scalar = scalar;
+            }
+        }
+        Self::from_vertices(device, &vertices, &indices, use_indices)
     }
 
     // pub fn pentagon(
@@ -263,8 +907,48 @@ impl Mesh {
         device: &wgpu::Device,
         radius: f32,
         iterations: u32,
+        uv_mode: IcoUvMode,
         use_indices: bool,
     ) -> Result<Self> {
+        let (vertices, indices) = Self::icosphere_cpu(radius, iterations, uv_mode);
+        Self::from_vertices(device, &vertices, &indices, use_indices)
+    }
+
+    /// Like `icosphere`, also returning the counts it ended up with.
+    #[allow(dead_code)]
+    pub fn icosphere_with_stats(
+        device: &wgpu::Device,
+        radius: f32,
+        iterations: u32,
+        uv_mode: IcoUvMode,
+        use_indices: bool,
+    ) -> Result<(Self, IcoStats)> {
+        let (vertices, indices, seam_splits) = Self::build_icosphere(radius, iterations, uv_mode);
+        let mesh = Self::from_vertices(device, &vertices, &indices, use_indices)?;
+        let stats = IcoStats {
+            vertex_count: mesh.num_vertices,
+            triangle_count: mesh.num_triangles(),
+            seam_splits,
+        };
+        Ok((mesh, stats))
+    }
+
+    /// Number of positions `iterations` rounds of tessellation generate, before seam splits.
+    /// Midpoints aren't shared between neighbouring triangles, so this is 20 * (4^n - 1) + 12
+    /// rather than the 10 * 4^n + 2 of a fully welded icosphere.
+    #[allow(dead_code)]
+    pub fn icosphere_vertex_count_for_iterations(iters: u32) -> u32 {
+        20 * (4u32.pow(iters) - 1) + 12
+    }
+
+    /// Generates icosphere vertices and triangle indices without touching the GPU.
+    pub fn icosphere_cpu(radius: f32, iterations: u32, uv_mode: IcoUvMode) -> (Vec<MeshVertex>, Vec<u32>) {
+        let (vertices, indices, _) = Self::build_icosphere(radius, iterations, uv_mode);
+        (vertices, indices)
+    }
+
+    /// Returns the vertices, the indices and how many vertices were split for texture seams.
+    fn build_icosphere(radius: f32, iterations: u32, uv_mode: IcoUvMode) -> (Vec<MeshVertex>, Vec<u32>, u32) {
         // Generate icosahedron.
         let sqrt5 = 5.0f32.sqrt();
         let phi = (1.0 + sqrt5) * 0.5;
@@ -344,63 +1028,11 @@ impl Mesh {
             mem::swap(&mut indices, &mut new_indices);
         }
 
-        // Generate tex coords.
-        let mut tex_coords = Vec::new();
-        for pos in positions.iter() {
-            let r0 = (pos.x * pos.x + pos.z * pos.z).sqrt();
-            let alpha = pos.z.atan2(pos.x);
-            let u = alpha / (consts::PI * 2.0) + 0.5;
-            let v = pos.y.atan2(r0) / consts::PI + 0.5;
-            tex_coords.push(cgmath::Vector2::new(1.0 - u, 1.0 - v));
-        }
-
-        let mut indices_to_split = Vec::new();
-        for c in indices.chunks(3) {
-            let t0 = tex_coords[c[0] as usize];
-            let t1 = tex_coords[c[1] as usize];
-            let t2 = tex_coords[c[2] as usize];
-
-            if (t2.x - t0.x).abs() > 0.5 {
-                if t0.x < 0.5 {
-                    indices_to_split.push(c[0]);
-                } else {
-                    indices_to_split.push(c[2]);
-                }
-            }
-            if (t1.x - t0.x).abs() > 0.5 {
-                if t0.x < 0.5 {
-                    indices_to_split.push(c[0]);
-                } else {
-                    indices_to_split.push(c[1]);
-                }
-            }
-            if (t2.x - t1.x).abs() > 0.5 {
-                if t1.x < 0.5 {
-                    indices_to_split.push(c[1]);
-                } else {
-                    indices_to_split.push(c[2]);
-                }
-            }
-        }
-
-        // Split verts.
-        for idx in indices_to_split.iter() {
-            let i: usize = idx.clone().try_into().unwrap();
-            let position = positions[i];
-            let tex_coord = tex_coords[i] + cgmath::Vector2::new(1.0, 0.0);
-            positions.push(position);
-            tex_coords.push(tex_coord);
-            let new_index = (positions.len() - 1) as u32;
-            for j in 0..indices.len() {
-                if i == indices[j] as usize {
-                    let ndx1 = indices[(j + 1) % 3 + (j / 3) * 3] as usize;
-                    let ndx2 = indices[(j + 2) % 3 + (j / 3) * 3] as usize;
-                    if tex_coords[ndx1].x > 0.5 || tex_coords[ndx2].x > 0.5 {
-                        indices[j] = new_index;
-                    }
-                }
-            }
-        }
+        let unsplit_count = positions.len();
+        let tex_coords = match uv_mode {
+            IcoUvMode::Spherical => spherical_tex_coords(&mut positions, &mut indices),
+            IcoUvMode::Cubic => cubic_tex_coords(&mut positions, &mut indices),
+        };
 
         // Flip faces.
         // for i in 0..(indices.len() / 3) {
@@ -415,65 +1047,382 @@ impl Mesh {
             positions[i] *= radius;
         }
 
-        let computed_vectors = calculate_tangents_bitangents(
-            &positions,
-            &tex_coords,
-            &indices,
-        );
+        let vertices = build_vertices(&positions, &tex_coords, &normals, &indices);
+        let seam_splits = (positions.len() - unsplit_count) as u32;
+        (vertices, indices, seam_splits)
+    }
 
-        if use_indices {
-            let mut vertices = Vec::new();
-            for i in 0..positions.len() {
-                vertices.push(MeshVertex {
-                    position: positions[i].into(),
-                    tex_coords: tex_coords[i].into(),
-                    normal: normals[i].into(),
-                    tangent: computed_vectors[i].tangent.into(),
-                    bitangent: computed_vectors[i].bitangent.into(),
-                });
+    pub fn cached_icosphere(
+        cache: &mut MeshCache,
+        device: &wgpu::Device,
+        radius: f32,
+        iterations: u32,
+        uv_mode: IcoUvMode,
+        use_indices: bool,
+    ) -> Result<Arc<Self>> {
+        let key = MeshCacheKey::Icosphere { radius: radius.to_bits(), iterations, uv_mode, use_indices };
+        cache.get_or_build(key, || Self::icosphere(device, radius, iterations, uv_mode, use_indices))
+    }
+
+    pub fn cached_cube(
+        cache: &mut MeshCache,
+        device: &wgpu::Device,
+        size: f32,
+        use_indices: bool,
+    ) -> Result<Arc<Self>> {
+        let key = MeshCacheKey::Cube { size: size.to_bits(), use_indices };
+        cache.get_or_build(key, || Self::cube(device, size, use_indices))
+    }
+
+    pub fn cached_plane(
+        cache: &mut MeshCache,
+        device: &wgpu::Device,
+        width: f32,
+        height: f32,
+        subdivisions: u32,
+        use_indices: bool,
+    ) -> Result<Arc<Self>> {
+        let key = MeshCacheKey::Plane { width: width.to_bits(), height: height.to_bits(), subdivisions, use_indices };
+        cache.get_or_build(key, || Self::plane(device, width, height, subdivisions, use_indices))
+    }
+
+    /// Loads the first model in an OBJ file as a non-indexed mesh. Normals are always
+    /// computed with `compute_smooth_normals`, ignoring any stored in the file.
+    pub fn from_obj<P: AsRef<std::path::Path>>(
+        device: &wgpu::Device,
+        path: P,
+        crease_angle_deg: f32,
+    ) -> Result<Self> {
+        let (models, _) = tobj::load_obj(path.as_ref(), &tobj::LoadOptions {
+            triangulate: true,
+            single_index: true,
+            ..Default::default()
+        })?;
+        let obj = &models.first().context("OBJ file contains no models")?.mesh;
+
+        let positions = obj.positions.chunks(3)
+            .map(|p| cgmath::Vector3::new(p[0], p[1], p[2]))
+            .collect::<Vec<_>>();
+        let tex_coords = if obj.texcoords.is_empty() {
+            vec![cgmath::Vector2::new(0.0, 0.0); positions.len()]
+        } else {
+            obj.texcoords.chunks(2)
+                .map(|t| cgmath::Vector2::new(t[0], t[1]))
+                .collect::<Vec<_>>()
+        };
+        let normals = Self::compute_smooth_normals(&positions, &obj.indices, crease_angle_deg);
+
+        // Normals are per corner, so give every corner its own vertex.
+        let corner_positions = obj.indices.iter().map(|&i| positions[i as usize]).collect::<Vec<_>>();
+        let corner_tex_coords = obj.indices.iter().map(|&i| tex_coords[i as usize]).collect::<Vec<_>>();
+        let corner_indices = (0..obj.indices.len() as u32).collect::<Vec<_>>();
+
+        Self::from_geometry(
+            device,
+            &corner_positions,
+            &corner_tex_coords,
+            &normals,
+            &corner_indices,
+            false,
+        )
+    }
+
+    /// Creates an Nx1 texture with a distinct hue per triangle, for telling faces apart when
+    /// debugging tessellation. The width is capped at the device limit, so very dense meshes
+    /// repeat colors and shaders should wrap the triangle index.
+    pub fn face_color_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        num_triangles: u32,
+    ) -> texture::Texture {
+        let width = num_triangles.clamp(1, device.limits().max_texture_dimension_2d);
+        let hash = |mut x: u32| {
+            x = (x ^ 61) ^ (x >> 16);
+            x = x.wrapping_mul(9);
+            x ^= x >> 4;
+            x = x.wrapping_mul(0x27d4_eb2d);
+            x ^ (x >> 15)
+        };
+        let pixels = (0..width)
+            .flat_map(|i| {
+                // Fully saturated HSV to RGB.
+                let hue = (hash(i) & 0xffff) as f32 / 65536.0;
+                let channel = |n: f32| {
+                    let k = (n + hue * 6.0) % 6.0;
+                    ((1.0 - k.min(4.0 - k).clamp(0.0, 1.0)) * 255.0) as u8
+                };
+                [channel(5.0), channel(3.0), channel(1.0), 255]
+            })
+            .collect::<Vec<_>>();
+        let image = image::RgbaImage::from_raw(width, 1, pixels).unwrap();
+        texture::Texture::from_image(
+            device,
+            queue,
+            &image::DynamicImage::ImageRgba8(image),
+            Some("Face Color Texture"),
+            false,
+        ).unwrap()
+    }
+
+    /// Transfer function for scalars in [0, 1], from cold blue through green to hot red.
+    pub fn heatmap_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> texture::Texture {
+        const WIDTH: u32 = 256;
+        let stops = [Color::BLUE, Color::CYAN, Color::GREEN, Color::YELLOW, Color::RED];
+        let pixels = (0..WIDTH)
+            .flat_map(|i| {
+                let t = i as f32 / (WIDTH - 1) as f32 * (stops.len() - 1) as f32;
+                let stop = (t as usize).min(stops.len() - 2);
+                let color = stops[stop].lerp(&stops[stop + 1], t - stop as f32);
+                color.0.map(|c| (c * 255.0) as u8)
+            })
+            .collect::<Vec<_>>();
+        let image = image::RgbaImage::from_raw(WIDTH, 1, pixels).unwrap();
+        texture::Texture::from_image(
+            device,
+            queue,
+            &image::DynamicImage::ImageRgba8(image),
+            Some("Heatmap Texture"),
+            false,
+        ).unwrap()
+    }
+
+    /// Approximates the smallest sphere enclosing `positions` with Ritter's algorithm, as (center, radius).
+    /// Starts from the most distant pair among the extreme points on each axis,
+    /// then grows the sphere just enough to take in every point left outside.
+    pub fn bounding_sphere(positions: &[cgmath::Vector3<f32>]) -> (cgmath::Vector3<f32>, f32) {
+        let zero = cgmath::Vector3::new(0.0, 0.0, 0.0);
+        if positions.is_empty() {
+            return (zero, 0.0);
+        }
+
+        let mut min = [positions[0]; 3];
+        let mut max = [positions[0]; 3];
+        for &p in positions {
+            for axis in 0..3 {
+                if p[axis] < min[axis][axis] {
+                    min[axis] = p;
+                }
+                if p[axis] > max[axis][axis] {
+                    max[axis] = p;
+                }
+            }
+        }
+        let (a, b) = (0..3)
+            .map(|axis| (min[axis], max[axis]))
+            .max_by(|(a0, b0), (a1, b1)| {
+                (b0 - a0).magnitude2().partial_cmp(&(b1 - a1).magnitude2()).unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap();
+
+        let mut center = (a + b) * 0.5;
+        let mut radius = (b - center).magnitude();
+        for &p in positions {
+            let distance = (p - center).magnitude();
+            if distance > radius {
+                // Move the center towards p so the far side of the old sphere stays on the boundary.
+                let new_radius = (radius + distance) * 0.5;
+                center += (p - center) * ((new_radius - radius) / distance);
+                radius = new_radius;
             }
+        }
+        (center, radius)
+    }
+
+    fn vertex_bounding_sphere(vertices: &[MeshVertex]) -> (cgmath::Vector3<f32>, f32) {
+        let positions = vertices.iter()
+            .map(|vertex| cgmath::Vector3::from(vertex.position))
+            .collect::<Vec<_>>();
+        Self::bounding_sphere(&positions)
+    }
+
+    /// Computes a normal for every corner of every triangle, i.e. one per entry in `indices`.
+    /// Each corner averages the area weighted normals of the triangles sharing its vertex,
+    /// skipping triangles that meet its own at more than `crease_angle_deg` so those edges stay hard.
+    pub fn compute_smooth_normals(
+        positions: &[cgmath::Vector3<f32>],
+        indices: &[u32],
+        crease_angle_deg: f32,
+    ) -> Vec<cgmath::Vector3<f32>> {
+        // Cross products are twice the triangle area, which weights the average.
+        let face_normals = indices.chunks(3)
+            .map(|tri| {
+                let p0 = positions[tri[0] as usize];
+                let p1 = positions[tri[1] as usize];
+                let p2 = positions[tri[2] as usize];
+                (p1 - p0).cross(p2 - p0)
+            })
+            .collect::<Vec<_>>();
+
+        let mut vertex_faces = vec![Vec::new(); positions.len()];
+        for (i, &index) in indices.iter().enumerate() {
+            vertex_faces[index as usize].push(i / 3);
+        }
+
+        let crease_cos = crease_angle_deg.to_radians().cos();
+        let unit = |n: cgmath::Vector3<f32>| if n.magnitude2() > 0.0 { n.normalize() } else { n };
+        indices.iter().enumerate()
+            .map(|(i, &index)| {
+                let face_normal = unit(face_normals[i / 3]);
+                let sum = vertex_faces[index as usize].iter()
+                    .map(|&face| face_normals[face])
+                    .filter(|&normal| unit(normal).dot(face_normal) >= crease_cos)
+                    .fold(cgmath::Vector3::new(0.0, 0.0, 0.0), |sum, normal| sum + normal);
+                if sum.magnitude2() > 0.0 { sum.normalize() } else { face_normal }
+            })
+            .collect()
+    }
+
+    /// Builds a non-indexed mesh for `PrimitiveTopology::LineList`, one segment per pair of positions.
+    pub fn lines(
+        device: &wgpu::Device,
+        positions: &[cgmath::Vector3<f32>],
+    ) -> Result<Self> {
+        let vertices = positions.iter()
+            .map(|position| MeshVertex {
+                position: (*position).into(),
+                tex_coords: [0.0; 2],
+                normal: [0.0; 3],
+                tangent: [0.0; 4],
+                scalar: 0.0,
+            })
+            .collect::<Vec<_>>();
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let num_elements = vertices.len() as u32;
+        let bounding_sphere = Self::vertex_bounding_sphere(&vertices);
+
+        Ok(Self {
+            vertex_buffer,
+            index_buffer: None,
+            num_elements,
+            num_vertices: num_elements,
+            vertices,
+            edge_buffer: None,
+            num_edge_indices: 0,
+            bounding_sphere,
+        })
+    }
+
+    /// Returns `LineList` indices for every edge of the triangles in `indices`,
+    /// with edges shared by neighbouring triangles listed once.
+    pub fn build_edge_list(indices: &[u32]) -> Vec<u32> {
+        let mut edges = indices.chunks(3)
+            .flat_map(|tri| [(tri[0], tri[1]), (tri[1], tri[2]), (tri[2], tri[0])])
+            .map(|(a, b)| (a.min(b), a.max(b)))
+            .collect::<Vec<_>>();
+        edges.sort_unstable();
+        edges.dedup();
+        edges.into_iter().flat_map(|(a, b)| [a, b]).collect()
+    }
+
+    /// Merges bitwise identical vertices of a non-indexed triangle list,
+    /// returning the unique vertices in first use order and an index per input vertex.
+    #[allow(dead_code)]
+    pub fn strip_to_indexed(vertices: &[MeshVertex]) -> (Vec<MeshVertex>, Vec<u32>) {
+        let mut unique = Vec::new();
+        let mut lookup = HashMap::new();
+        let indices = vertices.iter()
+            .map(|&vertex| {
+                let key: [u32; VERTEX_WORDS] = bytemuck::cast(vertex);
+                *lookup.entry(key).or_insert_with(|| {
+                    unique.push(vertex);
+                    unique.len() as u32 - 1
+                })
+            })
+            .collect();
+        (unique, indices)
+    }
 
+    fn create_edge_buffer(device: &wgpu::Device, edges: &[u32]) -> Option<wgpu::Buffer> {
+        if edges.is_empty() {
+            return None;
+        }
+        Some(device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Edge Buffer"),
+            contents: bytemuck::cast_slice(edges),
+            usage: wgpu::BufferUsages::INDEX,
+        }))
+    }
+
+    fn from_geometry(
+        device: &wgpu::Device,
+        positions: &[cgmath::Vector3<f32>],
+        tex_coords: &[cgmath::Vector2<f32>],
+        normals: &[cgmath::Vector3<f32>],
+        indices: &[u32],
+        use_indices: bool,
+    ) -> Result<Self> {
+        let vertices = build_vertices(positions, tex_coords, normals, indices);
+        Self::from_vertices(device, &vertices, indices, use_indices)
+    }
+
+    fn from_vertices(
+        device: &wgpu::Device,
+        vertices: &[MeshVertex],
+        indices: &[u32],
+        use_indices: bool,
+    ) -> Result<Self> {
+        if use_indices {
             let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(&vertices),
+                contents: bytemuck::cast_slice(vertices),
                 usage: wgpu::BufferUsages::VERTEX,
             });
             let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(&indices),
+                contents: bytemuck::cast_slice(indices),
                 usage: wgpu::BufferUsages::INDEX,
             });
             let num_elements  = indices.len() as u32;
+            let edges = Self::build_edge_list(indices);
 
             Ok(Self {
                 vertex_buffer,
                 index_buffer: Some(index_buffer),
                 num_elements,
+                num_vertices: vertices.len() as u32,
+                vertices: vertices.to_vec(),
+                edge_buffer: Self::create_edge_buffer(device, &edges),
+                num_edge_indices: edges.len() as u32,
+                bounding_sphere: Self::vertex_bounding_sphere(vertices),
             })
         } else {
-            let mut vertices = Vec::new();
-            for idx in 0..indices.len() {
-                let i = indices[idx] as usize;
-                vertices.push(MeshVertex {
-                    position: positions[i].into(),
-                    tex_coords: tex_coords[i].into(),
-                    normal: normals[i].into(),
-                    tangent: computed_vectors[i].tangent.into(),
-                    bitangent: computed_vectors[i].bitangent.into(),
-                });
-            }
+            let unrolled = indices.iter()
+                .map(|&i| vertices[i as usize])
+                .collect::<Vec<_>>();
 
             let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(&vertices),
+                contents: bytemuck::cast_slice(&unrolled),
                 usage: wgpu::BufferUsages::VERTEX,
             });
-            let num_elements  = vertices.len() as u32;
+            let num_elements  = unrolled.len() as u32;
+
+            // Vertices are unrolled per corner, so point each edge at the first corner using its vertex.
+            let mut first_corner = std::collections::HashMap::new();
+            for (corner, &index) in indices.iter().enumerate() {
+                first_corner.entry(index).or_insert(corner as u32);
+            }
+            let edges = Self::build_edge_list(indices).into_iter()
+                .map(|index| first_corner[&index])
+                .collect::<Vec<_>>();
 
             Ok(Self {
                 vertex_buffer,
                 index_buffer: None,
                 num_elements,
+                num_vertices: num_elements,
+                vertices: vertices.to_vec(),
+                edge_buffer: Self::create_edge_buffer(device, &edges),
+                num_edge_indices: edges.len() as u32,
+                bounding_sphere: Self::vertex_bounding_sphere(vertices),
             })
         }
     }
@@ -488,15 +1437,31 @@ pub trait DrawMesh<'a> {
         // camera_bind_group: &'a wgpu::BindGroup,
         // light_bind_group: &'a wgpu::BindGroup,
     );
+    /// `push_constants` are set at offset 0 for the vertex and fragment stages,
+    /// so the pipeline layout must declare a matching range.
     fn draw_mesh_instanced(
         &mut self,
         mesh: &'a Mesh,
         // material: &'a Material,
         instances: Range<u32>,
         bind_groups: Option<Vec<&'a wgpu::BindGroup>>,
+        push_constants: Option<&[u8]>,
         //camera_bind_group: &'a wgpu::BindGroup,
         // light_bind_group: &'a wgpu::BindGroup,
     );
+    fn draw_mesh_indirect(
+        &mut self,
+        mesh: &'a Mesh,
+        indirect_buffer: &'a wgpu::Buffer,
+        bind_groups: Option<Vec<&'a wgpu::BindGroup>>,
+    );
+    /// Draws the mesh's unique edges. Requires a `LineList` pipeline.
+    fn draw_wireframe(
+        &mut self,
+        mesh: &'a Mesh,
+        instances: Range<u32>,
+        bind_groups: Option<Vec<&'a wgpu::BindGroup>>,
+    );
 }
 
 impl<'a, 'b> DrawMesh<'b> for wgpu::RenderPass<'a>
@@ -512,7 +1477,7 @@ where
         // light_bind_group: &'b wgpu::BindGroup,
     ) {
         // self.draw_mesh_instanced(mesh, material, 0..1, camera_bind_group, light_bind_group);
-        self.draw_mesh_instanced(mesh, 0..1, bind_groups);
+        self.draw_mesh_instanced(mesh, 0..1, bind_groups, None);
     }
 
     fn draw_mesh_instanced(
@@ -521,6 +1486,7 @@ where
         // material: &'b Material,
         instances: Range<u32>,
         bind_groups: Option<Vec<&'a wgpu::BindGroup>>,
+        push_constants: Option<&[u8]>,
         // camera_bind_group: &'b wgpu::BindGroup,
         // light_bind_group: &'b wgpu::BindGroup,
     ) {
@@ -529,6 +1495,9 @@ where
                 self.set_bind_group(index as u32, *bind_group, &[]);
             }
         }
+        if let Some(push_constants) = push_constants {
+            self.set_push_constants(wgpu::ShaderStages::VERTEX_FRAGMENT, 0, push_constants);
+        }
         // self.set_bind_group(0, camera_bind_group, &[]);
         // self.set_bind_group(0, &material.bind_group, &[]);
         // self.set_bind_group(1, camera_bind_group, &[]);
@@ -544,4 +1513,69 @@ where
             },
         }
     }
+
+    fn draw_mesh_indirect(
+        &mut self,
+        mesh: &'b Mesh,
+        indirect_buffer: &'b wgpu::Buffer,
+        bind_groups: Option<Vec<&'a wgpu::BindGroup>>,
+    ) {
+        if bind_groups.is_some() {
+            for (index, bind_group) in bind_groups.unwrap().iter().enumerate() {
+                self.set_bind_group(index as u32, *bind_group, &[]);
+            }
+        }
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        match &mesh.index_buffer {
+            Some(index_buffer) => {
+                self.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                self.draw_indexed_indirect(indirect_buffer, 0);
+            },
+            None => {
+                self.draw_indirect(indirect_buffer, 0);
+            },
+        }
+    }
+
+    fn draw_wireframe(
+        &mut self,
+        mesh: &'b Mesh,
+        instances: Range<u32>,
+        bind_groups: Option<Vec<&'a wgpu::BindGroup>>,
+    ) {
+        let edge_buffer = match &mesh.edge_buffer {
+            Some(edge_buffer) => edge_buffer,
+            None => return,
+        };
+        if let Some(bind_groups) = bind_groups {
+            for (index, bind_group) in bind_groups.iter().enumerate() {
+                self.set_bind_group(index as u32, *bind_group, &[]);
+            }
+        }
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_index_buffer(edge_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.draw_indexed(0..mesh.num_edge_indices, 0, instances);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(vertices: &[MeshVertex]) -> Vec<[u32; VERTEX_WORDS]> {
+        vertices.iter().map(|&vertex| bytemuck::cast(vertex)).collect()
+    }
+
+    #[test]
+    fn strip_to_indexed_round_trips() {
+        let (vertices, indices) = Mesh::icosphere_cpu(1.0, 2, IcoUvMode::Spherical);
+        let flat = indices.iter().map(|&i| vertices[i as usize]).collect::<Vec<_>>();
+
+        let (unique, strip_indices) = Mesh::strip_to_indexed(&flat);
+        assert_eq!(strip_indices.len(), flat.len());
+        assert!(unique.len() < flat.len());
+
+        let expanded = strip_indices.iter().map(|&i| unique[i as usize]).collect::<Vec<_>>();
+        assert_eq!(bits(&expanded), bits(&flat));
+    }
 }
@@ -0,0 +1,63 @@
+use crate::camera;
+use crate::texture;
+
+/// A fixed top-down view of the instance grid, rendered offscreen and shown in the GUI.
+pub struct Minimap {
+    pub visible: bool,
+    pub camera: camera::Camera,
+    pub render_texture: texture::Texture,
+    depth_texture: texture::Texture,
+    // Set once the render texture is registered with the GUI.
+    pub texture_id: Option<egui::TextureId>,
+}
+
+impl Minimap {
+    pub const SIZE: u32 = 256;
+    // World space distance above the grid the camera looks down from.
+    const HEIGHT: f32 = 50.0;
+
+    /// The render texture uses the surface format, so the wire pipeline can draw into it.
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let config = wgpu::SurfaceConfiguration {
+            width: Self::SIZE,
+            height: Self::SIZE,
+            ..config.clone()
+        };
+        let mut camera = camera::Camera::new(
+            device,
+            (0.0, Self::HEIGHT, 0.0),
+            cgmath::Deg(0.0),
+            cgmath::Deg(0.0),
+            config.width,
+            config.height,
+            cgmath::Deg(45.0),
+            0.1,
+            Self::HEIGHT * 2.0,
+        );
+        camera.eye.set_top_down(true);
+
+        Self {
+            visible: false,
+            camera,
+            render_texture: texture::Texture::create_render_texture_with_format(
+                device,
+                &config,
+                config.format,
+                "Minimap Texture",
+            ),
+            depth_texture: texture::Texture::create_depth_texture(device, &config, "Minimap Depth Texture"),
+            texture_id: None,
+        }
+    }
+
+    /// Frames a grid of `dim` by `dim` instances `spacing` apart, centered on the origin,
+    /// with half a cell of margin around it.
+    pub fn fit_grid(&mut self, queue: &wgpu::Queue, dim: u32, spacing: f32) {
+        self.camera.projection.set_orthographic(Some(dim as f32 * spacing));
+        self.camera.update_uniform(queue);
+    }
+
+    pub fn depth_texture(&self) -> &texture::Texture {
+        &self.depth_texture
+    }
+}
@@ -0,0 +1,227 @@
+use wgpu::util::DeviceExt;
+
+use crate::mesh::{self, Vertex};
+use crate::pipeline::PipelineBuilder;
+use crate::texture;
+use crate::timer::Timer;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct MotionBlurUniform {
+    blend_factor: f32,
+    // Uniforms require 16 byte spacing, so we need padding here
+    _padding: [f32; 3],
+}
+
+/// Blurs motion by blending each frame with the ones before it.
+/// The blend is written to one history texture while the other holds the previous result.
+pub struct MotionBlurPass {
+    /// Weight of the previous frames, 0 turns the pass off.
+    pub strength: f32,
+    /// Seconds for the trail to fade, the blend weight drops as frames take longer.
+    pub decay: f32,
+    history: [texture::Texture; 2],
+    blend_factor: f32,
+    // Counts frames since the history was last cleared, the parity picks the history to read.
+    frame_index: usize,
+    uniform_buffer: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    // One per history texture read, both also binding the input from set_input.
+    bind_groups: Option<[wgpu::BindGroup; 2]>,
+    mesh: mesh::Mesh,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl MotionBlurPass {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Motion Blur Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[MotionBlurUniform {
+                blend_factor: 0.0,
+                _padding: [0.0; 3],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Motion Blur Bind Group Layout"),
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let mesh = mesh::Mesh::quad(
+            device,
+            2.0,
+            2.0,
+            true,
+        ).unwrap();
+
+        let render_pipeline = {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Motion Blur Pipeline Layout"),
+                bind_group_layouts: &[
+                    &layout,
+                ],
+                push_constant_ranges: &[],
+            });
+            let shader = wgpu::ShaderModuleDescriptor {
+                label: Some("Motion Blur Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("motion_blur.wgsl").into()),
+            };
+            PipelineBuilder::new("Motion Blur Render Pipeline")
+                .color_format(texture::Texture::RENDER_FORMAT)
+                .vertex_layouts(&[mesh::MeshVertex::desc()])
+                .shader(shader)
+                .build(device, &pipeline_layout)
+        };
+
+        Self {
+            strength: 0.0,
+            decay: 0.1,
+            history: Self::create_history(device, config),
+            blend_factor: 0.0,
+            frame_index: 0,
+            uniform_buffer,
+            layout,
+            bind_groups: None,
+            mesh,
+            render_pipeline,
+        }
+    }
+
+    fn create_history(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> [texture::Texture; 2] {
+        [
+            texture::Texture::create_render_texture(device, config, "Motion Blur History 0"),
+            texture::Texture::create_render_texture(device, config, "Motion Blur History 1"),
+        ]
+    }
+
+    /// The history textures, written alternately by `render`. Recreated by `resize`.
+    pub fn history(&self) -> &[texture::Texture; 2] {
+        &self.history
+    }
+
+    /// Drops the bind groups holding the old history, so call `set_input` again afterwards.
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        self.history = Self::create_history(device, config);
+        self.bind_groups = None;
+        self.frame_index = 0;
+    }
+
+    /// Blends `input` into the history from now on. Call again when it or the history is recreated.
+    pub fn set_input(&mut self, device: &wgpu::Device, input: &texture::Texture) {
+        let create_bind_group = |history: &texture::Texture| device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Motion Blur Bind Group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&input.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&history.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&input.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        self.bind_groups = Some([
+            create_bind_group(&self.history[0]),
+            create_bind_group(&self.history[1]),
+        ]);
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, timer: &Timer) {
+        // Nothing to blend with on the first frame.
+        self.blend_factor = if self.frame_index == 0 {
+            0.0
+        } else {
+            self.strength * (-timer.delta_secs / self.decay.max(0.001)).exp()
+        };
+        let uniform = MotionBlurUniform {
+            blend_factor: self.blend_factor,
+            _padding: [0.0; 3],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Weight of the history in the last blend.
+    pub fn blend_factor(&self) -> f32 {
+        self.blend_factor
+    }
+
+    /// Blends the input from `set_input` into the history and returns the index of the history
+    /// holding the result, or None while the pass is off and the input should be used as is.
+    pub fn render(&mut self, encoder: &mut wgpu::CommandEncoder) -> Option<usize> {
+        let bind_groups = match &self.bind_groups {
+            Some(bind_groups) if self.strength > 0.0 => bind_groups,
+            _ => {
+                // Start over once turned back on, the history is stale by then.
+                self.frame_index = 0;
+                return None;
+            }
+        };
+
+        let read = self.frame_index % 2;
+        let write = (self.frame_index + 1) % 2;
+        self.frame_index += 1;
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Motion Blur Render Pass"),
+                color_attachments: &[
+                    wgpu::RenderPassColorAttachment {
+                        view: &self.history[write].view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        },
+                    }
+                ],
+                depth_stencil_attachment: None,
+            });
+
+            use crate::mesh::DrawMesh;
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.draw_mesh(&self.mesh, Some(vec![&bind_groups[read]]));
+        }
+
+        Some(write)
+    }
+}
@@ -0,0 +1,44 @@
+// Vertex shader
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(model.position, 1.0);
+    out.tex_coords = model.tex_coords;
+    return out;
+}
+
+// Fragment shader
+
+[[group(0), binding(0)]]
+var t_current: texture_2d<f32>;
+// Blended result of the previous frames.
+[[group(0), binding(1)]]
+var t_history: texture_2d<f32>;
+[[group(0), binding(2)]]
+var s_frame: sampler;
+
+struct MotionBlur {
+    blend_factor: f32;
+};
+[[group(0), binding(3)]]
+var<uniform> motion_blur: MotionBlur;
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let current = textureSample(t_current, s_frame, in.tex_coords);
+    let history = textureSample(t_history, s_frame, in.tex_coords);
+    return mix(current, history, motion_blur.blend_factor);
+}
@@ -0,0 +1,292 @@
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+
+use crate::texture;
+
+/// Parameters of the noise, laid out to match `NoiseParams` in noise.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct NoiseParams {
+    /// Lattice cells across the texture for the first octave, rounded so the texture tiles.
+    pub frequency: f32,
+    pub octaves: u32,
+    pub seed: u32,
+    // Uniforms require 16 byte spacing, so we need padding here
+    pub _pad: u32,
+}
+
+impl Default for NoiseParams {
+    fn default() -> Self {
+        Self {
+            frequency: 4.0,
+            octaves: 4,
+            seed: 0,
+            _pad: 0,
+        }
+    }
+}
+
+/// Fills a tiling texture with fractal Perlin noise in a compute shader,
+/// only when the parameters change.
+pub struct NoiseComputePass {
+    // Shared with the displace passes that sample it.
+    pub output: Arc<texture::Texture>,
+    params: NoiseParams,
+    // None without compute shaders, the same noise is then generated on the CPU.
+    compute: Option<NoiseCompute>,
+    size: u32,
+    // Set when `output` doesn't match `params` yet.
+    dirty: bool,
+}
+
+struct NoiseCompute {
+    params_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl NoiseComputePass {
+    const WORKGROUP_SIZE: u32 = 8;
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    /// Generates the noise on the CPU unless `use_compute` is set,
+    /// which requires compute shaders and storage textures.
+    pub fn new(device: &wgpu::Device, size: u32, params: NoiseParams, use_compute: bool) -> Self {
+        let output = Arc::new(Self::create_output(device, size, use_compute));
+        let compute = if use_compute {
+            Some(Self::create_compute(device, &output, params))
+        } else {
+            None
+        };
+
+        Self {
+            output,
+            params,
+            compute,
+            size,
+            dirty: true,
+        }
+    }
+
+    fn create_compute(device: &wgpu::Device, output: &texture::Texture, params: NoiseParams) -> NoiseCompute {
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Noise Params Buffer"),
+            contents: bytemuck::cast_slice(&[params]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Noise Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: Self::FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Noise Bind Group"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&output.view),
+                },
+            ],
+        });
+
+        let pipeline = {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Noise Pipeline Layout"),
+                bind_group_layouts: &[
+                    &layout,
+                ],
+                push_constant_ranges: &[],
+            });
+            let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some("Noise Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("noise.wgsl").into()),
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Noise Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "cs_main",
+            })
+        };
+
+        NoiseCompute {
+            params_buffer,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    fn create_output(device: &wgpu::Device, size: u32, use_compute: bool) -> texture::Texture {
+        let usage = if use_compute {
+            wgpu::TextureUsages::STORAGE_BINDING
+        } else {
+            wgpu::TextureUsages::COPY_DST
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Noise Texture"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: usage | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // The noise tiles, so it can be sampled past its edges.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Noise Sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        texture::Texture {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    pub fn params(&self) -> NoiseParams {
+        self.params
+    }
+
+    /// Regenerates the noise with `params` on the next `dispatch`.
+    pub fn set_params(&mut self, queue: &wgpu::Queue, params: NoiseParams) {
+        if params == self.params {
+            return;
+        }
+        self.params = params;
+        if let Some(compute) = &self.compute {
+            queue.write_buffer(&compute.params_buffer, 0, bytemuck::cast_slice(&[params]));
+        }
+        self.dirty = true;
+    }
+
+    /// Fills `output`, must be encoded before anything samples it. Does nothing while it's up to date.
+    pub fn dispatch(&mut self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        let compute = match &self.compute {
+            Some(compute) => compute,
+            None => {
+                self.fill_cpu(queue);
+                return;
+            }
+        };
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Noise Compute Pass"),
+        });
+        compute_pass.set_pipeline(&compute.pipeline);
+        compute_pass.set_bind_group(0, &compute.bind_group, &[]);
+        let workgroups = self.size.div_ceil(Self::WORKGROUP_SIZE);
+        compute_pass.dispatch(workgroups, workgroups, 1);
+    }
+
+    /// Same as `cs_main` in noise.wgsl, texel by texel.
+    fn fill_cpu(&self, queue: &wgpu::Queue) {
+        let size = self.size;
+        let params = self.params;
+        let pixels = (0..size * size)
+            .flat_map(|i| {
+                let uv = (
+                    ((i % size) as f32 + 0.5) / size as f32,
+                    ((i / size) as f32 + 0.5) / size as f32,
+                );
+                let mut period = (params.frequency.round() as i32).max(1);
+                let mut amplitude = 1.0;
+                let mut total = 0.0;
+                let mut value = 0.0;
+                for octave in 0..params.octaves.max(1) {
+                    let p = (uv.0 * period as f32, uv.1 * period as f32);
+                    value += perlin(p, period, params.seed.wrapping_add(octave)) * amplitude;
+                    total += amplitude;
+                    amplitude *= 0.5;
+                    period *= 2;
+                }
+                let n = ((value / total * 0.7 + 0.5).clamp(0.0, 1.0) * 255.0).round() as u8;
+                [n, n, n, 255]
+            })
+            .collect::<Vec<_>>();
+
+        queue.write_texture(
+            self.output.texture.as_image_copy(),
+            &pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(4 * size),
+                rows_per_image: std::num::NonZeroU32::new(size),
+            },
+            wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+// CPU versions of the functions in noise.wgsl, for devices without compute shaders.
+
+fn hash(seed: u32) -> u32 {
+    let state = seed.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+fn gradient(cell: (i32, i32), period: i32, seed: u32) -> (f32, f32) {
+    let wrapped = (cell.0.rem_euclid(period), cell.1.rem_euclid(period));
+    let h = hash(hash(wrapped.0 as u32 ^ seed) ^ wrapped.1 as u32);
+    let angle = h as f32 / 4294967296.0 * std::f32::consts::TAU;
+    (angle.cos(), angle.sin())
+}
+
+fn perlin(p: (f32, f32), period: i32, seed: u32) -> f32 {
+    let cell = (p.0.floor(), p.1.floor());
+    let f = (p.0 - cell.0, p.1 - cell.1);
+    let i = (cell.0 as i32, cell.1 as i32);
+    let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let u = (fade(f.0), fade(f.1));
+    let corner = |dx: i32, dy: i32| {
+        let g = gradient((i.0 + dx, i.1 + dy), period, seed);
+        g.0 * (f.0 - dx as f32) + g.1 * (f.1 - dy as f32)
+    };
+    let mix = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    mix(mix(corner(0, 0), corner(1, 0), u.0), mix(corner(0, 1), corner(1, 1), u.0), u.1)
+}
@@ -0,0 +1,67 @@
+// Compute shader
+
+struct NoiseParams {
+    frequency: f32;
+    octaves: u32;
+    seed: u32;
+};
+[[group(0), binding(0)]]
+var<uniform> params: NoiseParams;
+
+[[group(0), binding(1)]]
+var output: texture_storage_2d<rgba8unorm, write>;
+
+// PCG hash.
+fn hash(seed: u32) -> u32 {
+    let state = seed * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Unit gradient at a lattice point, wrapped every `period` cells so the texture tiles.
+fn gradient(cell: vec2<i32>, period: i32, seed: u32) -> vec2<f32> {
+    let wrapped = ((cell % period) + period) % period;
+    let h = hash(hash(u32(wrapped.x) ^ seed) ^ u32(wrapped.y));
+    let angle = f32(h) / 4294967296.0 * 6.28318530718;
+    return vec2<f32>(cos(angle), sin(angle));
+}
+
+// Perlin noise in roughly [-0.7, 0.7].
+fn perlin(p: vec2<f32>, period: i32, seed: u32) -> f32 {
+    let cell = floor(p);
+    let f = p - cell;
+    let i = vec2<i32>(cell);
+    // Quintic fade, so the noise has continuous second derivatives.
+    let u = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+    let a = dot(gradient(i, period, seed), f);
+    let b = dot(gradient(i + vec2<i32>(1, 0), period, seed), f - vec2<f32>(1.0, 0.0));
+    let c = dot(gradient(i + vec2<i32>(0, 1), period, seed), f - vec2<f32>(0.0, 1.0));
+    let d = dot(gradient(i + vec2<i32>(1, 1), period, seed), f - vec2<f32>(1.0, 1.0));
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+[[stage(compute), workgroup_size(8, 8)]]
+fn cs_main(
+    [[builtin(global_invocation_id)]] id: vec3<u32>,
+) {
+    let size = textureDimensions(output);
+    let texel = vec2<i32>(id.xy);
+    if (texel.x >= size.x || texel.y >= size.y) {
+        return;
+    }
+
+    let uv = (vec2<f32>(texel) + 0.5) / vec2<f32>(size);
+    var period = max(i32(round(params.frequency)), 1);
+    var amplitude = 1.0;
+    var total = 0.0;
+    var value = 0.0;
+    for (var octave = 0u; octave < max(params.octaves, 1u); octave = octave + 1u) {
+        value = value + perlin(uv * f32(period), period, params.seed + octave) * amplitude;
+        total = total + amplitude;
+        amplitude = amplitude * 0.5;
+        period = period * 2;
+    }
+
+    let n = clamp(value / total * 0.7 + 0.5, 0.0, 1.0);
+    textureStore(output, texel, vec4<f32>(n, n, n, 1.0));
+}
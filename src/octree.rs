@@ -0,0 +1,276 @@
+use cgmath::Vector3;
+
+use crate::camera::{Frustum, Ray};
+
+/// Axis aligned bounding box in world space.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    /// The box around a sphere.
+    pub fn from_sphere(center: Vector3<f32>, radius: f32) -> Self {
+        let extent = Vector3::new(radius, radius, radius);
+        Self::new(center - extent, center + extent)
+    }
+
+    /// The smallest box around all of `boxes`, or None if there are none.
+    pub fn union<'a>(boxes: impl IntoIterator<Item = &'a Aabb>) -> Option<Self> {
+        boxes.into_iter().fold(None, |union, aabb| match union {
+            None => Some(*aabb),
+            Some(union) => Some(Self::new(
+                Vector3::new(union.min.x.min(aabb.min.x), union.min.y.min(aabb.min.y), union.min.z.min(aabb.min.z)),
+                Vector3::new(union.max.x.max(aabb.max.x), union.max.y.max(aabb.max.y), union.max.z.max(aabb.max.z)),
+            )),
+        })
+    }
+
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn contains(&self, other: &Aabb) -> bool {
+        self.min.x <= other.min.x && self.min.y <= other.min.y && self.min.z <= other.min.z
+            && self.max.x >= other.max.x && self.max.y >= other.max.y && self.max.z >= other.max.z
+    }
+
+    /// Distance along the ray to where it enters the box, 0 when it starts inside.
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<f32> {
+        // Slab test, a zero direction component divides into infinities which compare correctly.
+        let mut near = 0.0f32;
+        let mut far = f32::INFINITY;
+        for axis in 0..3 {
+            let inv = 1.0 / ray.direction[axis];
+            let t0 = (self.min[axis] - ray.origin[axis]) * inv;
+            let t1 = (self.max[axis] - ray.origin[axis]) * inv;
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+        }
+        if near <= far { Some(near) } else { None }
+    }
+
+    // Octant i takes the upper half on the axes whose bit is set (x = 1, y = 2, z = 4).
+    fn octant(&self, i: usize) -> Self {
+        let center = self.center();
+        let pick = |bit: usize, axis: usize| {
+            if i & bit == 0 { (self.min[axis], center[axis]) } else { (center[axis], self.max[axis]) }
+        };
+        let (x, y, z) = (pick(1, 0), pick(2, 1), pick(4, 2));
+        Self::new(Vector3::new(x.0, y.0, z.0), Vector3::new(x.1, y.1, z.1))
+    }
+}
+
+#[derive(Debug)]
+struct OctNode {
+    bounds: Aabb,
+    // Entries that don't fit in a single child, or all of them at the deepest level.
+    items: Vec<(usize, Aabb)>,
+    children: Option<Box<[OctNode; 8]>>,
+}
+
+impl OctNode {
+    fn new(bounds: Aabb) -> Self {
+        Self {
+            bounds,
+            items: Vec::new(),
+            children: None,
+        }
+    }
+
+    fn insert(&mut self, index: usize, aabb: &Aabb, depth: u32) {
+        if depth > 0 {
+            let bounds = self.bounds;
+            let children = self.children.get_or_insert_with(|| {
+                Box::new([0, 1, 2, 3, 4, 5, 6, 7].map(|i| OctNode::new(bounds.octant(i))))
+            });
+            if let Some(child) = children.iter_mut().find(|child| child.bounds.contains(aabb)) {
+                child.insert(index, aabb, depth - 1);
+                return;
+            }
+        }
+        self.items.push((index, *aabb));
+    }
+
+    /// Passes the entries of every node whose bounds `overlaps` accepts to `visit`.
+    fn visit(&self, overlaps: &impl Fn(&Aabb) -> bool, visit: &mut impl FnMut(usize, &Aabb)) {
+        if !overlaps(&self.bounds) {
+            return;
+        }
+        for (index, aabb) in &self.items {
+            visit(*index, aabb);
+        }
+        if let Some(children) = &self.children {
+            for child in children.iter() {
+                child.visit(overlaps, visit);
+            }
+        }
+    }
+}
+
+/// Splits space into nested octants so ray and frustum queries only test the entries
+/// in octants they reach. Entries are indices with their bounds, e.g. instances.
+#[derive(Debug)]
+pub struct OctTree {
+    root: OctNode,
+    bounds: Aabb,
+    max_depth: u32,
+    // Entries reaching outside `bounds`, tested on every query.
+    outside: Vec<(usize, Aabb)>,
+}
+
+impl OctTree {
+    pub fn new(bounds: Aabb, max_depth: u32) -> Self {
+        Self {
+            root: OctNode::new(bounds),
+            bounds,
+            max_depth,
+            outside: Vec::new(),
+        }
+    }
+
+    /// Builds a tree just large enough for `entries`.
+    pub fn from_entries(entries: &[(usize, Aabb)], max_depth: u32) -> Self {
+        let bounds = Aabb::union(entries.iter().map(|(_, aabb)| aabb))
+            .unwrap_or_else(|| Aabb::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0)));
+        let mut tree = Self::new(bounds, max_depth);
+        for (index, aabb) in entries {
+            tree.insert(*index, aabb);
+        }
+        tree
+    }
+
+    #[allow(dead_code)]
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    pub fn insert(&mut self, index: usize, aabb: &Aabb) {
+        if self.bounds.contains(aabb) {
+            self.root.insert(index, aabb, self.max_depth);
+        } else {
+            self.outside.push((index, *aabb));
+        }
+    }
+
+    /// Indices whose bounds the ray passes through, in no particular order.
+    pub fn query_ray(&self, ray: &Ray) -> Vec<usize> {
+        self.query(|aabb| aabb.intersect_ray(ray).is_some())
+    }
+
+    /// Indices whose bounds may be inside `frustum`, in no particular order.
+    /// Boxes just outside a corner of the frustum can be reported too.
+    pub fn query_frustum(&self, frustum: &Frustum) -> Vec<usize> {
+        self.query(|aabb| frustum.intersects_aabb(aabb.min, aabb.max))
+    }
+
+    fn query(&self, overlaps: impl Fn(&Aabb) -> bool) -> Vec<usize> {
+        let mut hits = Vec::new();
+        let mut test = |index, aabb: &Aabb| {
+            if overlaps(aabb) {
+                hits.push(index);
+            }
+        };
+        for (index, aabb) in &self.outside {
+            test(*index, aabb);
+        }
+        self.root.visit(&overlaps, &mut test);
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{InnerSpace, Matrix4, Point3};
+
+    // A 6x6x6 grid of unit boxes, plus two reaching outside the tree's bounds.
+    fn entries() -> Vec<(usize, Aabb)> {
+        let mut entries = Vec::new();
+        for x in 0..6 {
+            for y in 0..6 {
+                for z in 0..6 {
+                    let center = Vector3::new(x as f32, y as f32, z as f32) * 3.0;
+                    entries.push((entries.len(), Aabb::from_sphere(center, 0.5)));
+                }
+            }
+        }
+        entries.push((entries.len(), Aabb::from_sphere(Vector3::new(40.0, 7.5, 7.5), 1.0)));
+        entries.push((entries.len(), Aabb::new(Vector3::new(-10.0, 7.0, 7.0), Vector3::new(1.0, 8.0, 8.0))));
+        entries
+    }
+
+    fn tree(entries: &[(usize, Aabb)]) -> OctTree {
+        let mut tree = OctTree::new(Aabb::new(Vector3::new(-1.0, -1.0, -1.0), Vector3::new(16.0, 16.0, 16.0)), 4);
+        for (index, aabb) in entries {
+            tree.insert(*index, aabb);
+        }
+        tree
+    }
+
+    fn sorted(mut indices: Vec<usize>) -> Vec<usize> {
+        indices.sort_unstable();
+        indices
+    }
+
+    #[test]
+    fn query_ray_matches_brute_force() {
+        let entries = entries();
+        let tree = tree(&entries);
+        let rays = [
+            (Point3::new(-20.0, 7.5, 7.5), Vector3::new(1.0, 0.0, 0.0)),
+            (Point3::new(-5.0, -5.0, -5.0), Vector3::new(1.0, 1.0, 1.0)),
+            (Point3::new(6.0, 30.0, 3.0), Vector3::new(0.0, -1.0, 0.0)),
+            (Point3::new(50.0, 7.5, 7.5), Vector3::new(-1.0, 0.0, 0.0)),
+            (Point3::new(7.5, 7.5, 7.5), Vector3::new(0.3, -0.2, 1.0)),
+        ];
+        for (origin, direction) in rays {
+            let ray = Ray { origin, direction: direction.normalize() };
+            let expected: Vec<usize> = entries
+                .iter()
+                .filter(|(_, aabb)| aabb.intersect_ray(&ray).is_some())
+                .map(|(index, _)| *index)
+                .collect();
+            assert!(!expected.is_empty());
+            assert_eq!(sorted(tree.query_ray(&ray)), expected, "ray from {:?}", origin);
+        }
+    }
+
+    #[test]
+    fn query_frustum_matches_brute_force() {
+        let entries = entries();
+        let tree = tree(&entries);
+        let targets = [Point3::new(7.5, 7.5, 7.5), Point3::new(0.0, 0.0, 0.0), Point3::new(40.0, 7.5, 7.5)];
+        for target in targets {
+            let view = Matrix4::look_at_rh(Point3::new(-15.0, 20.0, 25.0), target, Vector3::unit_y());
+            let proj = cgmath::perspective(cgmath::Deg(30.0), 1.5, 0.1, 100.0);
+            let frustum = Frustum::from_matrix(proj * view);
+            let expected: Vec<usize> = entries
+                .iter()
+                .filter(|(_, aabb)| frustum.intersects_aabb(aabb.min, aabb.max))
+                .map(|(index, _)| *index)
+                .collect();
+            assert!(!expected.is_empty() && expected.len() < entries.len());
+            assert_eq!(sorted(tree.query_frustum(&frustum)), expected, "looking at {:?}", target);
+        }
+    }
+
+    #[test]
+    fn entries_outside_bounds_are_found() {
+        let entries = entries();
+        let tree = tree(&entries);
+        let far = entries.len() - 2;
+        let straddling = entries.len() - 1;
+
+        let ray = Ray { origin: Point3::new(40.0, 30.0, 7.5), direction: Vector3::new(0.0, -1.0, 0.0) };
+        assert_eq!(tree.query_ray(&ray), vec![far]);
+
+        let ray = Ray { origin: Point3::new(-8.0, 7.5, -20.0), direction: Vector3::new(0.0, 0.0, 1.0) };
+        assert_eq!(tree.query_ray(&ray), vec![straddling]);
+    }
+}
@@ -0,0 +1,393 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use wgpu::util::DeviceExt;
+
+use crate::mesh::Vertex;
+use crate::pipeline::PipelineBuilder;
+use crate::texture;
+use crate::timer::Timer;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Particle {
+    position: [f32; 3],
+    // Seconds left to live, dead once it reaches zero.
+    lifetime: f32,
+    velocity: [f32; 3],
+    // Seconds until the first spawn.
+    delay: f32,
+}
+
+impl Vertex for Particle {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        // Read once per billboard, the velocity is only used by the compute shader.
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<Particle>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 7]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32,
+                },
+            ],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct EmitterUniform {
+    dt: f32,
+    capacity: u32,
+    // Oldest slot of the ring, the compute shader walks the ring from here.
+    head: u32,
+    // Uniforms require 16 byte spacing, so we need padding here
+    _padding: u32,
+}
+
+/// Particle storage used as a ring. New particles go into the oldest slots, but only once the
+/// particles there have died, which the ring tracks by mirroring the compute shader's clock.
+struct ParticleRing {
+    buffer: wgpu::Buffer,
+    // Next slot to write, which holds the oldest particle.
+    head: u32,
+    capacity: u32,
+    // Simulated time at which each slot's particle dies.
+    expiry: Vec<f32>,
+    // Seconds the compute shader has simulated so far.
+    sim_time: f32,
+}
+
+impl ParticleRing {
+    fn new(device: &wgpu::Device, particles: &[Particle]) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Particle Buffer"),
+            contents: bytemuck::cast_slice(particles),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        Self {
+            buffer,
+            head: 0,
+            capacity: particles.len() as u32,
+            expiry: particles.iter().map(Self::lifespan).collect(),
+            sim_time: 0.0,
+        }
+    }
+
+    // Seconds from being written until the compute shader lets a particle die.
+    fn lifespan(particle: &Particle) -> f32 {
+        particle.delay.max(0.0) + particle.lifetime.max(0.0)
+    }
+
+    /// Call with the same time step the compute shader simulates.
+    fn advance(&mut self, dt: f32) {
+        self.sim_time += dt;
+    }
+
+    /// Dead slots from `head` on, the most particles `emit` can write.
+    fn free_count(&self) -> u32 {
+        // A little slack, the shader counts the lifetime down rather than comparing times.
+        const SLACK: f32 = 0.01;
+        let (tail, wrapped) = self.expiry.split_at(self.head as usize);
+        wrapped.iter().chain(tail)
+            .take_while(|&&expiry| expiry + SLACK <= self.sim_time)
+            .count() as u32
+    }
+
+    /// Writes `particles` starting at `head`, wrapping around the end of the buffer.
+    /// Live particles are never overwritten, so only as many as there are free slots
+    /// are written and the rest are dropped. Returns how many were written.
+    fn emit(&mut self, queue: &wgpu::Queue, particles: &[Particle]) -> u32 {
+        let particles = &particles[..particles.len().min(self.free_count() as usize)];
+        for (i, particle) in particles.iter().enumerate() {
+            let slot = (self.head as usize + i) % self.capacity as usize;
+            self.expiry[slot] = self.sim_time + Self::lifespan(particle);
+        }
+        let stride = std::mem::size_of::<Particle>() as wgpu::BufferAddress;
+        let (tail, wrapped) = particles.split_at(particles.len().min((self.capacity - self.head) as usize));
+        if !tail.is_empty() {
+            queue.write_buffer(&self.buffer, self.head as wgpu::BufferAddress * stride, bytemuck::cast_slice(tail));
+        }
+        if !wrapped.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(wrapped));
+        }
+        self.head = (self.head + particles.len() as u32) % self.capacity;
+        particles.len() as u32
+    }
+}
+
+/// Simulates particles in a compute shader and draws them as camera facing billboards.
+pub struct ParticlePass {
+    pub visible: bool,
+    pub emitter_pos: cgmath::Point3<f32>,
+    /// Seconds each particle lives for.
+    pub lifetime: f32,
+    /// Initial speed of a particle leaving the emitter.
+    pub speed: f32,
+    /// Particles emitted per second, fewer while the ring has no free slots.
+    pub rate: f32,
+    // Fraction of a particle owed to the next frame's emission.
+    pending: f32,
+    // Seeded by the caller, so emission is reproducible.
+    rng: StdRng,
+    ring: ParticleRing,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    update_pipeline: wgpu::ComputePipeline,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl ParticlePass {
+    const WORKGROUP_SIZE: u32 = 64;
+
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera_layout: &wgpu::BindGroupLayout,
+        capacity: u32,
+        seed: u64,
+    ) -> Self {
+        let lifetime = 2.0;
+        let speed = 4.0;
+
+        // Every slot starts out dead, free for the emitter.
+        let particles = vec![bytemuck::Zeroable::zeroed(); capacity as usize];
+        let ring = ParticleRing::new(device, &particles);
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle Uniform Buffer"),
+            size: std::mem::size_of::<EmitterUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Particle Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Particle Bind Group"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: ring.buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let update_pipeline = {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Particle Update Pipeline Layout"),
+                bind_group_layouts: &[
+                    &layout,
+                ],
+                push_constant_ranges: &[],
+            });
+            let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some("Particle Update Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("particle_update.wgsl").into()),
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Particle Update Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "cs_main",
+            })
+        };
+
+        let render_pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Particle Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+            let shader = wgpu::ShaderModuleDescriptor {
+                label: Some("Particle Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("particle.wgsl").into()),
+            };
+            // Billboards fade out, so blend them over the scene without writing depth.
+            PipelineBuilder::new("Particle Render Pipeline")
+                .color_format(config.format)
+                .depth_format(texture::Texture::DEPTH_FORMAT)
+                .depth_write_enabled(false)
+                .vertex_layouts(&[Particle::desc()])
+                .cull_mode(None)
+                .blend(wgpu::BlendState::ALPHA_BLENDING)
+                .shader(shader)
+                .build(device, &layout)
+        };
+
+        Self {
+            visible: false,
+            emitter_pos: cgmath::Point3::new(0.0, 0.0, 0.0),
+            lifetime,
+            speed,
+            // Half the ring at the default lifetime, leaving the rest for bursts.
+            rate: capacity as f32 / (2.0 * lifetime),
+            pending: 0.0,
+            rng: StdRng::seed_from_u64(seed),
+            ring,
+            uniform_buffer,
+            bind_group,
+            update_pipeline,
+            render_pipeline,
+        }
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.ring.capacity
+    }
+
+    fn spawn(&mut self, direction: cgmath::Vector3<f32>) -> Particle {
+        let direction = cgmath::InnerSpace::normalize(direction);
+        Particle {
+            position: self.emitter_pos.into(),
+            lifetime: self.lifetime,
+            velocity: (direction * self.speed * (0.5 + 0.5 * self.rng.gen::<f32>())).into(),
+            delay: 0.0,
+        }
+    }
+
+    /// Spawns up to `count` particles at the emitter at once, as many as there are free slots.
+    /// Returns how many were spawned.
+    pub fn burst(&mut self, queue: &wgpu::Queue, count: u32) -> u32 {
+        let particles = (0..count)
+            .map(|_| {
+                // Any direction on the upper hemisphere.
+                let direction = cgmath::Vector3::new(
+                    self.rng.gen::<f32>() * 2.0 - 1.0,
+                    self.rng.gen::<f32>(),
+                    self.rng.gen::<f32>() * 2.0 - 1.0,
+                );
+                self.spawn(direction)
+            })
+            .collect::<Vec<_>>();
+        self.ring.emit(queue, &particles)
+    }
+
+    /// Emits this frame's share of `rate` and uploads the time step the compute shader runs with.
+    pub fn update(&mut self, queue: &wgpu::Queue, timer: &Timer) {
+        if self.visible {
+            self.pending += self.rate * timer.delta_secs;
+            let count = self.pending.floor();
+            self.pending -= count;
+            let particles = (0..count as u32)
+                .map(|_| {
+                    // Heading up in a random direction within a cone.
+                    let direction = cgmath::Vector3::new(
+                        self.rng.gen::<f32>() * 2.0 - 1.0,
+                        2.0,
+                        self.rng.gen::<f32>() * 2.0 - 1.0,
+                    );
+                    self.spawn(direction)
+                })
+                .collect::<Vec<_>>();
+            self.ring.emit(queue, &particles);
+            // The compute shader only runs while visible.
+            self.ring.advance(timer.delta_secs);
+        }
+
+        let uniform = EmitterUniform {
+            dt: timer.delta_secs,
+            capacity: self.ring.capacity,
+            head: self.ring.head,
+            _padding: 0,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Steps the simulation, must be encoded before `render`.
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.visible {
+            return;
+        }
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Particle Compute Pass"),
+        });
+        compute_pass.set_pipeline(&self.update_pipeline);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.dispatch(self.ring.capacity.div_ceil(Self::WORKGROUP_SIZE), 1, 1);
+    }
+
+    pub fn render(
+        &self,
+        view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+        depth_texture: &texture::Texture,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        if !self.visible {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Particle Render Pass"),
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }
+            ],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.ring.buffer.slice(..));
+        // Six vertices per billboard, one instance per particle.
+        render_pass.draw(0..6, 0..self.ring.capacity);
+    }
+}
@@ -0,0 +1,66 @@
+// Vertex shader
+
+struct Camera {
+    view_pos: vec4<f32>;
+    view_proj: mat4x4<f32>;
+    right: vec4<f32>;
+    up: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+struct ParticleInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] lifetime: f32;
+    [[location(2)]] delay: f32;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] corner: vec2<f32>;
+    [[location(1)]] lifetime: f32;
+};
+
+let SIZE: f32 = 0.05;
+
+[[stage(vertex)]]
+fn vs_main(
+    [[builtin(vertex_index)]] vertex_index: u32,
+    particle: ParticleInput,
+) -> VertexOutput {
+    // Two triangles making up a quad.
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+
+    var out: VertexOutput;
+    out.corner = corner;
+    out.lifetime = particle.lifetime;
+    if (particle.delay > 0.0 || particle.lifetime <= 0.0) {
+        // Not alive, collapse the quad so nothing is rasterized.
+        out.clip_position = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+        return out;
+    }
+    let offset = (camera.right.xyz * corner.x + camera.up.xyz * corner.y) * SIZE;
+    out.clip_position = camera.view_proj * vec4<f32>(particle.position + offset, 1.0);
+    return out;
+}
+
+// Fragment shader
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let distance = length(in.corner);
+    if (distance > 1.0) {
+        discard;
+    }
+    // Soft round particles that fade out over their last half second.
+    let alpha = (1.0 - distance) * clamp(in.lifetime * 2.0, 0.0, 1.0);
+    return vec4<f32>(1.0, 0.6, 0.2, alpha);
+}
@@ -0,0 +1,50 @@
+// Compute shader
+
+struct Emitter {
+    dt: f32;
+    capacity: u32;
+    // Oldest slot of the ring.
+    head: u32;
+};
+[[group(0), binding(0)]]
+var<uniform> emitter: Emitter;
+
+// Matches the Particle layout in particle.rs.
+struct Particle {
+    position: vec3<f32>;
+    // Seconds left to live, dead once it reaches zero.
+    lifetime: f32;
+    velocity: vec3<f32>;
+    // Seconds until the particle appears.
+    delay: f32;
+};
+
+struct Particles {
+    particles: array<Particle>;
+};
+[[group(0), binding(1)]]
+var<storage, read_write> buffer: Particles;
+
+let GRAVITY: f32 = 9.8;
+
+[[stage(compute), workgroup_size(64)]]
+fn cs_main(
+    [[builtin(global_invocation_id)]] id: vec3<u32>,
+) {
+    if (id.x >= emitter.capacity) {
+        return;
+    }
+    // Walk the ring from its oldest particle, matching the order particles are emitted in.
+    let index = (emitter.head + id.x) % emitter.capacity;
+
+    var particle = buffer.particles[index];
+    if (particle.delay > 0.0) {
+        particle.delay = particle.delay - emitter.dt;
+    } else if (particle.lifetime > 0.0) {
+        // Dead particles stay dead until the CPU emits into their slot.
+        particle.lifetime = particle.lifetime - emitter.dt;
+        particle.velocity.y = particle.velocity.y - GRAVITY * emitter.dt;
+        particle.position = particle.position + particle.velocity * emitter.dt;
+    }
+    buffer.particles[index] = particle;
+}
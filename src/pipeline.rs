@@ -0,0 +1,184 @@
+use crate::texture;
+
+/// Creates a pipeline layout. With a `push_constant_size` in bytes, it also declares a push constant
+/// range visible to the vertex and fragment stages, which requires `Features::PUSH_CONSTANTS`.
+pub fn create_pipeline_layout(
+    device: &wgpu::Device,
+    label: &str,
+    bind_group_layouts: &[&wgpu::BindGroupLayout],
+    push_constant_size: Option<u32>,
+) -> wgpu::PipelineLayout {
+    let push_constant_ranges = push_constant_size
+        .map(|size| wgpu::PushConstantRange {
+            stages: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            range: 0..size,
+        })
+        .into_iter()
+        .collect::<Vec<_>>();
+    device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts,
+        push_constant_ranges: &push_constant_ranges,
+    })
+}
+
+/// Builds a render pipeline for a shader with `vs_main` and `fs_main` entry points.
+/// Defaults to a triangle list drawn into `texture::Texture::RENDER_FORMAT` without depth,
+/// with back face culling and no blending. Blending and write mask apply to every color target.
+pub struct PipelineBuilder<'a> {
+    label: &'a str,
+    color_formats: Vec<wgpu::TextureFormat>,
+    depth_format: Option<wgpu::TextureFormat>,
+    depth_write_enabled: bool,
+    stencil: wgpu::StencilState,
+    vertex_layouts: &'a [wgpu::VertexBufferLayout<'a>],
+    shader: Option<wgpu::ShaderModuleDescriptor<'a>>,
+    topology: wgpu::PrimitiveTopology,
+    polygon_mode: wgpu::PolygonMode,
+    cull_mode: Option<wgpu::Face>,
+    blend: wgpu::BlendState,
+    color_writes: wgpu::ColorWrites,
+    sample_count: u32,
+}
+
+impl<'a> PipelineBuilder<'a> {
+    pub fn new(label: &'a str) -> Self {
+        Self {
+            label,
+            color_formats: vec![texture::Texture::RENDER_FORMAT],
+            depth_format: None,
+            depth_write_enabled: true,
+            stencil: wgpu::StencilState::default(),
+            vertex_layouts: &[],
+            shader: None,
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            cull_mode: Some(wgpu::Face::Back),
+            blend: wgpu::BlendState::REPLACE,
+            color_writes: wgpu::ColorWrites::ALL,
+            sample_count: 1,
+        }
+    }
+
+    pub fn color_format(mut self, color_format: wgpu::TextureFormat) -> Self {
+        self.color_formats = vec![color_format];
+        self
+    }
+
+    /// Renders to multiple targets, in the order of `fs_main`'s output locations.
+    pub fn color_formats(mut self, color_formats: &[wgpu::TextureFormat]) -> Self {
+        self.color_formats = color_formats.to_vec();
+        self
+    }
+
+    pub fn depth_format(mut self, depth_format: wgpu::TextureFormat) -> Self {
+        self.depth_format = Some(depth_format);
+        self
+    }
+
+    /// Only used with a depth format.
+    pub fn depth_write_enabled(mut self, depth_write_enabled: bool) -> Self {
+        self.depth_write_enabled = depth_write_enabled;
+        self
+    }
+
+    /// Only used with a depth format that has a stencil aspect.
+    pub fn stencil(mut self, stencil: wgpu::StencilState) -> Self {
+        self.stencil = stencil;
+        self
+    }
+
+    pub fn vertex_layouts(mut self, vertex_layouts: &'a [wgpu::VertexBufferLayout<'a>]) -> Self {
+        self.vertex_layouts = vertex_layouts;
+        self
+    }
+
+    pub fn shader(mut self, shader: wgpu::ShaderModuleDescriptor<'a>) -> Self {
+        self.shader = Some(shader);
+        self
+    }
+
+    pub fn topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// Anything but Fill requires Features::POLYGON_MODE_LINE or POLYGON_MODE_POINT.
+    #[allow(dead_code)]
+    pub fn polygon_mode(mut self, polygon_mode: wgpu::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn blend(mut self, blend: wgpu::BlendState) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn color_writes(mut self, color_writes: wgpu::ColorWrites) -> Self {
+        self.color_writes = color_writes;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    /// Panics if no shader was set.
+    pub fn build(self, device: &wgpu::Device, layout: &wgpu::PipelineLayout) -> wgpu::RenderPipeline {
+        let shader = device.create_shader_module(&self.shader.expect("PipelineBuilder requires a shader"));
+        let targets = self.color_formats.iter()
+            .map(|&format| wgpu::ColorTargetState {
+                format,
+                blend: Some(self.blend),
+                write_mask: self.color_writes,
+            })
+            .collect::<Vec<_>>();
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(self.label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: self.vertex_layouts,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &targets,
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: self.topology,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: self.cull_mode,
+                polygon_mode: self.polygon_mode,
+                // Requires Features::DEPTH_CLIP_CONTROL
+                unclipped_depth: false,
+                // Requires Features::CONSERVATIVE_RASTERIZATION
+                conservative: false,
+            },
+            depth_stencil: self.depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: self.depth_write_enabled,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: self.stencil,
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: self.sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+}
@@ -0,0 +1,86 @@
+use crate::texture;
+use crate::timer::Timer;
+
+pub trait PostProcess {
+    /// Binds `input` for `apply`. The stack only calls this when its textures change.
+    fn create_bind_group(&self, device: &wgpu::Device, input: &texture::Texture) -> wgpu::BindGroup;
+
+    fn apply(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::BindGroup,
+        output: &texture::Texture,
+    );
+
+    /// Uploads anything that changes per frame, before `apply`.
+    fn update(&self, _queue: &wgpu::Queue, _timer: &Timer) {}
+}
+
+/// Runs post-processing passes in order, ping-ponging between two render textures.
+pub struct PostProcessStack {
+    // Each pass with its bind group for the texture it reads from.
+    passes: Vec<(Box<dyn PostProcess>, wgpu::BindGroup)>,
+    ping: texture::Texture,
+    pong: texture::Texture,
+}
+
+impl PostProcessStack {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        Self {
+            passes: Vec::new(),
+            ping: texture::Texture::create_render_texture(device, config, "Post Process Ping"),
+            pong: texture::Texture::create_render_texture(device, config, "Post Process Pong"),
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        self.ping = texture::Texture::create_render_texture(device, config, "Post Process Ping");
+        self.pong = texture::Texture::create_render_texture(device, config, "Post Process Pong");
+        for (i, (pass, bind_group)) in self.passes.iter_mut().enumerate() {
+            let input = if i.is_multiple_of(2) { &self.ping } else { &self.pong };
+            *bind_group = pass.create_bind_group(device, input);
+        }
+    }
+
+    pub fn push(&mut self, device: &wgpu::Device, pass: Box<dyn PostProcess>) {
+        let input = self.output();
+        let bind_group = pass.create_bind_group(device, input);
+        self.passes.push((pass, bind_group));
+    }
+
+    /// Removes the last pass pushed.
+    pub fn pop(&mut self) -> Option<Box<dyn PostProcess>> {
+        self.passes.pop().map(|(pass, _)| pass)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, timer: &Timer) {
+        for (pass, _) in &self.passes {
+            pass.update(queue, timer);
+        }
+    }
+
+    /// The texture the scene should be rendered into.
+    pub fn input(&self) -> &texture::Texture {
+        &self.ping
+    }
+
+    /// The texture holding the result after `apply`.
+    pub fn output(&self) -> &texture::Texture {
+        if self.passes.len().is_multiple_of(2) { &self.ping } else { &self.pong }
+    }
+
+    /// Applies every pass and returns the texture holding the result.
+    pub fn apply(&self, encoder: &mut wgpu::CommandEncoder) -> &texture::Texture {
+        let mut input = &self.ping;
+        let mut output = &self.pong;
+        for (pass, bind_group) in &self.passes {
+            pass.apply(encoder, bind_group, output);
+            std::mem::swap(&mut input, &mut output);
+        }
+        input
+    }
+}
@@ -0,0 +1,10 @@
+/// A pass supplied by an embedding application. It draws into the scene after the
+/// built-in passes and before post-processing, and must load rather than clear `view`.
+pub trait RenderPass {
+    fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        camera: &wgpu::BindGroup,
+    );
+}
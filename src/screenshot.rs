@@ -0,0 +1,220 @@
+use std::future::Future;
+use std::num::NonZeroU32;
+use std::pin::Pin;
+use std::task::Poll;
+
+type Mapping = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+/// A copy of a texture into a buffer, read back once the GPU has finished with it.
+/// The texture must use `texture::Texture::RENDER_FORMAT` and have `COPY_SRC` usage.
+pub struct Readback {
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    // Set by `map`, until then the copy may not have been submitted.
+    mapping: Option<Mapping>,
+}
+
+impl Readback {
+    /// Records the copy of `texture`, which is `width` by `height`, into `encoder`.
+    pub fn new(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        // Rows copied out of a texture must be aligned to COPY_BYTES_PER_ROW_ALIGNMENT.
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = (width * 4).div_ceil(align) * align;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Screenshot Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: NonZeroU32::new(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        Self {
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+            mapping: None,
+        }
+    }
+
+    /// Starts mapping the buffer. Call after the encoder passed to `new` is submitted.
+    pub fn map(&mut self) {
+        if self.mapping.is_none() {
+            self.mapping = Some(Box::pin(self.buffer.slice(..).map_async(wgpu::MapMode::Read)));
+        }
+    }
+
+    /// The pixels as tightly packed RGBA, or None while the GPU is still busy.
+    /// Mapping only makes progress when the device is polled.
+    pub fn try_read(&mut self) -> Option<Result<Vec<u8>, wgpu::BufferAsyncError>> {
+        let mapping = self.mapping.as_mut()?;
+        match crate::util::poll_once(mapping.as_mut()) {
+            Poll::Pending => None,
+            Poll::Ready(result) => Some(result.map(|()| self.pixels())),
+        }
+    }
+
+    /// Waits for the GPU and returns the pixels as tightly packed RGBA.
+    pub fn read(mut self, device: &wgpu::Device) -> Result<Vec<u8>, wgpu::BufferAsyncError> {
+        self.map();
+        device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(self.mapping.take().unwrap())?;
+        Ok(self.pixels())
+    }
+
+    fn pixels(&self) -> Vec<u8> {
+        let unpadded_bytes_per_row = (self.width * 4) as usize;
+        let mut pixels = Vec::with_capacity(unpadded_bytes_per_row * self.height as usize);
+        {
+            let data = self.buffer.slice(..).get_mapped_range();
+            for row in data.chunks(self.padded_bytes_per_row as usize) {
+                // Render textures are BGRA, swap to RGBA.
+                for bgra in row[..unpadded_bytes_per_row].chunks(4) {
+                    pixels.extend_from_slice(&[bgra[2], bgra[1], bgra[0], bgra[3]]);
+                }
+            }
+        }
+        self.buffer.unmap();
+        pixels
+    }
+}
+
+/// Copies `texture` back to the CPU and returns its pixels as tightly packed RGBA.
+/// The texture must match the size of `config`, see `Readback` for the other requirements.
+/// Blocks until the copy completes.
+pub fn capture_screenshot(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    config: &wgpu::SurfaceConfiguration,
+) -> Result<Vec<u8>, wgpu::BufferAsyncError> {
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Screenshot Encoder"),
+    });
+    let readback = Readback::new(device, &mut encoder, texture, config.width, config.height);
+    queue.submit(std::iter::once(encoder.finish()));
+    readback.read(device)
+}
+
+/// Saves RGBA pixels as `screenshot-<unix seconds>.png` in the working directory.
+pub fn save_screenshot(pixels: &[u8], width: u32, height: u32) -> anyhow::Result<String> {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    let path = format!("screenshot-{}.png", timestamp);
+    save_png(&path, pixels, width, height)?;
+    Ok(path)
+}
+
+pub fn save_png(path: &str, pixels: &[u8], width: u32, height: u32) -> anyhow::Result<()> {
+    image::save_buffer(path, pixels, width, height, image::ColorType::Rgba8)?;
+    Ok(())
+}
+
+/// Frames captured for an animated GIF, held in memory until the recording stops.
+pub struct GifRecording {
+    path: std::path::PathBuf,
+    fps: u32,
+    width: u32,
+    height: u32,
+    frames: Vec<Vec<u8>>,
+    // Seconds since the recording started, and when the next frame is due.
+    elapsed: f32,
+    next_frame: f32,
+}
+
+impl GifRecording {
+    /// Recordings stop on their own after this many seconds, or once the frames take `MAX_BYTES`.
+    pub const MAX_DURATION: f32 = 10.0;
+    pub const MAX_BYTES: usize = 100 * 1024 * 1024;
+    // Trades file size for encoding time, 1 is the slowest and smallest, 30 the fastest.
+    const ENCODE_SPEED: i32 = 10;
+
+    pub fn new(path: std::path::PathBuf, fps: u32, width: u32, height: u32) -> Self {
+        Self {
+            path,
+            fps: fps.max(1),
+            width,
+            height,
+            frames: Vec::new(),
+            elapsed: 0.0,
+            next_frame: 0.0,
+        }
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Advances the clock by `dt` seconds and returns whether a frame should be captured.
+    pub fn advance(&mut self, dt: f32) -> bool {
+        self.elapsed += dt;
+        if self.elapsed < self.next_frame {
+            return false;
+        }
+        // Skip frames that were missed rather than capturing them all at once.
+        let interval = 1.0 / self.fps as f32;
+        self.next_frame = (self.elapsed / interval).floor() * interval + interval;
+        true
+    }
+
+    /// Adds a frame of tightly packed RGBA pixels, as returned by `Readback`.
+    /// Returns false once the recording is full and should be stopped.
+    pub fn push(&mut self, pixels: Vec<u8>) -> bool {
+        self.frames.push(pixels);
+        let bytes = self.frames.len() * (self.width * self.height * 4) as usize;
+        self.elapsed < Self::MAX_DURATION && bytes + (self.width * self.height * 4) as usize <= Self::MAX_BYTES
+    }
+
+    /// Encodes the frames, looping forever, and writes them to the recording's path.
+    pub fn save(self) -> anyhow::Result<std::path::PathBuf> {
+        let file = std::fs::File::create(&self.path)?;
+        let mut encoder = image::codecs::gif::GifEncoder::new_with_speed(file, Self::ENCODE_SPEED);
+        encoder.set_repeat(image::codecs::gif::Repeat::Infinite)?;
+        let delay = image::Delay::from_numer_denom_ms(1000, self.fps);
+        let (width, height) = (self.width, self.height);
+        let frames = self.frames.into_iter()
+            .filter_map(|pixels| image::RgbaImage::from_raw(width, height, pixels))
+            .map(|buffer| image::Frame::from_parts(buffer, 0, 0, delay));
+        encoder.encode_frames(frames)?;
+        Ok(self.path)
+    }
+}
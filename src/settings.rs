@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+use crate::camera::CameraState;
+use crate::color::Color;
+
+const SETTINGS_PATH: &str = "settings.json";
+
+/// GUI values restored between sessions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    pub radius: f32,
+    pub iterations: u32,
+    pub rotation_speed: f32,
+    pub background_color: [f32; 4],
+    /// Restored on startup and with F9, when present.
+    pub camera: Option<CameraState>,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            radius: 1.0,
+            iterations: 2,
+            rotation_speed: 0.6,
+            background_color: Color::BLACK.0,
+            camera: None,
+        }
+    }
+}
+
+impl AppSettings {
+    /// Loads the settings file, falling back to defaults if it is missing or malformed.
+    /// Mesh values are clamped to the GUI's ranges, a hand-edited file could otherwise ask for
+    /// an icosphere too large to build.
+    pub fn load() -> Self {
+        let mut settings: Self = std::fs::read_to_string(SETTINGS_PATH)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        settings.radius = settings.radius.clamp(0.0, 5.0);
+        settings.iterations = settings.iterations.min(4);
+        settings
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let contents = serde_json::to_string_pretty(self)?;
+        std::fs::write(SETTINGS_PATH, contents)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Polls a shader file's modification time so it can be reloaded while running.
+pub struct ShaderWatcher {
+    path: PathBuf,
+    last_modified: SystemTime,
+}
+
+impl ShaderWatcher {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        let path = path.into();
+        let last_modified = Self::modified(&path).unwrap_or(SystemTime::UNIX_EPOCH);
+        Self {
+            path,
+            last_modified,
+        }
+    }
+
+    pub fn file_name(&self) -> String {
+        self.path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Returns the file's contents if it changed since the last call.
+    pub fn poll(&mut self) -> Option<String> {
+        let modified = Self::modified(&self.path)?;
+        if modified <= self.last_modified {
+            return None;
+        }
+        self.last_modified = modified;
+        std::fs::read_to_string(&self.path).ok()
+    }
+
+    fn modified(path: &PathBuf) -> Option<SystemTime> {
+        std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+}
+
+/// Runs `f` inside a validation error scope, so an invalid shader or pipeline
+/// returns an error instead of hitting the device's uncaptured error handler.
+pub fn validated<T>(device: &wgpu::Device, f: impl FnOnce() -> T) -> anyhow::Result<T> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let result = f();
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => Err(anyhow::anyhow!("{}", error)),
+        None => Ok(result),
+    }
+}
@@ -0,0 +1,302 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::camera::{self, Camera};
+use crate::mesh::{self, Vertex};
+use crate::pipeline::PipelineBuilder;
+use crate::InstanceRaw;
+
+/// Most cascades `Cascades` in wire.wgsl has room for.
+pub const MAX_CASCADES: usize = 4;
+
+/// Laid out to match `Cascades` in wire.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CascadeUniform {
+    view_proj: [[[f32; 4]; 4]; MAX_CASCADES],
+    // View space depth where each cascade ends.
+    splits: [f32; MAX_CASCADES],
+    count: u32,
+    enabled: u32,
+    // Uniforms require 16 byte spacing, so we need padding here
+    _padding: [u32; 2],
+}
+
+/// One cascade, a layer of the shared shadow texture covering a slice of the view frustum.
+pub struct ShadowMap {
+    view: wgpu::TextureView,
+    view_proj: Matrix4<f32>,
+    // View space depth where the slice ends.
+    split: f32,
+    light_buffer: wgpu::Buffer,
+    light_bind_group: wgpu::BindGroup,
+}
+
+/// Shadows from a directional light, with the view frustum split into slices that each get their own
+/// shadow map, so nearby shadows stay sharp without giving up distant ones.
+/// Only instances in the instance buffer cast shadows, so with CPU culling those outside the view don't.
+pub struct CascadedShadowMapPass {
+    pub enabled: bool,
+    /// Blend between uniform (0) and logarithmic (1) split distances.
+    pub split_lambda: f32,
+    /// Unit vector the light shines along.
+    pub light_direction: Vector3<f32>,
+    cascades: Vec<ShadowMap>,
+    num_cascades: u32,
+    array_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl CascadedShadowMapPass {
+    const SIZE: u32 = 1024;
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    // Shadows end this far from the camera, or at its far plane if that's closer.
+    const SHADOW_DISTANCE: f32 = 60.0;
+    // How far behind a cascade the light looks for shadow casters.
+    const CASTER_MARGIN: f32 = 20.0;
+
+    pub fn new(device: &wgpu::Device, num_cascades: u32) -> Self {
+        let num_cascades = num_cascades.clamp(1, MAX_CASCADES as u32);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow Map Texture"),
+            size: wgpu::Extent3d {
+                width: Self::SIZE,
+                height: Self::SIZE,
+                depth_or_array_layers: num_cascades,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let array_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Shadow Map Array View"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Map Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let light_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Shadow Light Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let cascades = (0..num_cascades)
+            .map(|layer| {
+                let view_proj = Matrix4::identity();
+                let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Shadow Light Buffer"),
+                    contents: bytemuck::cast_slice(&[Into::<[[f32; 4]; 4]>::into(view_proj)]),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                });
+                let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Shadow Light Bind Group"),
+                    layout: &light_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: light_buffer.as_entire_binding(),
+                        },
+                    ],
+                });
+                ShadowMap {
+                    view: texture.create_view(&wgpu::TextureViewDescriptor {
+                        label: Some("Shadow Map Layer View"),
+                        dimension: Some(wgpu::TextureViewDimension::D2),
+                        base_array_layer: layer,
+                        array_layer_count: std::num::NonZeroU32::new(1),
+                        ..Default::default()
+                    }),
+                    view_proj,
+                    split: 0.0,
+                    light_buffer,
+                    light_bind_group,
+                }
+            })
+            .collect();
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cascade Uniform Buffer"),
+            size: std::mem::size_of::<CascadeUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let render_pipeline = {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Shadow Pipeline Layout"),
+                bind_group_layouts: &[
+                    &light_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+            let shader = wgpu::ShaderModuleDescriptor {
+                label: Some("Shadow Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("shadow.wgsl").into()),
+            };
+            PipelineBuilder::new("Shadow Render Pipeline")
+                .color_formats(&[])
+                .depth_format(Self::FORMAT)
+                .vertex_layouts(&[mesh::MeshVertex::desc(), InstanceRaw::desc()])
+                .shader(shader)
+                .build(device, &pipeline_layout)
+        };
+
+        Self {
+            enabled: false,
+            split_lambda: 0.75,
+            light_direction: Vector3::new(-1.0, -2.0, -1.0).normalize(),
+            cascades,
+            num_cascades,
+            array_view,
+            sampler,
+            uniform_buffer,
+            render_pipeline,
+        }
+    }
+
+    pub fn num_cascades(&self) -> u32 {
+        self.num_cascades
+    }
+
+    /// The shadow resources bound after the spotlight, see `Spotlight::create_bind_group_layout`.
+    pub fn bind_group_entries(&self) -> [wgpu::BindGroupEntry<'_>; 3] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: self.uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&self.array_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ]
+    }
+
+    /// View space depth where each cascade ends, blending logarithmic and uniform splits by `split_lambda`.
+    fn split_distances(&self, z_near: f32, z_far: f32) -> Vec<f32> {
+        (1..=self.num_cascades)
+            .map(|i| {
+                let p = i as f32 / self.num_cascades as f32;
+                let log = z_near * (z_far / z_near).powf(p);
+                let uniform = z_near + (z_far - z_near) * p;
+                self.split_lambda * log + (1.0 - self.split_lambda) * uniform
+            })
+            .collect()
+    }
+
+    /// Fits each cascade around its slice of `camera`'s frustum and uploads the matrices.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        let z_near = camera.projection.z_near();
+        let z_far = camera.projection.z_far().min(Self::SHADOW_DISTANCE);
+        let splits = self.split_distances(z_near, z_far);
+
+        let eye = camera.eye.position;
+        let (forward, right, up) = (camera.eye.forward(), camera.eye.right(), camera.eye.up());
+        let tan_half_fov = (camera.projection.fov_y().0 * 0.5).tan();
+        let aspect = camera.projection.aspect();
+        let slice_corners = |depth: f32| {
+            let (half_height, half_width) = (depth * tan_half_fov, depth * tan_half_fov * aspect);
+            let center = eye + forward * depth;
+            [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)]
+                .map(|(x, y)| center + right * x * half_width + up * y * half_height)
+        };
+
+        let light_up = if self.light_direction.y.abs() > 0.99 { Vector3::unit_z() } else { Vector3::unit_y() };
+        let mut near = z_near;
+        for (cascade, &split) in self.cascades.iter_mut().zip(&splits) {
+            let corners = [slice_corners(near), slice_corners(split)].concat();
+            // A bounding sphere keeps the cascade the same size as the camera turns.
+            let center = Point3::centroid(&corners);
+            let radius = corners.iter()
+                .map(|&corner| (corner - center).magnitude())
+                .fold(0.0, f32::max);
+            let light_eye = center - self.light_direction * (radius + Self::CASTER_MARGIN);
+            let view = Matrix4::look_to_rh(light_eye, self.light_direction, light_up);
+            let projection = cgmath::ortho(-radius, radius, -radius, radius, 0.0, 2.0 * radius + Self::CASTER_MARGIN);
+            cascade.view_proj = camera::OPENGL_TO_WGPU_MATRIX * projection * view;
+            cascade.split = split;
+            queue.write_buffer(
+                &cascade.light_buffer,
+                0,
+                bytemuck::cast_slice(&[Into::<[[f32; 4]; 4]>::into(cascade.view_proj)]),
+            );
+            near = split;
+        }
+
+        let mut uniform = CascadeUniform {
+            view_proj: [Matrix4::identity().into(); MAX_CASCADES],
+            splits: [0.0; MAX_CASCADES],
+            count: self.num_cascades,
+            enabled: self.enabled as u32,
+            _padding: [0; 2],
+        };
+        for (i, cascade) in self.cascades.iter().enumerate() {
+            uniform.view_proj[i] = cascade.view_proj.into();
+            uniform.splits[i] = cascade.split;
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Renders `instances` of `instance_buffer` into every cascade.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        mesh: &mesh::Mesh,
+        instance_buffer: &wgpu::Buffer,
+        instances: std::ops::Range<u32>,
+    ) {
+        if !self.enabled {
+            return;
+        }
+
+        use crate::mesh::DrawMesh;
+        for cascade in &self.cascades {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Render Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &cascade.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+            render_pass.draw_mesh_instanced(
+                mesh,
+                instances.clone(),
+                Some(vec![&cascade.light_bind_group]),
+                None,
+            );
+        }
+    }
+}
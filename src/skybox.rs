@@ -0,0 +1,176 @@
+use anyhow::Result;
+
+use crate::color::Color;
+use crate::mesh::{self, Vertex};
+use crate::pipeline::PipelineBuilder;
+use crate::texture;
+
+/// Draws a cubemap behind the scene. Renders before everything else and
+/// clears the color target, without a depth attachment so it never writes depth.
+pub struct SkyboxPass {
+    cube_mesh: mesh::Mesh,
+    // Only sampled through the bind group, but owned here so it lives as long as the pass.
+    #[allow(dead_code)]
+    cubemap: texture::Texture,
+    cubemap_bind_group: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl SkyboxPass {
+    /// `faces` are ordered +X, -X, +Y, -Y, +Z, -Z.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[image::RgbaImage; 6],
+        config: &wgpu::SurfaceConfiguration,
+        camera_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let cube_mesh = mesh::Mesh::cube(device, 1.0, true)?;
+        let cubemap = texture::Texture::create_cubemap(device, queue, faces, "Skybox Cubemap")?;
+
+        let cubemap_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Skybox Cubemap Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let cubemap_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Skybox Cubemap Bind Group"),
+            layout: &cubemap_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&cubemap.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&cubemap.sampler),
+                },
+            ],
+        });
+
+        let render_pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Skybox Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_layout,
+                    &cubemap_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+            let shader = wgpu::ShaderModuleDescriptor {
+                label: Some("Skybox Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("skybox.wgsl").into()),
+            };
+            // The eye sits inside the cube, so only its inward facing sides are visible.
+            PipelineBuilder::new("Skybox Render Pipeline")
+                .color_format(config.format)
+                .vertex_layouts(&[mesh::MeshVertex::desc()])
+                .cull_mode(Some(wgpu::Face::Front))
+                .shader(shader)
+                .build(device, &layout)
+        };
+
+        Ok(Self {
+            cube_mesh,
+            cubemap,
+            cubemap_bind_group,
+            render_pipeline,
+        })
+    }
+
+    pub fn render(
+        &self,
+        view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Skybox Render Pass"),
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(Color::BLACK.to_wgpu()),
+                        store: true,
+                    },
+                }
+            ],
+            depth_stencil_attachment: None,
+        });
+
+        use crate::mesh::DrawMesh;
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.draw_mesh(
+            &self.cube_mesh,
+            Some(vec![
+                camera_bind_group,
+                &self.cubemap_bind_group,
+            ]),
+        );
+    }
+}
+
+/// Builds cube faces fading from `ground` below the horizon to `zenith` overhead,
+/// ordered for `SkyboxPass::new`.
+pub fn gradient_faces(size: u32, zenith: Color, horizon: Color, ground: Color) -> [image::RgbaImage; 6] {
+    // Height of the view direction through a texel, -1 straight down to 1 straight up.
+    let face = |height: &dyn Fn(f32, f32) -> f32| {
+        image::RgbaImage::from_fn(size, size, |x, y| {
+            let u = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+            let v = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+            let h = height(u, v);
+            let color = if h >= 0.0 {
+                horizon.lerp(&zenith, h)
+            } else {
+                horizon.lerp(&ground, -h)
+            };
+            // Colors are linear but the cubemap stores sRGB, so gamma encode them.
+            image::Rgba(color.0.map(|c| (c.clamp(0.0, 1.0).powf(1.0 / 2.2) * 255.0).round() as u8))
+        })
+    };
+    // The side faces run top to bottom along v, the caps face straight up and down.
+    let side = |u: f32, v: f32| -v / (1.0 + u * u + v * v).sqrt();
+    let top = |u: f32, v: f32| 1.0 / (1.0 + u * u + v * v).sqrt();
+    [
+        face(&side),
+        face(&side),
+        face(&top),
+        face(&|u, v| -top(u, v)),
+        face(&side),
+        face(&side),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gradient_faces_fade_from_ground_to_zenith() {
+        let faces = gradient_faces(9, Color::BLUE, Color::WHITE, Color::BLACK);
+        // The middle row of every side looks straight at the horizon.
+        for side in [&faces[0], &faces[1], &faces[4], &faces[5]] {
+            assert_eq!(side.get_pixel(0, 4), &image::Rgba([255, 255, 255, 255]));
+        }
+        assert_eq!(faces[2].get_pixel(4, 4), &image::Rgba([0, 0, 255, 255]));
+        assert_eq!(faces[3].get_pixel(4, 4), &image::Rgba([0, 0, 0, 255]));
+    }
+}
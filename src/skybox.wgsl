@@ -0,0 +1,41 @@
+// Vertex shader
+
+struct Camera {
+    view_pos: vec4<f32>;
+    view_proj: mat4x4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] direction: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    // Keep the cube centered on the eye so it never gets closer or further away.
+    out.clip_position = camera.view_proj * vec4<f32>(model.position + camera.view_pos.xyz, 1.0);
+    out.direction = model.position;
+    return out;
+}
+
+// Fragment shader
+
+[[group(1), binding(0)]]
+var t_skybox: texture_cube<f32>;
+[[group(1), binding(1)]]
+var s_skybox: sampler;
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // The interpolated cube position is the direction from the eye through this fragment.
+    return textureSample(t_skybox, s_skybox, normalize(in.direction));
+}
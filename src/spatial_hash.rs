@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Vector3};
+
+type Cell = (i32, i32, i32);
+
+/// Uniform grid of points hashed by cell, for finding the neighbors of a point.
+/// Cheaper to rebuild than the octree, so it suits points that move every frame.
+pub struct Grid3D {
+    cells: HashMap<Cell, Vec<usize>>,
+    cell_size: f32,
+    // Position each index was inserted at, so it can be removed by index alone.
+    positions: HashMap<usize, Vector3<f32>>,
+}
+
+impl Grid3D {
+    /// Queries are cheapest with `cell_size` close to the radius they use.
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cells: HashMap::new(),
+            cell_size,
+            positions: HashMap::new(),
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    fn cell(&self, pos: Vector3<f32>) -> Cell {
+        let cell = pos / self.cell_size;
+        (cell.x.floor() as i32, cell.y.floor() as i32, cell.z.floor() as i32)
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.positions.clear();
+    }
+
+    /// Adds `idx` at `pos`, moving it if it was already inserted.
+    pub fn insert(&mut self, idx: usize, pos: Vector3<f32>) {
+        self.remove(idx);
+        let cell = self.cell(pos);
+        self.cells.entry(cell).or_default().push(idx);
+        self.positions.insert(idx, pos);
+    }
+
+    /// Returns false if `idx` wasn't inserted.
+    pub fn remove(&mut self, idx: usize) -> bool {
+        let pos = match self.positions.remove(&idx) {
+            Some(pos) => pos,
+            None => return false,
+        };
+        let cell = self.cell(pos);
+        if let Some(indices) = self.cells.get_mut(&cell) {
+            indices.retain(|&other| other != idx);
+            if indices.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+        true
+    }
+
+    /// Indices inserted within `radius` of `pos`, including one at `pos` itself, in no particular order.
+    pub fn query_radius(&self, pos: Vector3<f32>, radius: f32) -> Vec<usize> {
+        let extent = Vector3::new(radius, radius, radius);
+        let (min, max) = (self.cell(pos - extent), self.cell(pos + extent));
+        let mut found = Vec::new();
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                for z in min.2..=max.2 {
+                    let indices = match self.cells.get(&(x, y, z)) {
+                        Some(indices) => indices,
+                        None => continue,
+                    };
+                    found.extend(indices.iter().copied().filter(|idx| {
+                        (self.positions[idx] - pos).magnitude2() <= radius * radius
+                    }));
+                }
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    fn sorted(mut indices: Vec<usize>) -> Vec<usize> {
+        indices.sort_unstable();
+        indices
+    }
+
+    #[test]
+    fn insert_moves_existing_index() {
+        let mut grid = Grid3D::new(1.0);
+        grid.insert(3, Vector3::new(0.5, 0.5, 0.5));
+        grid.insert(3, Vector3::new(10.5, 0.5, 0.5));
+        assert!(grid.query_radius(Vector3::new(0.5, 0.5, 0.5), 1.0).is_empty());
+        assert_eq!(grid.query_radius(Vector3::new(10.5, 0.5, 0.5), 1.0), vec![3]);
+    }
+
+    #[test]
+    fn remove_forgets_index() {
+        let mut grid = Grid3D::new(1.0);
+        grid.insert(0, Vector3::new(0.0, 0.0, 0.0));
+        grid.insert(1, Vector3::new(0.1, 0.0, 0.0));
+        assert!(grid.remove(0));
+        assert!(!grid.remove(0));
+        assert!(!grid.remove(7));
+        assert_eq!(grid.query_radius(Vector3::new(0.0, 0.0, 0.0), 1.0), vec![1]);
+    }
+
+    #[test]
+    fn query_radius_matches_brute_force() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let mut random_point = || Vector3::new(
+            rng.gen_range(-10.0..10.0),
+            rng.gen_range(-10.0..10.0),
+            rng.gen_range(-10.0..10.0),
+        );
+        let points = (0..500).map(|_| random_point()).collect::<Vec<_>>();
+        let mut grid = Grid3D::new(1.5);
+        for (idx, &pos) in points.iter().enumerate() {
+            grid.insert(idx, pos);
+        }
+        for radius in [0.5, 1.5, 4.0] {
+            for _ in 0..20 {
+                let pos = random_point();
+                let expected = (0..points.len())
+                    .filter(|&idx| (points[idx] - pos).magnitude2() <= radius * radius)
+                    .collect::<Vec<_>>();
+                assert_eq!(sorted(grid.query_radius(pos, radius)), expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use cgmath::prelude::*;
+use cgmath::{Point3, Vector3};
+
+/// Uniform Catmull-Rom spline passing through every control point.
+/// The whole curve is parameterized by `t` in [0, 1], with the control points evenly spaced in `t`.
+#[derive(Debug, Clone, Default)]
+pub struct CatmullRomSpline {
+    control_points: Vec<Point3<f32>>,
+}
+
+#[allow(dead_code)]
+impl CatmullRomSpline {
+    // Samples per segment used to approximate arc length.
+    const ARC_LENGTH_STEPS: u32 = 32;
+
+    pub fn new(control_points: Vec<Point3<f32>>) -> Self {
+        Self { control_points }
+    }
+
+    pub fn control_points(&self) -> &[Point3<f32>] {
+        &self.control_points
+    }
+
+    fn num_segments(&self) -> usize {
+        self.control_points.len().saturating_sub(1)
+    }
+
+    /// Splits `t` into a segment index and the parameter within that segment.
+    fn locate(&self, t: f32) -> (usize, f32) {
+        let segments = self.num_segments();
+        let scaled = t.clamp(0.0, 1.0) * segments as f32;
+        let segment = (scaled.floor() as usize).min(segments.saturating_sub(1));
+        (segment, scaled - segment as f32)
+    }
+
+    /// The four points around `segment`, duplicating the end points at either end of the curve.
+    fn segment_points(&self, segment: usize) -> [Vector3<f32>; 4] {
+        let last = self.control_points.len() - 1;
+        let point = |i: usize| self.control_points[i.min(last)].to_vec();
+        [
+            point(segment.saturating_sub(1)),
+            point(segment),
+            point(segment + 1),
+            point(segment + 2),
+        ]
+    }
+
+    /// Evaluates segment `segment` (between control points `segment` and `segment + 1`) at `u` in [0, 1].
+    pub fn evaluate_segment(&self, segment: usize, u: f32) -> Point3<f32> {
+        if self.control_points.len() < 2 {
+            return self.control_points.first().copied().unwrap_or_else(Point3::origin);
+        }
+        let [p0, p1, p2, p3] = self.segment_points(segment);
+        let u2 = u * u;
+        let u3 = u2 * u;
+        let position = (p1 * 2.0
+            + (p2 - p0) * u
+            + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * u2
+            + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * u3) * 0.5;
+        Point3::from_vec(position)
+    }
+
+    pub fn evaluate(&self, t: f32) -> Point3<f32> {
+        let (segment, u) = self.locate(t);
+        self.evaluate_segment(segment, u)
+    }
+
+    /// Derivative of `evaluate` with respect to `t`.
+    pub fn tangent(&self, t: f32) -> Vector3<f32> {
+        if self.control_points.len() < 2 {
+            return Vector3::zero();
+        }
+        let (segment, u) = self.locate(t);
+        let [p0, p1, p2, p3] = self.segment_points(segment);
+        let derivative = ((p2 - p0)
+            + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * (2.0 * u)
+            + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * (3.0 * u * u)) * 0.5;
+        derivative * self.num_segments() as f32
+    }
+
+    /// Returns `n_samples` values of `t` spaced at equal distances along the curve,
+    /// from 0 to 1 inclusive.
+    pub fn arc_length_parameterize(&self, n_samples: u32) -> Vec<f32> {
+        if n_samples < 2 || self.control_points.len() < 2 {
+            return vec![0.0; n_samples as usize];
+        }
+
+        // Cumulative length at evenly spaced values of t.
+        let steps = self.num_segments() as u32 * Self::ARC_LENGTH_STEPS;
+        let mut lengths = Vec::with_capacity(steps as usize + 1);
+        lengths.push(0.0);
+        let mut previous = self.evaluate(0.0);
+        for i in 1..=steps {
+            let point = self.evaluate(i as f32 / steps as f32);
+            lengths.push(lengths[i as usize - 1] + (point - previous).magnitude());
+            previous = point;
+        }
+        let total = lengths[steps as usize];
+
+        (0..n_samples).map(|i| {
+            let target = total * i as f32 / (n_samples - 1) as f32;
+            // First step reaching the target, then interpolate within it.
+            let step = lengths.partition_point(|length| *length < target).clamp(1, steps as usize);
+            let (start, end) = (lengths[step - 1], lengths[step]);
+            let fraction = if end > start { (target - start) / (end - start) } else { 0.0 };
+            ((step - 1) as f32 + fraction) / steps as f32
+        }).collect()
+    }
+}
+
+/// Cubic Bézier curve from `p0` to `p3`, pulled towards `p1` and `p2`, traversed in `duration` seconds.
+#[derive(Debug, Copy, Clone)]
+pub struct BezierPath {
+    pub p0: Point3<f32>,
+    pub p1: Point3<f32>,
+    pub p2: Point3<f32>,
+    pub p3: Point3<f32>,
+    pub duration: f32,
+}
+
+impl BezierPath {
+    /// Evaluates the curve at `t` in [0, 1] with de Casteljau's algorithm.
+    pub fn evaluate(&self, t: f32) -> Point3<f32> {
+        let t = t.clamp(0.0, 1.0);
+        let lerp = |a: Point3<f32>, b: Point3<f32>| a + (b - a) * t;
+        let a = lerp(self.p0, self.p1);
+        let b = lerp(self.p1, self.p2);
+        let c = lerp(self.p2, self.p3);
+        lerp(lerp(a, b), lerp(b, c))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spline() -> CatmullRomSpline {
+        CatmullRomSpline::new(vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 2.0, 0.0),
+            Point3::new(3.0, 2.0, -1.0),
+            Point3::new(4.0, 0.0, 1.0),
+            Point3::new(6.0, 1.0, 2.0),
+        ])
+    }
+
+    #[test]
+    fn evaluate_hits_control_points() {
+        let spline = spline();
+        let segments = spline.control_points().len() - 1;
+        for (i, &point) in spline.control_points().iter().enumerate() {
+            let t = i as f32 / segments as f32;
+            let distance = (spline.evaluate(t) - point).magnitude();
+            assert!(distance < 1e-5, "control point {} missed by {}", i, distance);
+        }
+    }
+
+    #[test]
+    fn tangent_is_non_zero() {
+        let spline = spline();
+        for i in 0..=100 {
+            let t = i as f32 / 100.0;
+            assert!(spline.tangent(t).magnitude() > 1e-3, "zero tangent at t = {}", t);
+        }
+    }
+}
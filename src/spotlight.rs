@@ -0,0 +1,81 @@
+use cgmath::InnerSpace;
+
+/// Cone shaped light, laid out to match `Spotlight` in wire.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Spotlight {
+    pub position: [f32; 3],
+    // Uniforms require 16 byte spacing, so we need padding here
+    pub _pad0: u32,
+    /// Unit vector the cone points along.
+    pub direction: [f32; 3],
+    /// Cosine of the half angle of the cone, nothing outside it is lit.
+    pub angle_cos: f32,
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+impl Spotlight {
+    pub fn new(
+        position: cgmath::Point3<f32>,
+        target: cgmath::Point3<f32>,
+        angle: cgmath::Deg<f32>,
+        color: [f32; 3],
+        intensity: f32,
+    ) -> Self {
+        Self {
+            position: position.into(),
+            _pad0: 0,
+            direction: (target - position).normalize().into(),
+            angle_cos: cgmath::Rad::from(angle).0.cos(),
+            color,
+            intensity,
+        }
+    }
+
+    /// Points the cone from its position at `target`, keeping the direction if the two coincide.
+    pub fn point_at(&mut self, target: cgmath::Point3<f32>) {
+        let offset = target - cgmath::Point3::from(self.position);
+        if offset.magnitude2() > 0.0 {
+            self.direction = offset.normalize().into();
+        }
+    }
+
+    /// Layout of a bind group with a uniform buffer holding a `Spotlight`, followed by the
+    /// shadow maps that darken it, see `CascadedShadowMapPass::bind_group_entries`.
+    pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Spotlight Bind Group Layout"),
+            entries: &[
+                uniform_entry(0),
+                uniform_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+            ],
+        })
+    }
+}
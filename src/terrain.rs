@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::mesh::{self, Vertex};
+use crate::pipeline::PipelineBuilder;
+use crate::texture;
+
+/// A large height field split into square patches, only the ones near the camera are built.
+pub struct Terrain {
+    pub visible: bool,
+    /// Patches whose center is within this horizontal distance of the camera are drawn.
+    pub view_distance: f32,
+    patches: HashMap<(i32, i32), mesh::Mesh>,
+    // cols x cols samples, row by row along x, centered on the origin.
+    heights: Vec<f32>,
+    cols: u32,
+    // Cells along each side of a patch, neighbouring patches share their border samples.
+    patch_size: u32,
+    cell_size: f32,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl Terrain {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera_layout: &wgpu::BindGroupLayout,
+        num_patches: u32,
+        patch_size: u32,
+        cell_size: f32,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let cols = num_patches * patch_size + 1;
+        let heights = Self::generate_heights(cols, cell_size, rng);
+
+        let render_pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Terrain Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+            let shader = wgpu::ShaderModuleDescriptor {
+                label: Some("Terrain Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("terrain.wgsl").into()),
+            };
+            // Visible from below as well.
+            PipelineBuilder::new("Terrain Render Pipeline")
+                .color_format(config.format)
+                .depth_format(texture::Texture::DEPTH_FORMAT)
+                .vertex_layouts(&[mesh::MeshVertex::desc()])
+                .cull_mode(None)
+                .shader(shader)
+                .build(device, &layout)
+        };
+
+        Self {
+            visible: false,
+            view_distance: 60.0,
+            patches: HashMap::new(),
+            heights,
+            cols,
+            patch_size,
+            cell_size,
+            render_pipeline,
+        }
+    }
+
+    /// Rolling hills from a few sine waves with random directions and phases.
+    fn generate_heights(cols: u32, cell_size: f32, rng: &mut impl Rng) -> Vec<f32> {
+        let waves = (0..6)
+            .map(|octave| {
+                let angle = rng.gen::<f32>() * std::f32::consts::TAU;
+                let frequency = 0.02 * 2.0f32.powi(octave);
+                let amplitude = 4.0 * 0.5f32.powi(octave);
+                (angle.cos() * frequency, angle.sin() * frequency, amplitude, rng.gen::<f32>() * std::f32::consts::TAU)
+            })
+            .collect::<Vec<_>>();
+        let offset = (cols - 1) as f32 * 0.5;
+        (0..cols * cols)
+            .map(|i| {
+                let x = ((i % cols) as f32 - offset) * cell_size;
+                let z = ((i / cols) as f32 - offset) * cell_size;
+                waves.iter()
+                    .map(|(fx, fz, amplitude, phase)| amplitude * (x * fx + z * fz + phase).sin())
+                    .sum::<f32>()
+            })
+            .collect()
+    }
+
+    pub fn num_patches(&self) -> usize {
+        self.patches.len()
+    }
+
+    /// World space center of patch (i, j), at height zero.
+    fn patch_center(&self, (i, j): (i32, i32)) -> cgmath::Vector3<f32> {
+        let extent = self.patch_size as f32 * self.cell_size;
+        let offset = (self.cols - 1) as f32 * self.cell_size * 0.5;
+        cgmath::Vector3::new(
+            (i as f32 + 0.5) * extent - offset,
+            0.0,
+            (j as f32 + 0.5) * extent - offset,
+        )
+    }
+
+    /// Builds the patches that came within `view_distance` of `camera_pos` and drops the ones that left it.
+    pub fn update(&mut self, device: &wgpu::Device, camera_pos: cgmath::Point3<f32>) {
+        let num_patches = ((self.cols - 1) / self.patch_size) as i32;
+        let in_range = |terrain: &Self, key: (i32, i32)| {
+            let center = terrain.patch_center(key);
+            let (dx, dz) = (center.x - camera_pos.x, center.z - camera_pos.z);
+            dx * dx + dz * dz <= terrain.view_distance * terrain.view_distance
+        };
+
+        let keys = self.patches.keys().copied().collect::<Vec<_>>();
+        for key in keys {
+            if !in_range(self, key) {
+                self.patches.remove(&key);
+            }
+        }
+
+        for j in 0..num_patches {
+            for i in 0..num_patches {
+                if self.patches.contains_key(&(i, j)) || !in_range(self, (i, j)) {
+                    continue;
+                }
+                let patch = self.build_patch(device, (i, j));
+                self.patches.insert((i, j), patch);
+            }
+        }
+    }
+
+    fn build_patch(&self, device: &wgpu::Device, (i, j): (i32, i32)) -> mesh::Mesh {
+        let row = self.patch_size + 1;
+        let (x0, z0) = (i as u32 * self.patch_size, j as u32 * self.patch_size);
+        let heights = (0..row * row)
+            .map(|k| self.heights[((z0 + k / row) * self.cols + x0 + k % row) as usize])
+            .collect::<Vec<_>>();
+        let extent = self.patch_size as f32 * self.cell_size;
+        mesh::Mesh::plane_with_heights(
+            device,
+            self.patch_center((i, j)),
+            extent,
+            extent,
+            self.patch_size,
+            &heights,
+            true,
+        ).unwrap()
+    }
+
+    pub fn render(
+        &self,
+        view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+        depth_texture: &texture::Texture,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        if !self.visible {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Terrain Render Pass"),
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }
+            ],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        use crate::mesh::DrawMesh;
+        render_pass.set_pipeline(&self.render_pipeline);
+        for patch in self.patches.values() {
+            render_pass.draw_mesh(patch, Some(vec![camera_bind_group]));
+        }
+    }
+}
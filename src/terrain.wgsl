@@ -0,0 +1,47 @@
+// Vertex shader
+
+struct Camera {
+    view_pos: vec4<f32>;
+    view_proj: mat4x4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+    [[location(2)]] normal: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] world_position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+};
+
+let LIGHT_DIRECTION: vec3<f32> = vec3<f32>(0.4, 1.0, 0.3);
+let LOW_COLOR: vec3<f32> = vec3<f32>(0.15, 0.3, 0.1);
+let HIGH_COLOR: vec3<f32> = vec3<f32>(0.6, 0.55, 0.5);
+// Heights blended between the low and high colors.
+let HEIGHT_RANGE: vec2<f32> = vec2<f32>(-4.0, 6.0);
+
+[[stage(vertex)]]
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
+    out.world_position = model.position;
+    out.normal = model.normal;
+    return out;
+}
+
+// Fragment shader
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let t = smoothStep(HEIGHT_RANGE.x, HEIGHT_RANGE.y, in.world_position.y);
+    let albedo = mix(LOW_COLOR, HIGH_COLOR, t);
+    let diffuse = max(dot(normalize(in.normal), normalize(LIGHT_DIRECTION)), 0.0);
+    return vec4<f32>(albedo * (0.25 + 0.75 * diffuse), 1.0);
+}
@@ -0,0 +1,13 @@
+/// Time since the app started and since the last frame, updated once per frame.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Timer {
+    pub elapsed_secs: f32,
+    pub delta_secs: f32,
+}
+
+impl Timer {
+    pub fn tick(&mut self, dt: std::time::Duration) {
+        self.delta_secs = dt.as_secs_f32();
+        self.elapsed_secs += self.delta_secs;
+    }
+}
@@ -0,0 +1,90 @@
+/// Fixed capacity buffer that overwrites its oldest value once full.
+pub struct RingBuffer<T> {
+    data: Vec<T>,
+    // Index of the oldest value once the buffer is full.
+    head: usize,
+    capacity: usize,
+}
+
+impl<T> RingBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "RingBuffer capacity must be non-zero");
+        Self {
+            data: Vec::with_capacity(capacity),
+            head: 0,
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, value: T) {
+        if self.data.len() < self.capacity {
+            self.data.push(value);
+        } else {
+            self.data[self.head] = value;
+            self.head = (self.head + 1) % self.capacity;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.head = 0;
+    }
+
+    /// Iterates from the oldest to the newest value.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.data[self.head..].iter().chain(self.data[..self.head].iter())
+    }
+
+    /// The most recently pushed value.
+    pub fn last(&self) -> Option<&T> {
+        let index = if self.head == 0 { self.data.len().checked_sub(1)? } else { self.head - 1 };
+        self.data.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+/// Records buffer writes into `encoder` through a staging belt instead of `Queue::write_buffer`,
+/// reusing staging memory across frames.
+pub struct BufferUploader<'a> {
+    pub device: &'a wgpu::Device,
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    pub belt: &'a mut wgpu::util::StagingBelt,
+}
+
+impl BufferUploader<'_> {
+    /// Copies `data` into `target` at `offset`. Both must be multiples of `COPY_BUFFER_ALIGNMENT`.
+    pub fn write(&mut self, target: &wgpu::Buffer, offset: wgpu::BufferAddress, data: &[u8]) {
+        if let Some(size) = wgpu::BufferSize::new(data.len() as u64) {
+            self.belt
+                .write_buffer(self.encoder, target, offset, size, self.device)
+                .copy_from_slice(data);
+        }
+    }
+}
+
+/// Polls `future` once, for futures that are completed by `Device::poll` rather than a waker.
+pub fn poll_once<F: std::future::Future + ?Sized>(future: std::pin::Pin<&mut F>) -> std::task::Poll<F::Output> {
+    use std::task::{RawWaker, RawWakerVTable, Waker};
+
+    // Nothing waits on the wake up, the caller polls again next frame.
+    fn noop_raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            noop_raw_waker()
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+
+    // Safe because every function in the vtable ignores the data pointer.
+    let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+    let mut context = std::task::Context::from_waker(&waker);
+    future.poll(&mut context)
+}
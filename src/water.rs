@@ -0,0 +1,315 @@
+use rand::Rng;
+use wgpu::util::DeviceExt;
+
+use crate::mesh::{self, Vertex};
+use crate::pipeline::PipelineBuilder;
+use crate::texture;
+use crate::timer::Timer;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct WaterUniform {
+    time: f32,
+    height: f32,
+    // Uniforms require 16 byte spacing, so we need padding here
+    _padding: [f32; 2],
+}
+
+/// Draws a horizontal water plane whose animated normals reflect a copy of the scene behind it.
+/// The reflection is a screen space approximation, so anything off screen can't show up in it.
+pub struct WaterPass {
+    pub visible: bool,
+    /// World space height of the surface.
+    pub height: f32,
+    mesh: mesh::Mesh,
+    // Tiling normal map and its repeating sampler, kept to rebuild the bind group on resize.
+    normal_map: texture::Texture,
+    normal_sampler: wgpu::Sampler,
+    time_uniform: wgpu::Buffer,
+    // Copy of the scene taken before the water is drawn over it, sampled for the reflection.
+    reflection: texture::Texture,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+    size: wgpu::Extent3d,
+}
+
+impl WaterPass {
+    const SIZE: f32 = 40.0;
+    const NOISE_SIZE: u32 = 128;
+    // Noise lattice cells across the normal map, it tiles because the lattice wraps.
+    const NOISE_CELLS: u32 = 8;
+
+    /// `config` must match the size of the scene target passed to `render`.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        camera_layout: &wgpu::BindGroupLayout,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let reflection = texture::Texture::create_copy_target(device, config, "Water Reflection Texture");
+        let mesh = mesh::Mesh::plane(device, Self::SIZE, Self::SIZE, 1, true).unwrap();
+        let normal_map = Self::create_normal_map(device, queue, rng);
+        let normal_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Water Normal Sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let height = -2.0;
+        let time_uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Water Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[WaterUniform {
+                time: 0.0,
+                height,
+                _padding: [0.0; 2],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Water Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(1),
+                sampler_entry(2),
+                texture_entry(3),
+                sampler_entry(4),
+            ],
+        });
+        let bind_group = Self::create_bind_group(
+            device,
+            &layout,
+            &time_uniform,
+            &normal_map,
+            &normal_sampler,
+            &reflection,
+        );
+
+        let render_pipeline = {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Water Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_layout,
+                    &layout,
+                ],
+                push_constant_ranges: &[],
+            });
+            let shader = wgpu::ShaderModuleDescriptor {
+                label: Some("Water Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("water.wgsl").into()),
+            };
+            // Visible from below as well.
+            PipelineBuilder::new("Water Render Pipeline")
+                .color_format(config.format)
+                .depth_format(texture::Texture::DEPTH_FORMAT)
+                .vertex_layouts(&[mesh::MeshVertex::desc()])
+                .cull_mode(None)
+                .shader(shader)
+                .build(device, &pipeline_layout)
+        };
+
+        Self {
+            visible: false,
+            height,
+            mesh,
+            normal_map,
+            normal_sampler,
+            time_uniform,
+            reflection,
+            layout,
+            bind_group,
+            render_pipeline,
+            size: Self::extent(config),
+        }
+    }
+
+    fn extent(config: &wgpu::SurfaceConfiguration) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        }
+    }
+
+    /// Tangent space normals, z up, of a smoothly interpolated random height field.
+    fn create_normal_map(device: &wgpu::Device, queue: &wgpu::Queue, rng: &mut impl Rng) -> texture::Texture {
+        let cells = Self::NOISE_CELLS;
+        let lattice = (0..cells * cells)
+            .map(|_| rng.gen::<f32>())
+            .collect::<Vec<_>>();
+        let height = |x: f32, y: f32| {
+            let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+            let (x0, y0) = (x.floor(), y.floor());
+            let (tx, ty) = (smooth(x - x0), smooth(y - y0));
+            let at = |i: f32, j: f32| {
+                let i = (i as i32).rem_euclid(cells as i32) as u32;
+                let j = (j as i32).rem_euclid(cells as i32) as u32;
+                lattice[(j * cells + i) as usize]
+            };
+            let top = at(x0, y0) + (at(x0 + 1.0, y0) - at(x0, y0)) * tx;
+            let bottom = at(x0, y0 + 1.0) + (at(x0 + 1.0, y0 + 1.0) - at(x0, y0 + 1.0)) * tx;
+            top + (bottom - top) * ty
+        };
+
+        let size = Self::NOISE_SIZE;
+        let scale = cells as f32 / size as f32;
+        let pixels = (0..size * size)
+            .flat_map(|i| {
+                let (x, y) = ((i % size) as f32 * scale, (i / size) as f32 * scale);
+                let dx = height(x + scale, y) - height(x - scale, y);
+                let dy = height(x, y + scale) - height(x, y - scale);
+                let normal = cgmath::InnerSpace::normalize(cgmath::Vector3::new(-dx, -dy, 2.0 * scale));
+                let encode = |v: f32| ((v * 0.5 + 0.5) * 255.0) as u8;
+                [encode(normal.x), encode(normal.y), encode(normal.z), 255]
+            })
+            .collect::<Vec<_>>();
+        let image = image::RgbaImage::from_raw(size, size, pixels).unwrap();
+        texture::Texture::from_image(
+            device,
+            queue,
+            &image::DynamicImage::ImageRgba8(image),
+            Some("Water Normal Map"),
+            true,
+        ).unwrap()
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        time_uniform: &wgpu::Buffer,
+        normal_map: &texture::Texture,
+        normal_sampler: &wgpu::Sampler,
+        reflection: &texture::Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Water Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: time_uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&normal_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(normal_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&reflection.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(&reflection.sampler),
+                },
+            ],
+        })
+    }
+
+    /// Call after the scene target was recreated at the size of `config`.
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        self.reflection = texture::Texture::create_copy_target(device, config, "Water Reflection Texture");
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.layout,
+            &self.time_uniform,
+            &self.normal_map,
+            &self.normal_sampler,
+            &self.reflection,
+        );
+        self.size = Self::extent(config);
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, timer: &Timer) {
+        let uniform = WaterUniform {
+            time: timer.elapsed_secs,
+            height: self.height,
+            _padding: [0.0; 2],
+        };
+        queue.write_buffer(&self.time_uniform, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Copies `scene` into the reflection texture, then draws the water over `scene`.
+    pub fn render(
+        &self,
+        scene: &texture::Texture,
+        encoder: &mut wgpu::CommandEncoder,
+        depth_texture: &texture::Texture,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        if !self.visible {
+            return;
+        }
+
+        encoder.copy_texture_to_texture(
+            scene.texture.as_image_copy(),
+            self.reflection.texture.as_image_copy(),
+            self.size,
+        );
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Water Render Pass"),
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    view: &scene.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }
+            ],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        use crate::mesh::DrawMesh;
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.draw_mesh(
+            &self.mesh,
+            Some(vec![
+                camera_bind_group,
+                &self.bind_group,
+            ]),
+        );
+    }
+}
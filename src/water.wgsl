@@ -0,0 +1,83 @@
+// Vertex shader
+
+struct Camera {
+    view_pos: vec4<f32>;
+    view_proj: mat4x4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+struct Water {
+    time: f32;
+    height: f32;
+};
+[[group(1), binding(0)]]
+var<uniform> water: Water;
+[[group(1), binding(1)]]
+var t_normal: texture_2d<f32>;
+[[group(1), binding(2)]]
+var s_normal: sampler;
+// Copy of the scene drawn before the water.
+[[group(1), binding(3)]]
+var t_reflection: texture_2d<f32>;
+[[group(1), binding(4)]]
+var s_reflection: sampler;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] world_position: vec3<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+};
+
+// Normal map repeats across the plane.
+let NORMAL_TILING: f32 = 8.0;
+// How far along the reflected ray to look up the scene, in world units.
+let REFLECTION_DISTANCE: f32 = 8.0;
+// Reflectance looking straight down, exaggerated so the reflection shows from above.
+let BASE_REFLECTANCE: f32 = 0.1;
+let WATER_COLOR: vec3<f32> = vec3<f32>(0.02, 0.12, 0.18);
+
+[[stage(vertex)]]
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    let world_position = model.position + vec3<f32>(0.0, water.height, 0.0);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.world_position = world_position;
+    out.tex_coords = model.tex_coords;
+    return out;
+}
+
+// Fragment shader
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // Two layers scrolling in different directions, so the pattern never just slides.
+    let uv = in.tex_coords * NORMAL_TILING;
+    let n0 = textureSample(t_normal, s_normal, uv + vec2<f32>(0.03, 0.02) * water.time).xyz * 2.0 - 1.0;
+    let n1 = textureSample(t_normal, s_normal, uv * 1.7 - vec2<f32>(0.02, 0.04) * water.time).xyz * 2.0 - 1.0;
+    // The normal map has z up, the plane faces +y.
+    let bump = normalize(n0 + n1);
+    let normal = normalize(vec3<f32>(bump.x, bump.z, bump.y));
+
+    let view_dir = normalize(in.world_position - camera.view_pos.xyz);
+    let reflected = reflect(view_dir, normal);
+
+    // Project a point along the reflected ray back onto the screen and sample the scene there.
+    let reflected_clip = camera.view_proj * vec4<f32>(in.world_position + reflected * REFLECTION_DISTANCE, 1.0);
+    let ndc = reflected_clip.xy / max(reflected_clip.w, 0.0001);
+    let reflection_uv = clamp(ndc * vec2<f32>(0.5, -0.5) + 0.5, vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 1.0));
+    let reflection = textureSample(t_reflection, s_reflection, reflection_uv).rgb;
+
+    // Schlick's approximation, water reflects more at grazing angles.
+    let cos_theta = max(dot(-view_dir, normal), 0.0);
+    let fresnel = BASE_REFLECTANCE + (1.0 - BASE_REFLECTANCE) * pow(1.0 - cos_theta, 5.0);
+    return vec4<f32>(mix(WATER_COLOR, reflection, fresnel), 1.0);
+}
@@ -3,6 +3,8 @@
 struct Camera {
     view_pos: vec4<f32>;
     view_proj: mat4x4<f32>;
+    right: vec4<f32>;
+    up: vec4<f32>;
 };
 [[group(0), binding(0)]]
 var<uniform> camera: Camera;
@@ -11,11 +13,68 @@ struct Wire {
     smoothing: f32;
     thickness: f32;
     invert_edges: u32;
-    //_padding: u32;
+    face_colors: u32;
+    heatmap: u32;
 };
 [[group(1), binding(0)]]
 var<uniform> wire: Wire;
 
+// One hue per triangle, wrapped when the mesh has more triangles than texels.
+[[group(1), binding(1)]]
+var t_face_colors: texture_2d<f32>;
+
+// Transfer function for vertex scalars in [0, 1], one texel high.
+[[group(1), binding(2)]]
+var t_heatmap: texture_2d<f32>;
+[[group(1), binding(3)]]
+var s_heatmap: sampler;
+
+struct Spotlight {
+    position: vec3<f32>;
+    direction: vec3<f32>;
+    angle_cos: f32;
+    color: vec3<f32>;
+    intensity: f32;
+};
+[[group(2), binding(0)]]
+var<uniform> spotlight: Spotlight;
+
+// Falloff from the center of the spotlight's cone towards its edge.
+let SPOTLIGHT_SHARPNESS: f32 = 8.0;
+
+// Must match `CascadeUniform` in shadow.rs.
+struct Cascades {
+    view_proj: array<mat4x4<f32>, 4>;
+    // View space depth where each cascade ends.
+    splits: vec4<f32>;
+    count: u32;
+    enabled: u32;
+};
+[[group(2), binding(1)]]
+var<uniform> cascades: Cascades;
+[[group(2), binding(2)]]
+var t_shadow: texture_depth_2d_array;
+[[group(2), binding(3)]]
+var s_shadow: sampler_comparison;
+
+// Keeps surfaces from shadowing themselves, in light clip space depth.
+let SHADOW_BIAS: f32 = 0.002;
+
+struct MeshMorph {
+    blend: f32;
+    enabled: u32;
+};
+[[group(3), binding(0)]]
+var<uniform> morph: MeshMorph;
+
+// Offset of each vertex from where it sits on the coarser mesh, as a position texel
+// followed by a normal texel.
+[[group(3), binding(1)]]
+var morph_offsets: texture_2d<f32>;
+
+// Must match `MorphTarget::ROW_VERTICES`.
+let MORPH_ROW_VERTICES: u32 = 1024u;
+
 // [[block]]
 // struct Light {
 //     position: vec3<f32>;
@@ -29,8 +88,9 @@ struct VertexInput {
     [[location(0)]] position: vec3<f32>;
     [[location(1)]] tex_coords: vec2<f32>;
     [[location(2)]] normal: vec3<f32>;
-    [[location(3)]] tangent: vec3<f32>;
-    [[location(4)]] bitangent: vec3<f32>;
+    // w holds the bitangent's handedness.
+    [[location(3)]] tangent: vec4<f32>;
+    [[location(4)]] scalar: f32;
 };
 
 struct VertexOutput {
@@ -38,6 +98,9 @@ struct VertexOutput {
     [[location(0)]] tex_coords: vec2<f32>;
     [[location(1)]] world_position: vec3<f32>;
     [[location(2)]] bary_coords: vec2<f32>;
+    [[location(3)]] color: vec4<f32>;
+    [[location(4)]] world_normal: vec3<f32>;
+    [[location(5)]] scalar: f32;
     // [[location(1)]] tangent_position: vec3<f32>;
     // [[location(2)]] tangent_light_position: vec3<f32>;
     // [[location(3)]] tangent_view_position: vec3<f32>;
@@ -52,6 +115,8 @@ struct InstanceInput {
     [[location( 9)]] normal_matrix_0: vec3<f32>;
     [[location(10)]] normal_matrix_1: vec3<f32>;
     [[location(11)]] normal_matrix_2: vec3<f32>;
+
+    [[location(12)]] color: vec4<f32>;
 };
 
 [[stage(vertex)]]
@@ -72,20 +137,43 @@ fn vs_main(
     );
 
     // let world_normal = normalize(normal_matrix * model.normal);
-    // let world_tangent = normalize(normal_matrix * model.tangent);
-    // let world_bitangent = normalize(normal_matrix * model.bitangent);
+    // let world_tangent = normalize(normal_matrix * model.tangent.xyz);
+    // let world_bitangent = cross(world_normal, world_tangent) * model.tangent.w;
     // let tangent_matrix = transpose(mat3x3<f32>(
     //     world_tangent,
     //     world_bitangent,
     //     world_normal,
     // ));
 
-    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+    var position = model.position;
+    var normal = model.normal;
+    if (morph.enabled != 0u) {
+        let texel = vec2<i32>(
+            i32(model.vertex_index % MORPH_ROW_VERTICES) * 2,
+            i32(model.vertex_index / MORPH_ROW_VERTICES),
+        );
+        let offset_position = textureLoad(morph_offsets, texel, 0).xyz;
+        let offset_normal = textureLoad(morph_offsets, texel + vec2<i32>(1, 0), 0).xyz;
+        let base_position = model.position - offset_position;
+        let base_normal = model.normal - offset_normal;
+        position = base_position + offset_position * morph.blend;
+        normal = normalize(base_normal + offset_normal * morph.blend);
+    }
+
+    let world_position = model_matrix * vec4<f32>(position, 1.0);
 
     var out: VertexOutput;
     out.clip_position = camera.view_proj * world_position;
     out.tex_coords = model.tex_coords;
     out.world_position = world_position.xyz;
+    out.color = instance.color;
+    out.world_normal = normal_matrix * normal;
+    out.scalar = model.scalar;
+    if (wire.face_colors != 0u) {
+        // Only meaningful for non-indexed meshes, where every three vertices make a triangle.
+        let triangle = i32(model.vertex_index / 3u) % textureDimensions(t_face_colors).x;
+        out.color = vec4<f32>(textureLoad(t_face_colors, vec2<i32>(triangle, 0), 0).rgb, instance.color.a);
+    }
 
     // out.tangent_position = tangent_matrix * world_position.xyz;
     // out.tangent_view_position = tangent_matrix * camera.view_pos.xyz;
@@ -104,6 +192,41 @@ fn vs_main(
 
 // Fragment shader
 
+// 1 where the spotlight reaches `world_position`, 0 where it's in shadow, filtered over 3x3 texels.
+fn shadow_factor(world_position: vec3<f32>) -> f32 {
+    if (cascades.enabled == 0u) {
+        return 1.0;
+    }
+
+    // The cascade is picked by view space depth, the view looks along up x right.
+    let forward = cross(camera.up.xyz, camera.right.xyz);
+    let depth = dot(world_position - camera.view_pos.xyz, forward);
+    var index = cascades.count - 1u;
+    for (var i = 0u; i < cascades.count; i = i + 1u) {
+        if (depth < cascades.splits[i]) {
+            index = i;
+            break;
+        }
+    }
+
+    let light_clip = cascades.view_proj[index] * vec4<f32>(world_position, 1.0);
+    let ndc = light_clip.xyz / light_clip.w;
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0 || ndc.z > 1.0) {
+        return 1.0;
+    }
+
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_shadow));
+    var lit = 0.0;
+    for (var y = -1; y <= 1; y = y + 1) {
+        for (var x = -1; x <= 1; x = x + 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            lit = lit + textureSampleCompareLevel(t_shadow, s_shadow, uv + offset, i32(index), ndc.z - SHADOW_BIAS);
+        }
+    }
+    return lit / 9.0;
+}
+
 // [[group(0), binding(0)]]
 // var t_diffuse: texture_2d<f32>;
 // [[group(0), binding(1)]]
@@ -141,6 +264,7 @@ fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
     //return vec4<f32>(normal_color, 1.0);
 
     let camera_distance = distance(in.world_position, camera.view_pos.xyz);
+    let heat = textureSample(t_heatmap, s_heatmap, vec2<f32>(clamp(in.scalar, 0.0, 1.0), 0.5)).rgb;
 
     var barys = vec3<f32>(in.bary_coords, 0.0);
     barys.z = 1.0 - barys.x - barys.y;
@@ -158,5 +282,21 @@ fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
         discard;
     }
 
-    return vec4<f32>(minBary, minBary, minBary, 1.0);
+    // Lambert lighting inside the spotlight's cone, added on top of the unlit wire color.
+    let frag_to_light = spotlight.position - in.world_position;
+    let light_dir = normalize(frag_to_light);
+    let spot_cos = dot(light_dir, -spotlight.direction);
+    var cone = 0.0;
+    if (spot_cos >= spotlight.angle_cos) {
+        cone = pow(max(spot_cos, 0.0), SPOTLIGHT_SHARPNESS);
+    }
+    let diffuse = max(dot(normalize(in.world_normal), light_dir), 0.0);
+    let light = spotlight.color * spotlight.intensity * cone * diffuse * shadow_factor(in.world_position);
+
+    var base_color = in.color;
+    if (wire.heatmap != 0u) {
+        base_color = vec4<f32>(heat, in.color.a);
+    }
+    let color = vec4<f32>(minBary, minBary, minBary, 1.0) * base_color;
+    return vec4<f32>(color.rgb + light * minBary, color.a);
 }